@@ -13,8 +13,9 @@ import logging
 import time
 import uuid
 import threading
+from contextlib import contextmanager
 from datetime import datetime
-from typing import Optional, Dict, Any, List, Union
+from typing import Optional, Dict, Any, List, Union, Callable
 import websocket
 import socket
 from pathlib import Path
@@ -41,7 +42,10 @@ class AgentHUDv4:
         agent_name: str = "Python Agent",
         metadata: Optional[Dict[str, Any]] = None,
         auto_connect: bool = True,
-        discovery_timeout: int = 10
+        discovery_timeout: int = 10,
+        parent_id: Optional[str] = None,
+        tags: Optional[List[str]] = None,
+        run_id: Optional[str] = None
     ):
         """
         Initialize the Agent HUD v4 client.
@@ -51,9 +55,15 @@ class AgentHUDv4:
             metadata: Additional metadata about the agent
             auto_connect: Whether to connect immediately
             discovery_timeout: Timeout for discovering the HUD application
+            parent_id: Agent id of the agent that spawned this one
+            tags: Labels the HUD can group and filter agents by
+            run_id: Id shared by every agent taking part in the same run
         """
         self.agent_name = agent_name
         self.metadata = metadata or {}
+        self.parent_id = parent_id
+        self.tags = tags or []
+        self.run_id = run_id
         self.connected = False
         self.agent_id = None
         self.ws = None
@@ -63,6 +73,22 @@ class AgentHUDv4:
         self.pending_requests = {}
         self.request_responses = {}
         
+        # Operator control state; the resume event is set while the agent is running
+        self.resume_event = threading.Event()
+        self.resume_event.set()
+        self.stop_requested = False
+        self.verbosity = None
+        self.control_handlers: List[Callable[[Dict[str, Any]], None]] = []
+        
+        # Unsolicited operator instructions, oldest first
+        self.instructions: List[Dict[str, Any]] = []
+        self.instructions_lock = threading.Lock()
+        self.seen_instruction_ids = set()
+        self.instruction_handlers: List[Callable[[Dict[str, Any]], None]] = []
+        
+        # Open tool-call spans per thread, innermost last, so nested calls find their parent
+        self.span_stack = threading.local()
+        
         if auto_connect:
             if not self.discover_and_connect(timeout=discovery_timeout):
                 raise ConnectionError("Could not find or connect to Agent HUD v4 application")
@@ -143,7 +169,10 @@ class AgentHUDv4:
         registration_message = {
             "type": "register-agent",
             "name": self.agent_name,
-            "metadata": self.metadata
+            "metadata": self.metadata,
+            "parentId": self.parent_id,
+            "tags": self.tags,
+            "runId": self.run_id
         }
         
         self._send_message(registration_message)
@@ -162,6 +191,33 @@ class AgentHUDv4:
             elif message_type == "human-input-response":
                 self._handle_human_response(data)
                 
+            elif message_type == "agent-control":
+                self._handle_control(data)
+                
+            elif message_type == "operator-message":
+                self._handle_instruction(data)
+                
+            elif message_type == "operator-messages":
+                for instruction in data.get("messages", []):
+                    self._handle_instruction(instruction)
+                
+            elif message_type in ("start-session-ack", "join-session-ack"):
+                self.run_id = data.get("session", {}).get("id")
+                logger.info(f"Now in session {self.run_id}")
+                
+            elif message_type == "end-session-ack":
+                logger.info(f"Session {data.get('session', {}).get('id')} ended")
+                
+            elif message_type == "budget-warning":
+                logger.warning(f"Approaching budget for {data.get('scope', {}).get('scope')}: {data.get('reason')}")
+                
+            elif message_type == "session-rejected":
+                logger.error(f"Session {data.get('action')} rejected by HUD: {data.get('reason')}")
+                
+            elif message_type == "request-rejected":
+                logger.error(f"Request {data.get('requestId')} rejected by HUD: {data.get('reason')}")
+                self._handle_human_response({**data, "error": data.get("reason"), "response": None})
+                
             else:
                 logger.debug(f"Received message: {message_type}")
                 
@@ -205,6 +261,178 @@ class AgentHUDv4:
             if 'event' in self.pending_requests[request_id]:
                 self.pending_requests[request_id]['event'].set()
     
+    def _handle_control(self, data: Dict[str, Any]):
+        """Apply an operator control command and acknowledge it."""
+        command = data.get("command")
+        logger.info(f"Received control command from {data.get('issuedBy')}: {command}")
+        
+        try:
+            # Handlers may refuse a command by raising
+            for handler in self.control_handlers:
+                handler(data)
+            
+            if command == "pause":
+                self.resume_event.clear()
+            elif command == "resume":
+                self.resume_event.set()
+            elif command == "stop":
+                self.stop_requested = True
+                self.resume_event.set()
+            elif command == "set-verbosity":
+                self.verbosity = data.get("level")
+                level = logging.getLevelName(str(self.verbosity).upper())
+                if isinstance(level, int):
+                    logger.setLevel(level)
+            else:
+                raise ValueError(f"Unknown control command: {command}")
+            
+            ack = {"success": True}
+        except Exception as e:
+            logger.warning(f"Could not apply control command {command}: {e}")
+            ack = {"success": False, "error": str(e)}
+        
+        self._send_message({
+            "type": "agent-control-ack",
+            "controlId": data.get("controlId"),
+            **ack
+        })
+    
+    def _handle_instruction(self, data: Dict[str, Any]):
+        """Queue an operator instruction, notify subscribers and acknowledge delivery."""
+        message_id = data.get("messageId")
+        with self.instructions_lock:
+            is_new = message_id not in self.seen_instruction_ids
+            if is_new:
+                self.seen_instruction_ids.add(message_id)
+                self.instructions.append(data)
+        
+        if is_new:
+            logger.info(f"Instruction from {data.get('from')}: {data.get('message')}")
+            for handler in self.instruction_handlers:
+                try:
+                    handler(data)
+                except Exception as e:
+                    logger.error(f"Instruction handler failed: {e}")
+        
+        self._send_message({"type": "operator-message-ack", "messageId": message_id})
+    
+    def on_instruction(self, handler: Callable[[Dict[str, Any]], None]):
+        """
+        Subscribe to operator instructions. The handler receives a dict with "messageId",
+        "message", "data", "from" and "timestamp" as soon as the instruction arrives.
+        """
+        self.instruction_handlers.append(handler)
+    
+    def get_instructions(self, refresh: bool = False, wait: float = 1.0) -> List[Dict[str, Any]]:
+        """
+        Return and clear the operator instructions received so far.
+        
+        Args:
+            refresh: Ask the HUD to resend instructions that were not acknowledged yet
+                (e.g. sent while the connection was down) before returning
+            wait: Seconds to wait for the HUD's reply when refreshing
+        """
+        if refresh and self._send_message({"type": "poll-operator-messages"}):
+            time.sleep(wait)
+        
+        with self.instructions_lock:
+            instructions, self.instructions = self.instructions, []
+        return instructions
+    
+    def on_control(self, handler: Callable[[Dict[str, Any]], None]):
+        """
+        Register a callback for operator control commands (pause, resume, stop, set-verbosity).
+        
+        The handler receives the raw command message; raising an exception refuses the command.
+        """
+        self.control_handlers.append(handler)
+    
+    @property
+    def is_paused(self) -> bool:
+        return not self.resume_event.is_set()
+    
+    def wait_if_paused(self, timeout: Optional[float] = None) -> bool:
+        """
+        Block while an operator has paused this agent.
+        
+        Returns:
+            True if the agent may continue, False if the wait timed out
+        """
+        return self.resume_event.wait(timeout=timeout)
+    
+    def should_stop(self) -> bool:
+        """Whether an operator has asked this agent to stop."""
+        return self.stop_requested
+    
+    def start_session(
+        self,
+        name: Optional[str] = None,
+        session_id: Optional[str] = None,
+        metadata: Optional[Dict[str, Any]] = None
+    ) -> Optional[str]:
+        """
+        Start a session that this agent's messages, requests and content are attributed to.
+        Other agents can join it with the returned id; starting an existing id joins it.
+        
+        Returns:
+            The session id, or None if the message could not be sent
+        """
+        session_id = session_id or str(uuid.uuid4())
+        sent = self._send_message({
+            "type": "start-session",
+            "sessionId": session_id,
+            "name": name,
+            "metadata": metadata
+        })
+        return session_id if sent else None
+    
+    def join_session(self, session_id: str) -> bool:
+        """Join a session another agent started."""
+        return self._send_message({"type": "join-session", "sessionId": session_id})
+    
+    def end_session(self, status: str = "completed", summary: Optional[str] = None) -> bool:
+        """
+        End this agent's current session.
+        
+        Args:
+            status: "completed", "failed" or "cancelled"
+            summary: What the session accomplished
+        """
+        return self._send_message({
+            "type": "end-session",
+            "sessionId": self.run_id,
+            "status": status,
+            "summary": summary
+        })
+    
+    def report_usage(
+        self,
+        model: str,
+        input_tokens: int,
+        output_tokens: int,
+        cost: float = 0.0,
+        latency_ms: Optional[int] = None
+    ) -> bool:
+        """
+        Report one LLM call so the HUD can track spend. If this pushes the agent over its
+        budget the HUD pauses it; use wait_if_paused() between calls to honour that.
+        
+        Args:
+            model: Model name
+            input_tokens: Prompt tokens
+            output_tokens: Completion tokens
+            cost: Cost of the call in dollars
+            latency_ms: How long the call took
+        """
+        return self._send_message({
+            "type": "usage-report",
+            "model": model,
+            "inputTokens": input_tokens,
+            "outputTokens": output_tokens,
+            "cost": cost,
+            "latencyMs": latency_ms or 0
+        })
+    
     def emit_markdown(
         self,
         content: str,
@@ -346,6 +574,76 @@ class AgentHUDv4:
         self._send_message(msg)
         return message_id
     
+    def start_tool_call(
+        self,
+        tool_name: str,
+        arguments: Optional[Dict[str, Any]] = None,
+        parent_span_id: Optional[str] = None
+    ) -> str:
+        """
+        Report that a tool call started. Pair with end_tool_call(), or use tool_call().
+        
+        Args:
+            tool_name: Name of the tool
+            arguments: Arguments the tool was called with
+            parent_span_id: Span of the call this one runs inside; defaults to the innermost
+                open tool_call() on this thread
+            
+        Returns:
+            Span ID
+        """
+        span_id = str(uuid.uuid4())
+        stack = getattr(self.span_stack, "spans", [])
+        self._send_message({
+            "type": "tool-call-start",
+            "spanId": span_id,
+            "parentSpanId": parent_span_id or (stack[-1] if stack else None),
+            "toolName": tool_name,
+            "arguments": arguments
+        })
+        return span_id
+    
+    def end_tool_call(
+        self,
+        span_id: str,
+        result: Any = None,
+        error: Optional[str] = None,
+        duration_ms: Optional[int] = None
+    ) -> bool:
+        """Report that a tool call finished, with its result or the error it failed with."""
+        return self._send_message({
+            "type": "tool-call-end",
+            "spanId": span_id,
+            "result": result,
+            "error": error,
+            "durationMs": duration_ms
+        })
+    
+    @contextmanager
+    def tool_call(self, tool_name: str, arguments: Optional[Dict[str, Any]] = None):
+        """
+        Trace a tool call around a block. Exceptions are reported as the call's error and
+        re-raised; calls started inside the block become its children.
+        
+        Usage:
+            with hud.tool_call("search", {"query": q}) as span:
+                span["result"] = search(q)
+        """
+        span = {"span_id": self.start_tool_call(tool_name, arguments), "result": None}
+        if not hasattr(self.span_stack, "spans"):
+            self.span_stack.spans = []
+        self.span_stack.spans.append(span["span_id"])
+        started = time.monotonic()
+        try:
+            yield span
+        except Exception as e:
+            self.end_tool_call(span["span_id"], error=str(e), duration_ms=int((time.monotonic() - started) * 1000))
+            raise
+        else:
+            self.end_tool_call(span["span_id"], result=span["result"], duration_ms=int((time.monotonic() - started) * 1000))
+        finally:
+            self.span_stack.spans.pop()
+    
     def emit_notification(
         self,
         title: str,
@@ -422,19 +720,37 @@ class AgentHUDv4:
         self,
         message: str,
         input_type: str = "text",
-        options: Optional[List[str]] = None,
+        options: Optional[List[Union[str, Dict[str, Any]]]] = None,
         context: Optional[Dict[str, Any]] = None,
-        timeout: int = 300
+        timeout: int = 300,
+        schema: Optional[Dict[str, Any]] = None,
+        min_selections: Optional[int] = None,
+        max_selections: Optional[int] = None,
+        on_timeout: Optional[Union[str, Dict[str, Any]]] = None,
+        priority: Optional[str] = None,
+        required_approvers: Optional[int] = None
     ) -> Dict[str, Any]:
         """
         Request input from human operator.
         
         Args:
             message: The question/request message for the human
-            input_type: Type of input requested ("text", "approval", "choice", etc.)
-            options: List of available options for choice-type requests
+            input_type: Type of input requested ("text", "approval", "choice", "form", etc.)
+            options: Available options for choice-type requests, either plain labels or
+                dicts with "id", "label", "description", "recommended" and "preview"
+                (e.g. {"format": "diff", "content": "..."}). Responses carry the option id.
             context: Additional context data for the human
             timeout: Timeout in seconds (default: 300 = 5 minutes)
+            schema: JSON Schema describing the fields of a "form" request
+            min_selections: Minimum number of options for "multi-choice" requests
+            max_selections: Maximum number of options for "multi-choice" requests
+            on_timeout: What the HUD should answer if nobody responds in time: "deny",
+                "approve" (low priority only), "escalate", or
+                {"action": "default", "value": ...} (defaults to the recommended option)
+            priority: Explicit priority ("low", "medium", "high" or "critical"); when omitted
+                the HUD classifies the request itself
+            required_approvers: Number of distinct operators who must approve an "approval"
+                or "confirmation" request; a single denial rejects it
             
         Returns:
             Dict containing the response from human or timeout info
@@ -454,6 +770,24 @@ class AgentHUDv4:
             "context": context or {},
             "timeout": timeout
         }
+        if schema is not None:
+            request_message["schema"] = schema
+        if min_selections is not None:
+            request_message["minSelections"] = min_selections
+        if max_selections is not None:
+            request_message["maxSelections"] = max_selections
+        if on_timeout is not None:
+            request_message["onTimeout"] = on_timeout
+        if priority is not None:
+            request_message["priority"] = priority
+        if required_approvers is not None:
+            request_message["requiredApprovers"] = required_approvers
+        
+        # Escalated requests get a second timeout window on the HUD side
+        escalates = on_timeout == "escalate" or (
+            isinstance(on_timeout, dict) and on_timeout.get("action") == "escalate"
+        )
+        wait_timeout = timeout * 2 if escalates else timeout
         
         # Create event for synchronization
         response_event = threading.Event()
@@ -469,7 +803,7 @@ class AgentHUDv4:
             logger.info(f"Sent human input request: {message}")
             
             # Wait for response
-            if response_event.wait(timeout=timeout + 5):  # Add 5s buffer
+            if response_event.wait(timeout=wait_timeout + 5):  # Add 5s buffer
                 response = self.request_responses.get(request_id, {})
                 logger.info(f"Received human response: {response.get('response', 'No response')}")
                 return response
@@ -491,7 +825,8 @@ class AgentHUDv4:
         self,
         action: str,
         context: Optional[Dict[str, Any]] = None,
-        timeout: int = 300
+        timeout: int = 300,
+        required_approvers: Optional[int] = None
     ) -> bool:
         """
         Request approval from human for a specific action.
@@ -500,6 +835,7 @@ class AgentHUDv4:
             action: Description of the action requiring approval
             context: Additional context about the action
             timeout: Timeout in seconds
+            required_approvers: Number of distinct operators who must approve
             
         Returns:
             True if approved, False if rejected or timed out
@@ -509,7 +845,8 @@ class AgentHUDv4:
             input_type="approval",
             options=["Approve", "Reject"],
             context=context,
-            timeout=timeout
+            timeout=timeout,
+            required_approvers=required_approvers
         )
         
         if response.get("timeout") or response.get("error"):
@@ -520,7 +857,7 @@ class AgentHUDv4:
     def request_choice(
         self,
         question: str,
-        choices: List[str],
+        choices: List[Union[str, Dict[str, Any]]],
         context: Optional[Dict[str, Any]] = None,
         timeout: int = 300
     ) -> Optional[str]:
@@ -534,7 +871,7 @@ class AgentHUDv4:
             timeout: Timeout in seconds
             
         Returns:
-            Selected choice or None if timed out/error
+            Id of the selected choice or None if timed out/error
         """
         response = self.request_human_input(
             message=question,
@@ -549,6 +886,108 @@ class AgentHUDv4:
         
         return response.get("response")
     
+    def request_multi_choice(
+        self,
+        question: str,
+        choices: List[str],
+        min_selections: int = 1,
+        max_selections: Optional[int] = None,
+        context: Optional[Dict[str, Any]] = None,
+        timeout: int = 300
+    ) -> Optional[List[str]]:
+        """
+        Request human to pick several options from a list.
+        
+        Args:
+            question: The question to ask
+            choices: List of available choices
+            min_selections: Minimum number of choices to pick
+            max_selections: Maximum number of choices to pick (default: all)
+            context: Additional context
+            timeout: Timeout in seconds
+            
+        Returns:
+            Selected choices or None if timed out/error
+        """
+        response = self.request_human_input(
+            message=question,
+            input_type="multi-choice",
+            options=choices,
+            context=context,
+            timeout=timeout,
+            min_selections=min_selections,
+            max_selections=max_selections
+        )
+        
+        if response.get("timeout") or response.get("error"):
+            return None
+        
+        return response.get("response")
+    
+    def request_ranking(
+        self,
+        question: str,
+        items: List[str],
+        context: Optional[Dict[str, Any]] = None,
+        timeout: int = 300
+    ) -> Optional[List[str]]:
+        """
+        Request human to order a list of options by preference.
+        
+        Args:
+            question: The question to ask
+            items: Options to rank
+            context: Additional context
+            timeout: Timeout in seconds
+            
+        Returns:
+            All items, most preferred first, or None if timed out/error
+        """
+        response = self.request_human_input(
+            message=question,
+            input_type="ranking",
+            options=items,
+            context=context,
+            timeout=timeout
+        )
+        
+        if response.get("timeout") or response.get("error"):
+            return None
+        
+        return response.get("response")
+    
+    def request_form(
+        self,
+        message: str,
+        schema: Dict[str, Any],
+        context: Optional[Dict[str, Any]] = None,
+        timeout: int = 300
+    ) -> Optional[Dict[str, Any]]:
+        """
+        Ask human to fill in several fields at once.
+        
+        Args:
+            message: What the form is for
+            schema: JSON Schema of the form (an object with "properties")
+            context: Additional context
+            timeout: Timeout in seconds
+            
+        Returns:
+            The submitted fields, validated against the schema, or None if timed out/error
+        """
+        response = self.request_human_input(
+            message=message,
+            input_type="form",
+            context=context,
+            timeout=timeout,
+            schema=schema
+        )
+        
+        if response.get("timeout") or response.get("error"):
+            return None
+        
+        return response.get("response")
+    
     def request_context(
         self,
         query: str,
@@ -0,0 +1,266 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::models::*;
+use crate::policy::PolicyError;
+
+/// The `access` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Role of operators not listed in `operators`. Their names are not authenticated, so
+    /// anything above viewer lets any local client answer requests.
+    pub default_role: Role,
+    /// Role of whoever uses the desktop app itself
+    pub desktop_role: Role,
+    pub operators: Vec<OperatorAccess>,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        AccessConfig {
+            default_role: Role::Viewer,
+            desktop_role: Role::Admin,
+            operators: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperatorAccess {
+    pub name: String,
+    pub role: Role,
+    /// Token a GUI has to present to register as this operator
+    pub token: String,
+    /// Regexes matched against agent names. Together with `tags` this limits the operator to
+    /// some agents; when both are empty the operator may act on every agent.
+    #[serde(default)]
+    pub agents: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// What an operator may do. Each role can do everything the previous one can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Sees everything but can't answer requests
+    Viewer,
+    /// Answers and claims requests
+    Responder,
+    /// Also runs control commands such as replays and config reloads
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    Respond,
+    Control,
+}
+
+impl Permission {
+    fn required_role(self) -> Role {
+        match self {
+            Permission::Respond => Role::Responder,
+            Permission::Control => Role::Admin,
+        }
+    }
+}
+
+struct CompiledAccess {
+    access: OperatorAccess,
+    agents: Vec<Regex>,
+}
+
+/// Operator name for actions taken in the desktop app. Only the app's own webview can register
+/// under it, using the token it gets from the `get_desktop_token` command.
+pub const DESKTOP_OPERATOR: &str = "desktop";
+
+/// Decides which operators may answer which requests and run control commands
+pub struct AccessControl {
+    default_role: Role,
+    desktop_role: Role,
+    /// Generated at launch and kept across config reloads
+    desktop_token: String,
+    operators: Vec<CompiledAccess>,
+}
+
+impl AccessControl {
+    pub fn new(config: AccessConfig, desktop_token: String) -> Result<Self, PolicyError> {
+        let mut operators = Vec::with_capacity(config.operators.len());
+        for access in config.operators {
+            if access.token.trim().is_empty() {
+                return Err(PolicyError::MissingToken(access.name));
+            }
+            let mut agents = Vec::with_capacity(access.agents.len());
+            for pattern in &access.agents {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| PolicyError::InvalidRegex(format!("access for {}", access.name), e.to_string()))?;
+                agents.push(regex);
+            }
+            operators.push(CompiledAccess { access, agents });
+        }
+
+        Ok(AccessControl {
+            default_role: config.default_role,
+            desktop_role: config.desktop_role,
+            desktop_token,
+            operators,
+        })
+    }
+
+    pub fn desktop_token(&self) -> &str {
+        &self.desktop_token
+    }
+
+    pub fn role_of(&self, operator: &str) -> Role {
+        match self.find(operator) {
+            Some(compiled) => compiled.access.role,
+            None if operator == DESKTOP_OPERATOR => self.desktop_role,
+            None => self.default_role,
+        }
+    }
+
+    /// Checks the name a GUI registers with. Configured operators must present their token and
+    /// the desktop operator the per-launch desktop token; other names get the default role.
+    pub fn authenticate(&self, operator: &str, token: Option<&str>) -> Result<Role, AccessError> {
+        let (expected, role) = match self.find(operator) {
+            Some(compiled) => (compiled.access.token.as_str(), compiled.access.role),
+            None if operator == DESKTOP_OPERATOR => (self.desktop_token.as_str(), self.desktop_role),
+            None => return Ok(self.default_role),
+        };
+
+        if token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+            Ok(role)
+        } else {
+            Err(AccessError::BadToken(operator.to_string()))
+        }
+    }
+
+    /// Checks that `operator` holds `permission`, and when an agent is given, that the agent
+    /// is within the operator's scope
+    pub fn authorize(&self, operator: &str, permission: Permission, agent: Option<&Agent>) -> Result<(), AccessError> {
+        let role = self.role_of(operator);
+        if role < permission.required_role() {
+            return Err(AccessError::Forbidden {
+                operator: operator.to_string(),
+                role,
+                permission,
+            });
+        }
+
+        if let (Some(agent), Some(compiled)) = (agent, self.find(operator)) {
+            let unscoped = compiled.agents.is_empty() && compiled.access.tags.is_empty();
+            let in_scope = unscoped
+                || compiled.agents.iter().any(|regex| regex.is_match(&agent.name))
+                || agent.tags.iter().any(|tag| compiled.access.tags.contains(tag));
+            if !in_scope {
+                return Err(AccessError::OutOfScope {
+                    operator: operator.to_string(),
+                    agent: agent.name.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, operator: &str) -> Option<&CompiledAccess> {
+        self.operators.iter().find(|o| o.access.name == operator)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Responder => write!(f, "responder"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Respond => write!(f, "answer requests"),
+            Permission::Control => write!(f, "run control commands"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessError {
+    #[error("{operator} is a {role} and cannot {permission}")]
+    Forbidden { operator: String, role: Role, permission: Permission },
+
+    #[error("{operator} is not allowed to act on agent {agent}")]
+    OutOfScope { operator: String, agent: String },
+
+    #[error("Wrong or missing token for operator {0}")]
+    BadToken(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access_control() -> AccessControl {
+        AccessControl::new(AccessConfig {
+            operators: vec![OperatorAccess {
+                name: "alice".to_string(),
+                role: Role::Responder,
+                token: "s3cret".to_string(),
+                agents: vec!["^build-".to_string()],
+                tags: Vec::new(),
+            }],
+            ..AccessConfig::default()
+        }, "desktop-token".to_string()).unwrap()
+    }
+
+    #[test]
+    fn configured_operators_need_their_token() {
+        let access = access_control();
+        assert_eq!(access.authenticate("alice", Some("s3cret")).unwrap(), Role::Responder);
+        assert!(matches!(access.authenticate("alice", Some("guess")), Err(AccessError::BadToken(_))));
+        assert!(matches!(access.authenticate("alice", None), Err(AccessError::BadToken(_))));
+    }
+
+    #[test]
+    fn unknown_operators_only_view() {
+        let access = access_control();
+        assert_eq!(access.authenticate("mallory", None).unwrap(), Role::Viewer);
+        assert!(matches!(
+            access.authorize("mallory", Permission::Respond, None),
+            Err(AccessError::Forbidden { role: Role::Viewer, .. })
+        ));
+    }
+
+    #[test]
+    fn only_the_desktop_app_is_the_desktop_operator() {
+        let access = access_control();
+        assert!(access.authenticate(DESKTOP_OPERATOR, None).is_err());
+        assert!(access.authenticate(DESKTOP_OPERATOR, Some("guess")).is_err());
+        assert_eq!(access.authenticate(DESKTOP_OPERATOR, Some("desktop-token")).unwrap(), Role::Admin);
+        assert!(access.authorize(DESKTOP_OPERATOR, Permission::Control, None).is_ok());
+        assert!(access.authorize(DESKTOP_OPERATOR, Permission::Respond, None).is_ok());
+    }
+
+    #[test]
+    fn operators_without_tokens_are_rejected() {
+        let config = AccessConfig {
+            operators: vec![OperatorAccess {
+                name: "bob".to_string(),
+                role: Role::Admin,
+                token: " ".to_string(),
+                agents: Vec::new(),
+                tags: Vec::new(),
+            }],
+            ..AccessConfig::default()
+        };
+        assert!(matches!(AccessControl::new(config, "desktop-token".to_string()), Err(PolicyError::MissingToken(name)) if name == "bob"));
+    }
+}
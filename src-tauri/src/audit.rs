@@ -0,0 +1,263 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use tracing::error;
+use crate::database::Database;
+
+/// `prev_hash` of the first entry in the chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One row of the audit log. Each entry's hash covers its own fields and the previous
+/// entry's hash, so changing or removing a row breaks the chain from that point on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: i64,
+    pub timestamp: DateTime<Utc>,
+    /// What happened, e.g. `request-created` or `policy-decision`
+    pub event: String,
+    /// Who did it: an agent name, operator name, `policy:<rule id>` or `system:*`
+    pub actor: String,
+    /// The agent or request the event is about
+    pub subject: Option<String>,
+    pub data: serde_json::Value,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        seq: i64,
+        prev_hash: String,
+        event: &str,
+        actor: &str,
+        subject: Option<&str>,
+        data: serde_json::Value,
+    ) -> Self {
+        let mut entry = AuditEntry {
+            seq,
+            timestamp: Utc::now(),
+            event: event.to_string(),
+            actor: actor.to_string(),
+            subject: subject.map(|s| s.to_string()),
+            data,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        entry
+    }
+
+    pub fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            self.prev_hash.as_str(),
+            &self.seq.to_string(),
+            &self.timestamp.to_rfc3339(),
+            &self.event,
+            &self.actor,
+            self.subject.as_deref().unwrap_or(""),
+            &self.data.to_string(),
+        ] {
+            hasher.update(field.as_bytes());
+            // Separator so adjacent fields can't be shifted into each other
+            hasher.update([0u8]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Sequence number and hash of the last entry, stored apart from the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditHead {
+    pub seq: i64,
+    pub hash: String,
+}
+
+/// Result of checking the audit chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    pub entries_checked: usize,
+    pub last_hash: String,
+    /// Sequence number of the first entry that doesn't fit the chain
+    pub first_invalid_seq: Option<i64>,
+    pub problem: Option<String>,
+}
+
+/// Walks the chain in sequence order, detecting edited rows (hash mismatch), inserted or
+/// reordered rows (broken `prev_hash` link) and deleted rows (gaps in `seq`). Rows removed
+/// from the end, up to the whole chain, are detected by comparing the last entry with `head`.
+pub fn verify_chain(entries: &[AuditEntry], head: Option<&AuditHead>) -> AuditVerification {
+    let mut prev_hash = GENESIS_HASH.to_string();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let expected_seq = index as i64 + 1;
+        let problem = if entry.seq != expected_seq {
+            Some(format!("expected entry {} but found {}; rows are missing", expected_seq, entry.seq))
+        } else if entry.prev_hash != prev_hash {
+            Some("previous hash does not match the preceding entry".to_string())
+        } else if entry.hash != entry.compute_hash() {
+            Some("entry contents do not match its hash".to_string())
+        } else {
+            None
+        };
+
+        if problem.is_some() {
+            return AuditVerification {
+                valid: false,
+                entries_checked: index,
+                last_hash: prev_hash,
+                first_invalid_seq: Some(entry.seq),
+                problem,
+            };
+        }
+
+        prev_hash = entry.hash.clone();
+    }
+
+    let last = entries.last().map(|entry| AuditHead { seq: entry.seq, hash: entry.hash.clone() });
+    if last.as_ref() != head {
+        let (first_invalid_seq, problem) = match (&last, head) {
+            (_, None) => (1, "entries exist but no chain head was recorded".to_string()),
+            (None, Some(head)) => (1, format!("the chain is empty but should end at entry {}", head.seq)),
+            (Some(last), Some(head)) if last.seq < head.seq => (
+                last.seq + 1,
+                format!("the chain ends at entry {} but should end at entry {}; rows are missing", last.seq, head.seq),
+            ),
+            (Some(_), Some(head)) => (head.seq, format!("the chain does not end at the recorded entry {}", head.seq)),
+        };
+        return AuditVerification {
+            valid: false,
+            entries_checked: entries.len(),
+            last_hash: prev_hash,
+            first_invalid_seq: Some(first_invalid_seq),
+            problem: Some(problem),
+        };
+    }
+
+    AuditVerification {
+        valid: true,
+        entries_checked: entries.len(),
+        last_hash: prev_hash,
+        first_invalid_seq: None,
+        problem: None,
+    }
+}
+
+/// Writes entries as JSON Lines, one entry per line
+pub fn write_jsonl<W: Write>(writer: &mut W, entries: &[AuditEntry]) -> std::io::Result<usize> {
+    for entry in entries {
+        writeln!(writer, "{}", serde_json::to_string(entry)?)?;
+    }
+
+    writer.flush()?;
+    Ok(entries.len())
+}
+
+/// Appends an event to the audit log. Failures are logged rather than returned so auditing
+/// never blocks the action being audited.
+pub async fn record(
+    database: &Database,
+    event: &str,
+    actor: &str,
+    subject: Option<&str>,
+    data: serde_json::Value,
+) {
+    if let Err(e) = database.append_audit(event, actor, subject, data).await {
+        error!("Failed to write audit entry for {}: {}", event, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(length: i64) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        for seq in 1..=length {
+            let prev_hash = entries.last().map_or_else(|| GENESIS_HASH.to_string(), |e| e.hash.clone());
+            entries.push(AuditEntry::new(seq, prev_hash, "request-created", "agent", Some("r1"),
+                serde_json::json!({ "seq": seq })));
+        }
+        entries
+    }
+
+    fn head_of(entries: &[AuditEntry]) -> AuditHead {
+        let last = entries.last().unwrap();
+        AuditHead { seq: last.seq, hash: last.hash.clone() }
+    }
+
+    #[test]
+    fn hash_covers_every_field() {
+        let entry = chain(1).remove(0);
+        assert_eq!(entry.hash, entry.compute_hash());
+        assert_eq!(entry.hash.len(), 64);
+
+        let mut edited = entry.clone();
+        edited.actor = "someone-else".to_string();
+        assert_ne!(edited.compute_hash(), entry.hash);
+
+        // Moving text between fields changes the hash thanks to the separators
+        let mut shifted = entry.clone();
+        shifted.event = "request-createda".to_string();
+        shifted.actor = "gent".to_string();
+        assert_ne!(shifted.compute_hash(), entry.hash);
+    }
+
+    #[test]
+    fn intact_chain_verifies() {
+        let entries = chain(3);
+        let result = verify_chain(&entries, Some(&head_of(&entries)));
+        assert!(result.valid);
+        assert_eq!(result.entries_checked, 3);
+        assert_eq!(result.last_hash, entries[2].hash);
+        assert!(verify_chain(&[], None).valid);
+    }
+
+    #[test]
+    fn edited_entries_are_detected() {
+        let mut entries = chain(3);
+        let head = head_of(&entries);
+        entries[1].data = serde_json::json!({ "seq": 99 });
+        let result = verify_chain(&entries, Some(&head));
+        assert!(!result.valid);
+        assert_eq!(result.first_invalid_seq, Some(2));
+    }
+
+    #[test]
+    fn deleted_entries_are_detected() {
+        let mut entries = chain(3);
+        let head = head_of(&entries);
+        entries.remove(1);
+        assert_eq!(verify_chain(&entries, Some(&head)).first_invalid_seq, Some(3));
+    }
+
+    #[test]
+    fn truncated_chains_are_detected() {
+        let mut entries = chain(3);
+        let head = head_of(&entries);
+        entries.truncate(2);
+        let result = verify_chain(&entries, Some(&head));
+        assert!(!result.valid);
+        assert_eq!(result.first_invalid_seq, Some(3));
+
+        let result = verify_chain(&[], Some(&head));
+        assert!(!result.valid);
+        assert_eq!(result.first_invalid_seq, Some(1));
+    }
+
+    #[tokio::test]
+    async fn database_keeps_the_head_with_the_chain() {
+        let database = Database::new().await.unwrap();
+        assert!(database.get_audit_head().await.unwrap().is_none());
+        for event in ["operator-connected", "request-created", "request-answered"] {
+            database.append_audit(event, "alice", None, serde_json::Value::Null).await.unwrap();
+        }
+
+        let entries = database.get_audit_entries().await.unwrap();
+        let head = database.get_audit_head().await.unwrap().unwrap();
+        assert_eq!(head, head_of(&entries));
+        assert!(verify_chain(&entries, Some(&head)).valid);
+    }
+}
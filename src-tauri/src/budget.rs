@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The `budgets` section of the config file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    /// Limits on what a single agent may use
+    pub agent: Option<Budget>,
+    /// Limits on what all agents in a session may use together
+    pub session: Option<Budget>,
+    /// What happens to agents once a hard limit is crossed
+    pub on_exceeded: BudgetAction,
+}
+
+/// Crossing a soft limit only warns operators; crossing a hard limit halts the agents and
+/// blocks their human input requests until an operator raises the limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Budget {
+    pub soft: BudgetLimits,
+    pub hard: BudgetLimits,
+}
+
+/// Limits that aren't set don't apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetLimits {
+    /// Input plus output tokens
+    pub max_tokens: Option<u64>,
+    pub max_cost: Option<f64>,
+    /// Seconds since the agent connected or the session started
+    pub max_wall_clock_seconds: Option<u64>,
+    /// Human input requests sent
+    pub max_approvals: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetAction {
+    /// Pause the agents until an operator raises the limit
+    #[default]
+    Pause,
+    Stop,
+    /// Pause the agents and ask operators whether they may continue; approving lifts the
+    /// hard limit and resumes them, denying stops them
+    RequestApproval,
+}
+
+/// What a budget is measured against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "scope", content = "id", rename_all = "lowercase")]
+pub enum BudgetScope {
+    Agent(String),
+    Session(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetLevel {
+    Soft,
+    Hard,
+}
+
+/// Current consumption of one agent or session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    pub tokens: u64,
+    pub cost: f64,
+    pub wall_clock_seconds: u64,
+    pub approvals: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetBreach {
+    pub scope: BudgetScope,
+    pub level: BudgetLevel,
+    pub reason: String,
+    pub usage: BudgetUsage,
+}
+
+impl BudgetConfig {
+    pub fn for_scope(&self, scope: &BudgetScope) -> Option<&Budget> {
+        match scope {
+            BudgetScope::Agent(_) => self.agent.as_ref(),
+            BudgetScope::Session(_) => self.session.as_ref(),
+        }
+    }
+
+    /// Whether any budget limits wall-clock time, which has to be checked periodically
+    pub fn limits_wall_clock(&self) -> bool {
+        [&self.agent, &self.session].into_iter().flatten()
+            .any(|b| b.soft.max_wall_clock_seconds.is_some() || b.hard.max_wall_clock_seconds.is_some())
+    }
+}
+
+impl BudgetLimits {
+    /// Describes the first limit `usage` goes over, if any
+    pub fn exceeded_by(&self, usage: &BudgetUsage) -> Option<String> {
+        if let Some(max) = self.max_tokens.filter(|max| usage.tokens > *max) {
+            return Some(format!("used {} tokens, over the limit of {}", usage.tokens, max));
+        }
+        if let Some(max) = self.max_cost.filter(|max| usage.cost > *max) {
+            return Some(format!("spent ${:.4}, over the limit of ${:.4}", usage.cost, max));
+        }
+        if let Some(max) = self.max_wall_clock_seconds.filter(|max| usage.wall_clock_seconds > *max) {
+            return Some(format!("ran for {}s, over the limit of {}s", usage.wall_clock_seconds, max));
+        }
+        if let Some(max) = self.max_approvals.filter(|max| usage.approvals > *max) {
+            return Some(format!("sent {} human input requests, over the limit of {}", usage.approvals, max));
+        }
+        None
+    }
+}
+
+impl BudgetScope {
+    fn key(&self) -> String {
+        match self {
+            BudgetScope::Agent(id) => format!("agent:{}", id),
+            BudgetScope::Session(id) => format!("session:{}", id),
+        }
+    }
+}
+
+impl std::fmt::Display for BudgetScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetScope::Agent(id) => write!(f, "agent {}", id),
+            BudgetScope::Session(id) => write!(f, "session {}", id),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ScopeState {
+    approvals: u32,
+    warned: bool,
+    exceeded: bool,
+    // Hard limits an operator set in place of the configured ones
+    raised: Option<BudgetLimits>,
+}
+
+/// Which budgets have been crossed, and the per-scope counters usage reports don't cover
+#[derive(Default)]
+pub struct BudgetTracker {
+    scopes: HashMap<String, ScopeState>,
+    // Budget approval requests awaiting an answer, mapped to the breach they're about
+    approval_requests: HashMap<String, BudgetBreach>,
+}
+
+impl BudgetTracker {
+    pub fn new() -> Self {
+        BudgetTracker::default()
+    }
+
+    pub fn count_approval(&mut self, scope: &BudgetScope) {
+        self.scopes.entry(scope.key()).or_default().approvals += 1;
+    }
+
+    /// Takes back a count for a request that was refused after all
+    pub fn uncount_approval(&mut self, scope: &BudgetScope) {
+        if let Some(state) = self.scopes.get_mut(&scope.key()) {
+            state.approvals = state.approvals.saturating_sub(1);
+        }
+    }
+
+    pub fn approvals(&self, scope: &BudgetScope) -> u32 {
+        self.scopes.get(&scope.key()).map_or(0, |s| s.approvals)
+    }
+
+    /// Compares `usage` with the budget and returns a breach the first time each level is
+    /// crossed. A scope stays over its hard limit until the limit is raised.
+    pub fn check(&mut self, scope: &BudgetScope, budget: &Budget, usage: BudgetUsage) -> Option<BudgetBreach> {
+        let state = self.scopes.entry(scope.key()).or_default();
+        let hard = state.raised.as_ref().unwrap_or(&budget.hard);
+
+        let (level, reason) = if let Some(reason) = hard.exceeded_by(&usage).filter(|_| !state.exceeded) {
+            state.exceeded = true;
+            (BudgetLevel::Hard, reason)
+        } else if let Some(reason) = budget.soft.exceeded_by(&usage).filter(|_| !state.warned) {
+            state.warned = true;
+            (BudgetLevel::Soft, reason)
+        } else {
+            return None;
+        };
+
+        Some(BudgetBreach { scope: scope.clone(), level, reason, usage })
+    }
+
+    pub fn is_exceeded(&self, scope: &BudgetScope) -> bool {
+        self.scopes.get(&scope.key()).is_some_and(|s| s.exceeded)
+    }
+
+    /// Replaces the scope's hard limits and lifts the block. Returns whether the scope was
+    /// over its hard limit.
+    pub fn raise(&mut self, scope: &BudgetScope, limits: BudgetLimits) -> bool {
+        let state = self.scopes.entry(scope.key()).or_default();
+        state.raised = Some(limits);
+        std::mem::replace(&mut state.exceeded, false)
+    }
+
+    pub fn track_approval_request(&mut self, request_id: &str, breach: BudgetBreach) {
+        self.approval_requests.insert(request_id.to_string(), breach);
+    }
+
+    /// The breach a budget approval request was about, once it has been answered
+    pub fn take_approval_request(&mut self, request_id: &str) -> Option<BudgetBreach> {
+        self.approval_requests.remove(request_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(max_approvals: u32) -> Budget {
+        Budget {
+            soft: BudgetLimits::default(),
+            hard: BudgetLimits { max_approvals: Some(max_approvals), ..BudgetLimits::default() },
+        }
+    }
+
+    fn usage(approvals: u32) -> BudgetUsage {
+        BudgetUsage { approvals, ..BudgetUsage::default() }
+    }
+
+    #[test]
+    fn refused_requests_are_taken_back() {
+        let scope = BudgetScope::Agent("agent-1".to_string());
+        let mut tracker = BudgetTracker::new();
+        tracker.count_approval(&scope);
+        tracker.count_approval(&scope);
+        tracker.uncount_approval(&scope);
+        assert_eq!(tracker.approvals(&scope), 1);
+
+        tracker.uncount_approval(&scope);
+        tracker.uncount_approval(&scope);
+        assert_eq!(tracker.approvals(&scope), 0);
+    }
+
+    #[test]
+    fn hard_limit_blocks_until_raised() {
+        let scope = BudgetScope::Session("run-1".to_string());
+        let mut tracker = BudgetTracker::new();
+        assert!(tracker.check(&scope, &budget(2), usage(2)).is_none());
+
+        let breach = tracker.check(&scope, &budget(2), usage(3)).unwrap();
+        assert_eq!(breach.level, BudgetLevel::Hard);
+        assert!(tracker.is_exceeded(&scope));
+        // Reported once only
+        assert!(tracker.check(&scope, &budget(2), usage(4)).is_none());
+
+        assert!(tracker.raise(&scope, BudgetLimits { max_approvals: Some(10), ..BudgetLimits::default() }));
+        assert!(!tracker.is_exceeded(&scope));
+        assert!(tracker.check(&scope, &budget(2), usage(4)).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::access::AccessConfig;
+use crate::budget::BudgetConfig;
+use crate::policy::PolicyRule;
+use crate::priority::PriorityConfig;
+use crate::request_queue::QueueConfig;
+use crate::response_links::ResponseLinkConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::webhooks::WebhookConfig;
+
+/// Server configuration, read from `config.json` in the platform config directory
+/// (e.g. `~/.config/agent-hud/config.json`) or from the path in `AGENT_HUD_CONFIG`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HudConfig {
+    /// Rules evaluated against every human input request, in order
+    pub policies: Vec<PolicyRule>,
+    pub priority: PriorityConfig,
+    pub queue: QueueConfig,
+    pub access: AccessConfig,
+    pub budgets: BudgetConfig,
+    pub telemetry: TelemetryConfig,
+    /// Endpoints notified of request and agent lifecycle events
+    pub webhooks: Vec<WebhookConfig>,
+    /// Signed links that answer requests from outside the desktop app
+    pub response_links: ResponseLinkConfig,
+}
+
+impl HudConfig {
+    pub fn path() -> PathBuf {
+        if let Ok(path) = std::env::var("AGENT_HUD_CONFIG") {
+            return PathBuf::from(path);
+        }
+
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("agent-hud")
+            .join("config.json")
+    }
+
+    /// Loads the configuration file, falling back to defaults when it doesn't exist
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path();
+        if !path.exists() {
+            info!("No config file at {}, using defaults", path.display());
+            return Ok(HudConfig::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config: HudConfig = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        config.response_links.validate()
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+        info!("Loaded config from {} ({} policy rules)", path.display(), config.policies.len());
+        Ok(config)
+    }
+}
@@ -1,15 +1,31 @@
-use sqlx::{sqlite::SqlitePool, Row};
+use sqlx::{sqlite::{SqlitePool, SqliteRow}, Row};
 use std::path::PathBuf;
+use std::sync::Arc;
 use tracing::info;
+use uuid::Uuid;
 use crate::models::*;
+use crate::audit::{AuditEntry, AuditHead, GENESIS_HASH};
+use crate::session_archive::{ArchiveHeader, ArchiveRecord};
+use crate::usage::UsageReport;
+use crate::webhooks::{DeliveryStatus, WebhookDelivery};
 
+/// Stored in SQLite's `user_version` once the schema has been created; bump it when the
+/// schema changes
+const SCHEMA_VERSION: i64 = 5;
+
+/// Clones share the connection pool, so background tasks can hold their own handle
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    // Serializes audit appends so each entry links to the one before it
+    audit_lock: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl Database {
     pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        // Use in-memory database for simplicity and to avoid file permission issues
+        // Use in-memory database for simplicity and to avoid file permission issues. Nothing
+        // survives a restart, including the audit log: export it before closing the app to
+        // keep it.
         let database_url = "sqlite::memory:";
         
         info!("Initializing in-memory database");
@@ -17,7 +33,7 @@ impl Database {
         // Create connection pool
         let pool = SqlitePool::connect(&database_url).await?;
         
-        let database = Database { pool };
+        let database = Database { pool, audit_lock: Arc::new(tokio::sync::Mutex::new(())) };
         
         // Initialize database schema
         database.initialize_schema().await?;
@@ -35,7 +51,10 @@ impl Database {
                 status TEXT NOT NULL,
                 connected_at TEXT NOT NULL,
                 last_activity TEXT NOT NULL,
-                metadata TEXT
+                metadata TEXT,
+                parent_id TEXT,
+                tags TEXT NOT NULL DEFAULT '[]',
+                run_id TEXT
             )
             "#,
         )
@@ -51,6 +70,105 @@ impl Database {
                 message_type TEXT NOT NULL,
                 payload TEXT NOT NULL,
                 timestamp TEXT NOT NULL,
+                direction TEXT NOT NULL DEFAULT 'inbound',
+                sender TEXT,
+                delivered_at TEXT,
+                session_id TEXT,
+                FOREIGN KEY (agent_id) REFERENCES agents (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create sessions table; agents, messages and requests refer to it by session id
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                name TEXT,
+                started_by TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                ended_at TEXT,
+                status TEXT NOT NULL,
+                summary TEXT,
+                metadata TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create usage_reports table, one row per LLM call an agent reports
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS usage_reports (
+                id TEXT PRIMARY KEY,
+                agent_id TEXT NOT NULL,
+                session_id TEXT,
+                model TEXT NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cost REAL NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                timestamp TEXT NOT NULL,
+                FOREIGN KEY (agent_id) REFERENCES agents (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create webhook_deliveries table, one row per event sent to a webhook
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook TEXT NOT NULL,
+                event TEXT NOT NULL,
+                url TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                response_status INTEGER,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                next_attempt_at TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create used_response_links table; a request's links are spent once one is followed
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS used_response_links (
+                request_id TEXT PRIMARY KEY,
+                used_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create tool_calls table, one row per span
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tool_calls (
+                span_id TEXT PRIMARY KEY,
+                parent_span_id TEXT,
+                agent_id TEXT NOT NULL,
+                session_id TEXT,
+                tool_name TEXT NOT NULL,
+                arguments TEXT,
+                result TEXT,
+                error TEXT,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                ended_at TEXT,
+                duration_ms INTEGER,
                 FOREIGN KEY (agent_id) REFERENCES agents (id)
             )
             "#,
@@ -69,10 +187,18 @@ impl Database {
                 message TEXT NOT NULL,
                 options TEXT,
                 context TEXT,
+                schema TEXT,
+                min_selections INTEGER,
+                max_selections INTEGER,
                 timeout_seconds INTEGER NOT NULL,
+                on_timeout TEXT,
                 timestamp TEXT NOT NULL,
                 status TEXT NOT NULL,
                 priority TEXT NOT NULL,
+                priority_reason TEXT,
+                claimed_by TEXT,
+                required_approvers INTEGER,
+                session_id TEXT,
                 FOREIGN KEY (agent_id) REFERENCES agents (id)
             )
             "#,
@@ -90,6 +216,25 @@ impl Database {
                 additional_context TEXT,
                 responded_by TEXT NOT NULL,
                 timestamp TEXT NOT NULL,
+                source TEXT NOT NULL DEFAULT 'operator',
+                FOREIGN KEY (request_id) REFERENCES human_requests (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create request_approvals table for requests that need several operators to sign off
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS request_approvals (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                request_id TEXT NOT NULL,
+                operator TEXT NOT NULL,
+                approved INTEGER NOT NULL,
+                comment TEXT,
+                timestamp TEXT NOT NULL,
+                UNIQUE (request_id, operator),
                 FOREIGN KEY (request_id) REFERENCES human_requests (id)
             )
             "#,
@@ -97,16 +242,138 @@ impl Database {
         .execute(&self.pool)
         .await?;
         
+        // Create audit_log table; the triggers keep it append-only
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_log (
+                seq INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                event TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                subject TEXT,
+                data TEXT NOT NULL,
+                prev_hash TEXT NOT NULL,
+                hash TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN SELECT RAISE(ABORT, 'audit_log is append-only'); END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create audit_head table, a single row holding the last entry of the chain. A chain
+        // that is cut short still verifies on its own; comparing it with the head doesn't.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS audit_head (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                seq INTEGER NOT NULL,
+                hash TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_head_forward_only BEFORE UPDATE ON audit_head
+            WHEN NEW.seq != OLD.seq + 1
+            BEGIN SELECT RAISE(ABORT, 'audit_head only moves forward'); END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_head_no_delete BEFORE DELETE ON audit_head
+            BEGIN SELECT RAISE(ABORT, 'audit_head cannot be deleted'); END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create session_archives table for imported, read-only sessions
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS session_archives (
+                id TEXT PRIMARY KEY,
+                label TEXT NOT NULL,
+                source_path TEXT NOT NULL,
+                format_version INTEGER NOT NULL,
+                exported_at TEXT NOT NULL,
+                imported_at TEXT NOT NULL,
+                started_at TEXT,
+                ended_at TEXT,
+                record_count INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        // Create archive_records table holding the raw records of each imported session
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS archive_records (
+                archive_id TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                agent_id TEXT,
+                timestamp TEXT NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (archive_id, seq),
+                FOREIGN KEY (archive_id) REFERENCES session_archives (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+            .execute(&self.pool)
+            .await?;
+        
         info!("Database schema initialized successfully");
         Ok(())
     }
     
+    /// Runs a trivial query to check that the pool can hand out a working connection
+    pub async fn ping(&self) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+    
+    /// Whether the schema has been created at the version this build expects
+    pub async fn schema_ready(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&self.pool).await?;
+        Ok(version == SCHEMA_VERSION)
+    }
+    
     pub async fn save_agent(&self, agent: &Agent) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query(
             r#"
             INSERT OR REPLACE INTO agents 
-            (id, name, status, connected_at, last_activity, metadata)
-            VALUES (?, ?, ?, ?, ?, ?)
+            (id, name, status, connected_at, last_activity, metadata, parent_id, tags, run_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&agent.id)
@@ -115,6 +382,9 @@ impl Database {
         .bind(agent.connected_at.to_rfc3339())
         .bind(agent.last_activity.to_rfc3339())
         .bind(agent.metadata.as_ref().and_then(|m| serde_json::to_string(m).ok()))
+        .bind(&agent.parent_id)
+        .bind(serde_json::to_string(&agent.tags)?)
+        .bind(&agent.run_id)
         .execute(&self.pool)
         .await?;
         
@@ -125,8 +395,8 @@ impl Database {
         sqlx::query(
             r#"
             INSERT INTO agent_messages 
-            (id, agent_id, message_type, payload, timestamp)
-            VALUES (?, ?, ?, ?, ?)
+            (id, agent_id, message_type, payload, timestamp, direction, sender, delivered_at, session_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&message.id)
@@ -134,19 +404,75 @@ impl Database {
         .bind(&message.message_type)
         .bind(serde_json::to_string(&message.payload)?)
         .bind(message.timestamp.to_rfc3339())
+        .bind(message.direction.to_string())
+        .bind(&message.sender)
+        .bind(message.delivered_at.map(|t| t.to_rfc3339()))
+        .bind(&message.session_id)
         .execute(&self.pool)
         .await?;
         
         Ok(())
     }
     
+    /// Records that an agent acknowledged an outbound message. Returns the updated message,
+    /// or `None` if the agent has no such undelivered message.
+    pub async fn mark_message_delivered(
+        &self,
+        message_id: &str,
+        agent_id: &str,
+    ) -> Result<Option<AgentMessage>, Box<dyn std::error::Error>> {
+        let result = sqlx::query(
+            r#"
+            UPDATE agent_messages SET delivered_at = ?
+            WHERE id = ? AND agent_id = ? AND direction = 'outbound' AND delivered_at IS NULL
+            "#,
+        )
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(message_id)
+        .bind(agent_id)
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        
+        let row = sqlx::query("SELECT * FROM agent_messages WHERE id = ?")
+            .bind(message_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Some(Self::row_to_message(&row)?))
+    }
+    
+    /// Outbound messages an agent has not acknowledged yet, oldest first
+    pub async fn get_undelivered_messages(&self, agent_id: &str) -> Result<Vec<AgentMessage>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM agent_messages
+            WHERE agent_id = ? AND direction = 'outbound' AND delivered_at IS NULL
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(agent_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(Self::row_to_message(&row)?);
+        }
+        
+        Ok(messages)
+    }
+    
     pub async fn save_human_request(&self, request: &HumanInputRequest) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query(
             r#"
             INSERT OR REPLACE INTO human_requests 
-            (id, agent_id, agent_name, request_type, message, options, context, 
-             timeout_seconds, timestamp, status, priority)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            (id, agent_id, agent_name, request_type, message, options, context, schema,
+             min_selections, max_selections, timeout_seconds, on_timeout, timestamp, status, priority,
+             priority_reason, claimed_by, required_approvers, session_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&request.id)
@@ -156,10 +482,18 @@ impl Database {
         .bind(&request.message)
         .bind(serde_json::to_string(&request.options)?)
         .bind(request.context.as_ref().and_then(|c| serde_json::to_string(c).ok()))
+        .bind(request.schema.as_ref().and_then(|s| serde_json::to_string(s).ok()))
+        .bind(request.min_selections.map(|n| n as i64))
+        .bind(request.max_selections.map(|n| n as i64))
         .bind(request.timeout_seconds as i64)
+        .bind(request.on_timeout.as_ref().and_then(|p| serde_json::to_string(p).ok()))
         .bind(request.timestamp.to_rfc3339())
         .bind(request.status.to_string())
         .bind(request.priority.to_string())
+        .bind(&request.priority_reason)
+        .bind(request.claimed_by.as_ref().and_then(|c| serde_json::to_string(c).ok()))
+        .bind(request.required_approvers.map(|n| n as i64))
+        .bind(&request.session_id)
         .execute(&self.pool)
         .await?;
         
@@ -170,21 +504,69 @@ impl Database {
         sqlx::query(
             r#"
             INSERT INTO human_responses 
-            (request_id, response, additional_context, responded_by, timestamp)
-            VALUES (?, ?, ?, ?, ?)
+            (request_id, response, additional_context, responded_by, timestamp, source)
+            VALUES (?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&response.request_id)
-        .bind(&response.response)
+        .bind(serde_json::to_string(&response.response)?)
         .bind(&response.additional_context)
         .bind(&response.responded_by)
         .bind(response.timestamp.to_rfc3339())
+        .bind(response.source.to_string())
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn save_approval(&self, request_id: &str, approval: &Approval) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO request_approvals (request_id, operator, approved, comment, timestamp)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(request_id)
+        .bind(&approval.operator)
+        .bind(approval.approved)
+        .bind(&approval.comment)
+        .bind(approval.timestamp.to_rfc3339())
         .execute(&self.pool)
         .await?;
         
         Ok(())
     }
     
+    pub async fn get_approvals(&self, request_id: &str) -> Result<Vec<Approval>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM request_approvals WHERE request_id = ? ORDER BY timestamp")
+            .bind(request_id)
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut approvals = Vec::new();
+        for row in rows {
+            approvals.push(Approval {
+                operator: row.get("operator"),
+                approved: row.get("approved"),
+                comment: row.get("comment"),
+                timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            });
+        }
+        
+        Ok(approvals)
+    }
+    
+    pub async fn update_request_status(&self, request_id: &str, status: &RequestStatus) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE human_requests SET status = ? WHERE id = ?")
+            .bind(status.to_string())
+            .bind(request_id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
     pub async fn get_recent_agents(&self, limit: i64) -> Result<Vec<Agent>, Box<dyn std::error::Error>> {
         let rows = sqlx::query(
             "SELECT * FROM agents ORDER BY last_activity DESC LIMIT ?"
@@ -195,46 +577,389 @@ impl Database {
         
         let mut agents = Vec::new();
         for row in rows {
-            let metadata_str: Option<String> = row.get("metadata");
-            let metadata = metadata_str
-                .and_then(|s| serde_json::from_str(&s).ok());
-            
-            agents.push(Agent {
-                id: row.get("id"),
-                name: row.get("name"),
-                status: match row.get::<String, _>("status").as_str() {
-                    "connected" => AgentStatus::Connected,
-                    "active" => AgentStatus::Active,
-                    _ => AgentStatus::Disconnected,
-                },
-                connected_at: chrono::DateTime::parse_from_rfc3339(&row.get::<String, _>("connected_at"))?.with_timezone(&chrono::Utc),
-                last_activity: chrono::DateTime::parse_from_rfc3339(&row.get::<String, _>("last_activity"))?.with_timezone(&chrono::Utc),
-                metadata,
-            });
+            agents.push(Self::row_to_agent(&row)?);
         }
         
         Ok(agents)
     }
     
-    pub async fn get_recent_messages(&self, limit: i64) -> Result<Vec<AgentMessage>, Box<dyn std::error::Error>> {
+    /// Every agent that took part in a run, oldest first
+    pub async fn get_run_agents(&self, run_id: &str) -> Result<Vec<Agent>, Box<dyn std::error::Error>> {
         let rows = sqlx::query(
-            "SELECT * FROM agent_messages ORDER BY timestamp DESC LIMIT ?"
+            "SELECT * FROM agents WHERE run_id = ? ORDER BY connected_at ASC"
         )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut agents = Vec::new();
+        for row in rows {
+            agents.push(Self::row_to_agent(&row)?);
+        }
+        
+        Ok(agents)
+    }
+    
+    pub async fn save_session(&self, session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO sessions
+            (id, name, started_by, started_at, ended_at, status, summary, metadata)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&session.id)
+        .bind(&session.name)
+        .bind(&session.started_by)
+        .bind(session.started_at.to_rfc3339())
+        .bind(session.ended_at.map(|t| t.to_rfc3339()))
+        .bind(session.status.to_string())
+        .bind(&session.summary)
+        .bind(session.metadata.as_ref().and_then(|m| serde_json::to_string(m).ok()))
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn get_session(&self, session_id: &str) -> Result<Option<Session>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM sessions WHERE id = ?")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        row.as_ref().map(Self::row_to_session).transpose()
+    }
+    
+    /// Sessions, most recently started first
+    pub async fn list_sessions(
+        &self,
+        status: Option<SessionStatus>,
+        limit: i64,
+    ) -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            "SELECT * FROM sessions WHERE (?1 IS NULL OR status = ?1) ORDER BY started_at DESC LIMIT ?2"
+        )
+        .bind(status.map(|s| s.to_string()))
         .bind(limit)
         .fetch_all(&self.pool)
         .await?;
         
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(Self::row_to_session(&row)?);
+        }
+        
+        Ok(sessions)
+    }
+    
+    pub async fn count_sessions(&self, status: SessionStatus) -> Result<i64, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM sessions WHERE status = ?")
+            .bind(status.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.get("count"))
+    }
+    
+    /// Loads a session with its agents, messages, requests and responses in time order
+    pub async fn get_session_detail(&self, session_id: &str) -> Result<Option<SessionDetail>, Box<dyn std::error::Error>> {
+        let Some(session) = self.get_session(session_id).await? else {
+            return Ok(None);
+        };
+        
+        let agents = self.get_run_agents(session_id).await?;
+        
+        let rows = sqlx::query("SELECT * FROM agent_messages WHERE session_id = ? ORDER BY timestamp")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await?;
         let mut messages = Vec::new();
         for row in rows {
-            messages.push(AgentMessage {
+            messages.push(Self::row_to_message(&row)?);
+        }
+        
+        let rows = sqlx::query("SELECT * FROM human_requests WHERE session_id = ? ORDER BY timestamp")
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut human_requests = Vec::new();
+        for row in rows {
+            let mut request = Self::row_to_human_request(&row)?;
+            if request.quorum() > 1 {
+                request.approvals = self.get_approvals(&request.id).await?;
+            }
+            human_requests.push(request);
+        }
+        
+        let rows = sqlx::query(
+            r#"
+            SELECT r.* FROM human_responses r
+            JOIN human_requests q ON q.id = r.request_id
+            WHERE q.session_id = ?
+            ORDER BY r.timestamp
+            "#,
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut human_responses = Vec::new();
+        for row in rows {
+            human_responses.push(Self::row_to_human_response(&row)?);
+        }
+        
+        Ok(Some(SessionDetail { session, agents, messages, human_requests, human_responses }))
+    }
+    
+    /// Stores a usage report, returning false if one with the same id is already stored
+    pub async fn save_usage_report(&self, report: &UsageReport) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO usage_reports
+            (id, agent_id, session_id, model, input_tokens, output_tokens, cost, latency_ms, timestamp)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&report.id)
+        .bind(&report.agent_id)
+        .bind(&report.session_id)
+        .bind(&report.model)
+        .bind(report.input_tokens as i64)
+        .bind(report.output_tokens as i64)
+        .bind(report.cost)
+        .bind(report.latency_ms as i64)
+        .bind(report.timestamp.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() == 1)
+    }
+    
+    pub async fn get_usage_reports(
+        &self,
+        agent_id: Option<&str>,
+        session_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<UsageReport>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM usage_reports
+            WHERE (?1 IS NULL OR agent_id = ?1)
+              AND (?2 IS NULL OR session_id = ?2)
+            ORDER BY timestamp DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(agent_id)
+        .bind(session_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut reports = Vec::new();
+        for row in rows {
+            reports.push(UsageReport {
                 id: row.get("id"),
                 agent_id: row.get("agent_id"),
-                message_type: row.get("message_type"),
+                session_id: row.get("session_id"),
+                model: row.get("model"),
+                input_tokens: row.get::<i64, _>("input_tokens") as u64,
+                output_tokens: row.get::<i64, _>("output_tokens") as u64,
+                cost: row.get("cost"),
+                latency_ms: row.get::<i64, _>("latency_ms") as u64,
+                timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            });
+        }
+        
+        Ok(reports)
+    }
+    
+    pub async fn save_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO webhook_deliveries
+            (id, webhook, event, url, payload, status, attempts, response_status, last_error,
+             created_at, updated_at, next_attempt_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook)
+        .bind(delivery.event.to_string())
+        .bind(&delivery.url)
+        .bind(delivery.payload.to_string())
+        .bind(delivery.status.to_string())
+        .bind(delivery.attempts as i64)
+        .bind(delivery.response_status.map(|s| s as i64))
+        .bind(&delivery.last_error)
+        .bind(delivery.created_at.to_rfc3339())
+        .bind(delivery.updated_at.to_rfc3339())
+        .bind(delivery.next_attempt_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Spends the response links of a request. Returns false if they were already spent.
+    pub async fn consume_response_link(&self, request_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("INSERT OR IGNORE INTO used_response_links (request_id, used_at) VALUES (?, ?)")
+            .bind(request_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() == 1)
+    }
+    
+    /// Makes a request's response links usable again after the answer they gave was refused
+    pub async fn restore_response_link(&self, request_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM used_response_links WHERE request_id = ?")
+            .bind(request_id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn response_link_used(&self, request_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT 1 FROM used_response_links WHERE request_id = ?")
+            .bind(request_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        Ok(row.is_some())
+    }
+    
+    pub async fn human_request_exists(&self, request_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT 1 FROM human_requests WHERE id = ?")
+            .bind(request_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        Ok(row.is_some())
+    }
+    
+    /// The delivery log, most recent first
+    pub async fn get_webhook_deliveries(
+        &self,
+        webhook: Option<&str>,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM webhook_deliveries
+            WHERE (?1 IS NULL OR webhook = ?1)
+              AND (?2 IS NULL OR status = ?2)
+            ORDER BY created_at DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(webhook)
+        .bind(status.map(|s| s.to_string()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut deliveries = Vec::new();
+        for row in rows {
+            deliveries.push(WebhookDelivery {
+                id: row.get("id"),
+                webhook: row.get("webhook"),
+                event: serde_json::from_value(serde_json::Value::String(row.get("event")))?,
+                url: row.get("url"),
                 payload: serde_json::from_str(&row.get::<String, _>("payload"))?,
-                timestamp: chrono::DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))?.with_timezone(&chrono::Utc),
+                status: DeliveryStatus::parse(&row.get::<String, _>("status"))
+                    .unwrap_or(DeliveryStatus::Pending),
+                attempts: row.get::<i64, _>("attempts") as u32,
+                response_status: row.get::<Option<i64>, _>("response_status").map(|s| s as u16),
+                last_error: row.get("last_error"),
+                created_at: Self::parse_timestamp(&row.get::<String, _>("created_at"))?,
+                updated_at: Self::parse_timestamp(&row.get::<String, _>("updated_at"))?,
+                next_attempt_at: row.get::<Option<String>, _>("next_attempt_at")
+                    .map(|t| Self::parse_timestamp(&t))
+                    .transpose()?,
             });
         }
         
+        Ok(deliveries)
+    }
+    
+    pub async fn save_tool_call(&self, call: &ToolCall) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO tool_calls
+            (span_id, parent_span_id, agent_id, session_id, tool_name, arguments, result, error,
+             status, started_at, ended_at, duration_ms)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&call.span_id)
+        .bind(&call.parent_span_id)
+        .bind(&call.agent_id)
+        .bind(&call.session_id)
+        .bind(&call.tool_name)
+        .bind(call.arguments.as_ref().and_then(|a| serde_json::to_string(a).ok()))
+        .bind(call.result.as_ref().and_then(|r| serde_json::to_string(r).ok()))
+        .bind(&call.error)
+        .bind(call.status.to_string())
+        .bind(call.started_at.to_rfc3339())
+        .bind(call.ended_at.map(|t| t.to_rfc3339()))
+        .bind(call.duration_ms.map(|d| d as i64))
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn get_tool_call(&self, span_id: &str) -> Result<Option<ToolCall>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM tool_calls WHERE span_id = ?")
+            .bind(span_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        row.as_ref().map(Self::row_to_tool_call).transpose()
+    }
+    
+    /// An agent's tool calls, optionally only those made in one session, in start order
+    pub async fn get_tool_calls(
+        &self,
+        agent_id: &str,
+        session_id: Option<&str>,
+    ) -> Result<Vec<ToolCall>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM tool_calls
+            WHERE agent_id = ?1 AND (?2 IS NULL OR session_id = ?2)
+            ORDER BY started_at
+            "#,
+        )
+        .bind(agent_id)
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut calls = Vec::new();
+        for row in rows {
+            calls.push(Self::row_to_tool_call(&row)?);
+        }
+        
+        Ok(calls)
+    }
+    
+    pub async fn get_recent_messages(&self, limit: i64) -> Result<Vec<AgentMessage>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            "SELECT * FROM agent_messages ORDER BY timestamp DESC LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut messages = Vec::new();
+        for row in rows {
+            messages.push(Self::row_to_message(&row)?);
+        }
+        
         Ok(messages)
     }
     
@@ -248,37 +973,11 @@ impl Database {
         
         let mut requests = Vec::new();
         for row in rows {
-            let request_type_str: String = row.get("request_type");
-            let request_type = serde_json::from_str(&request_type_str).unwrap_or(RequestType::Input);
-            
-            let options_str: String = row.get("options");
-            let options = serde_json::from_str(&options_str).unwrap_or_default();
-            
-            let context_str: Option<String> = row.get("context");
-            let context = context_str.and_then(|s| serde_json::from_str(&s).ok());
-            
-            requests.push(HumanInputRequest {
-                id: row.get("id"),
-                agent_id: row.get("agent_id"),
-                agent_name: row.get("agent_name"),
-                request_type,
-                message: row.get("message"),
-                options,
-                context,
-                timeout_seconds: row.get::<i64, _>("timeout_seconds") as u32,
-                timestamp: chrono::DateTime::parse_from_rfc3339(&row.get::<String, _>("timestamp"))?.with_timezone(&chrono::Utc),
-                status: match row.get::<String, _>("status").as_str() {
-                    "completed" => RequestStatus::Completed,
-                    "timeout" => RequestStatus::Timeout,
-                    _ => RequestStatus::Pending,
-                },
-                priority: match row.get::<String, _>("priority").as_str() {
-                    "low" => RequestPriority::Low,
-                    "high" => RequestPriority::High,
-                    "critical" => RequestPriority::Critical,
-                    _ => RequestPriority::Medium,
-                },
-            });
+            let mut request = Self::row_to_human_request(&row)?;
+            if request.quorum() > 1 {
+                request.approvals = self.get_approvals(&request.id).await?;
+            }
+            requests.push(request);
         }
         
         Ok(requests)
@@ -302,5 +1001,476 @@ impl Database {
         
         info!("Cleaned up data older than {} days", days);
         Ok(())
+    }    
+    /// Collects everything recorded for an agent and/or time window, in chronological order
+    pub async fn collect_export_records(&self, filter: &ExportFilter) -> Result<Vec<ArchiveRecord>, Box<dyn std::error::Error>> {
+        let since = filter.since.map(|t| t.to_rfc3339());
+        let until = filter.until.map(|t| t.to_rfc3339());
+        let mut records = Vec::new();
+        
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM agents
+            WHERE (?1 IS NULL OR id = ?1)
+              AND (?2 IS NULL OR last_activity >= ?2)
+              AND (?3 IS NULL OR connected_at <= ?3)
+            ORDER BY connected_at
+            "#,
+        )
+        .bind(&filter.agent_id)
+        .bind(&since)
+        .bind(&until)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            records.push(ArchiveRecord::Agent(Self::row_to_agent(&row)?));
+        }
+        
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM agent_messages
+            WHERE (?1 IS NULL OR agent_id = ?1)
+              AND (?2 IS NULL OR timestamp >= ?2)
+              AND (?3 IS NULL OR timestamp <= ?3)
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(&filter.agent_id)
+        .bind(&since)
+        .bind(&until)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            records.push(ArchiveRecord::from_message(Self::row_to_message(&row)?));
+        }
+        
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM human_requests
+            WHERE (?1 IS NULL OR agent_id = ?1)
+              AND (?2 IS NULL OR timestamp >= ?2)
+              AND (?3 IS NULL OR timestamp <= ?3)
+            ORDER BY timestamp
+            "#,
+        )
+        .bind(&filter.agent_id)
+        .bind(&since)
+        .bind(&until)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            let mut request = Self::row_to_human_request(&row)?;
+            if request.quorum() > 1 {
+                request.approvals = self.get_approvals(&request.id).await?;
+            }
+            records.push(ArchiveRecord::Request(Box::new(request)));
+        }
+        
+        let rows = sqlx::query(
+            r#"
+            SELECT r.* FROM human_responses r
+            JOIN human_requests q ON q.id = r.request_id
+            WHERE (?1 IS NULL OR q.agent_id = ?1)
+              AND (?2 IS NULL OR r.timestamp >= ?2)
+              AND (?3 IS NULL OR r.timestamp <= ?3)
+            ORDER BY r.timestamp
+            "#,
+        )
+        .bind(&filter.agent_id)
+        .bind(&since)
+        .bind(&until)
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            records.push(ArchiveRecord::Response(Self::row_to_human_response(&row)?));
+        }
+        
+        records.sort_by_key(|r| r.timestamp());
+        Ok(records)
+    }
+    
+    /// Stores a parsed archive as a new read-only session and returns its summary
+    pub async fn import_archive(
+        &self,
+        source_path: &str,
+        header: &ArchiveHeader,
+        records: &[ArchiveRecord],
+    ) -> Result<SessionArchiveInfo, Box<dyn std::error::Error>> {
+        let info = SessionArchiveInfo {
+            id: Uuid::new_v4().to_string(),
+            label: header.label.clone().unwrap_or_else(|| source_path.to_string()),
+            source_path: source_path.to_string(),
+            format_version: header.version,
+            exported_at: header.exported_at,
+            imported_at: chrono::Utc::now(),
+            started_at: records.first().map(|r| r.timestamp()),
+            ended_at: records.last().map(|r| r.timestamp()),
+            record_count: records.len() as u32,
+        };
+        
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO session_archives
+            (id, label, source_path, format_version, exported_at, imported_at, started_at, ended_at, record_count)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&info.id)
+        .bind(&info.label)
+        .bind(&info.source_path)
+        .bind(info.format_version as i64)
+        .bind(info.exported_at.to_rfc3339())
+        .bind(info.imported_at.to_rfc3339())
+        .bind(info.started_at.map(|t| t.to_rfc3339()))
+        .bind(info.ended_at.map(|t| t.to_rfc3339()))
+        .bind(info.record_count as i64)
+        .execute(&mut *tx)
+        .await?;
+        
+        for (seq, record) in records.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO archive_records
+                (archive_id, seq, kind, agent_id, timestamp, data)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&info.id)
+            .bind(seq as i64)
+            .bind(record.kind())
+            .bind(record.agent_id())
+            .bind(record.timestamp().to_rfc3339())
+            .bind(serde_json::to_string(record)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        tx.commit().await?;
+        
+        info!("Imported session archive {} ({} records)", info.id, info.record_count);
+        Ok(info)
+    }
+    
+    pub async fn list_session_archives(&self) -> Result<Vec<SessionArchiveInfo>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM session_archives ORDER BY imported_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut archives = Vec::new();
+        for row in rows {
+            archives.push(Self::row_to_archive_info(&row)?);
+        }
+        
+        Ok(archives)
+    }
+    
+    /// Returns the records of an imported session in their original order
+    pub async fn get_archive_records(&self, archive_id: &str) -> Result<Vec<ArchiveRecord>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT data FROM archive_records WHERE archive_id = ? ORDER BY seq")
+            .bind(archive_id)
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(serde_json::from_str(&row.get::<String, _>("data"))?);
+        }
+        
+        Ok(records)
+    }
+    
+    pub async fn get_archived_session(&self, archive_id: &str) -> Result<ArchivedSession, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM session_archives WHERE id = ?")
+            .bind(archive_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or("Session archive not found")?;
+        
+        let mut session = ArchivedSession {
+            info: Self::row_to_archive_info(&row)?,
+            agents: Vec::new(),
+            messages: Vec::new(),
+            human_requests: Vec::new(),
+            human_responses: Vec::new(),
+        };
+        
+        for record in self.get_archive_records(archive_id).await? {
+            match record {
+                ArchiveRecord::Agent(agent) => session.agents.push(agent),
+                ArchiveRecord::Message(message) | ArchiveRecord::Content(message) => session.messages.push(message),
+                ArchiveRecord::Request(request) => session.human_requests.push(*request),
+                ArchiveRecord::Response(response) => session.human_responses.push(response),
+                ArchiveRecord::Header(_) => {}
+            }
+        }
+        
+        Ok(session)
+    }
+    
+    /// Appends an entry to the audit log, chained to the current last entry, and moves the
+    /// chain's head to it
+    pub async fn append_audit(
+        &self,
+        event: &str,
+        actor: &str,
+        subject: Option<&str>,
+        data: serde_json::Value,
+    ) -> Result<AuditEntry, Box<dyn std::error::Error>> {
+        let _guard = self.audit_lock.lock().await;
+        
+        let last = sqlx::query("SELECT seq, hash FROM audit_log ORDER BY seq DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        let (seq, prev_hash) = match last {
+            Some(row) => (row.get::<i64, _>("seq") + 1, row.get::<String, _>("hash")),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        
+        let entry = AuditEntry::new(seq, prev_hash, event, actor, subject, data);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (seq, timestamp, event, actor, subject, data, prev_hash, hash)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(entry.seq)
+        .bind(entry.timestamp.to_rfc3339())
+        .bind(&entry.event)
+        .bind(&entry.actor)
+        .bind(&entry.subject)
+        .bind(entry.data.to_string())
+        .bind(&entry.prev_hash)
+        .bind(&entry.hash)
+        .execute(&mut *tx)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO audit_head (id, seq, hash) VALUES (1, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET seq = excluded.seq, hash = excluded.hash
+            "#,
+        )
+        .bind(entry.seq)
+        .bind(&entry.hash)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        
+        Ok(entry)
+    }
+    
+    /// The last entry the audit chain should end with, or `None` before anything was audited
+    pub async fn get_audit_head(&self) -> Result<Option<AuditHead>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT seq, hash FROM audit_head WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        Ok(row.map(|row| AuditHead { seq: row.get("seq"), hash: row.get("hash") }))
+    }
+    
+    /// The whole audit chain in sequence order
+    pub async fn get_audit_entries(&self) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM audit_log ORDER BY seq")
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            entries.push(AuditEntry {
+                seq: row.get("seq"),
+                timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+                event: row.get("event"),
+                actor: row.get("actor"),
+                subject: row.get("subject"),
+                data: serde_json::from_str::<serde_json::Value>(&row.get::<String, _>("data"))?,
+                prev_hash: row.get("prev_hash"),
+                hash: row.get("hash"),
+            });
+        }
+        
+        Ok(entries)
+    }
+    
+    pub async fn delete_session_archive(&self, archive_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM archive_records WHERE archive_id = ?")
+            .bind(archive_id)
+            .execute(&self.pool)
+            .await?;
+        
+        sqlx::query("DELETE FROM session_archives WHERE id = ?")
+            .bind(archive_id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    fn parse_timestamp(value: &str) -> Result<chrono::DateTime<chrono::Utc>, chrono::ParseError> {
+        Ok(chrono::DateTime::parse_from_rfc3339(value)?.with_timezone(&chrono::Utc))
+    }
+    
+    fn row_to_agent(row: &SqliteRow) -> Result<Agent, Box<dyn std::error::Error>> {
+        let metadata_str: Option<String> = row.get("metadata");
+        let metadata = metadata_str
+            .and_then(|s| serde_json::from_str(&s).ok());
+        
+        Ok(Agent {
+            id: row.get("id"),
+            name: row.get("name"),
+            status: match row.get::<String, _>("status").as_str() {
+                "connected" => AgentStatus::Connected,
+                "active" => AgentStatus::Active,
+                "paused" => AgentStatus::Paused,
+                "stopping" => AgentStatus::Stopping,
+                _ => AgentStatus::Disconnected,
+            },
+            connected_at: Self::parse_timestamp(&row.get::<String, _>("connected_at"))?,
+            last_activity: Self::parse_timestamp(&row.get::<String, _>("last_activity"))?,
+            metadata,
+            parent_id: row.get("parent_id"),
+            tags: serde_json::from_str(&row.get::<String, _>("tags")).unwrap_or_default(),
+            run_id: row.get("run_id"),
+        })
+    }
+    
+    fn row_to_message(row: &SqliteRow) -> Result<AgentMessage, Box<dyn std::error::Error>> {
+        Ok(AgentMessage {
+            id: row.get("id"),
+            agent_id: row.get("agent_id"),
+            message_type: row.get("message_type"),
+            payload: serde_json::from_str(&row.get::<String, _>("payload"))?,
+            timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            direction: match row.get::<String, _>("direction").as_str() {
+                "outbound" => MessageDirection::Outbound,
+                _ => MessageDirection::Inbound,
+            },
+            sender: row.get("sender"),
+            delivered_at: row.get::<Option<String>, _>("delivered_at")
+                .map(|t| Self::parse_timestamp(&t))
+                .transpose()?,
+            session_id: row.get("session_id"),
+        })
+    }
+    
+    fn row_to_human_request(row: &SqliteRow) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        let request_type_str: String = row.get("request_type");
+        let request_type = serde_json::from_str(&request_type_str).unwrap_or(RequestType::Input);
+        
+        let options_str: String = row.get("options");
+        let options = serde_json::from_str(&options_str).unwrap_or_default();
+        
+        let context_str: Option<String> = row.get("context");
+        let context = context_str.and_then(|s| serde_json::from_str(&s).ok());
+        
+        let schema_str: Option<String> = row.get("schema");
+        let schema = schema_str.and_then(|s| serde_json::from_str(&s).ok());
+        
+        Ok(HumanInputRequest {
+            id: row.get("id"),
+            agent_id: row.get("agent_id"),
+            agent_name: row.get("agent_name"),
+            request_type,
+            message: row.get("message"),
+            options,
+            context,
+            schema,
+            min_selections: row.get::<Option<i64>, _>("min_selections").map(|n| n as u32),
+            max_selections: row.get::<Option<i64>, _>("max_selections").map(|n| n as u32),
+            timeout_seconds: row.get::<i64, _>("timeout_seconds") as u32,
+            on_timeout: row.get::<Option<String>, _>("on_timeout")
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            status: match row.get::<String, _>("status").as_str() {
+                "completed" => RequestStatus::Completed,
+                "timeout" => RequestStatus::Timeout,
+                "cancelled" => RequestStatus::Cancelled,
+                _ => RequestStatus::Pending,
+            },
+            priority: RequestPriority::parse(&row.get::<String, _>("priority"))
+                .unwrap_or(RequestPriority::Medium),
+            priority_reason: row.get("priority_reason"),
+            claimed_by: row.get::<Option<String>, _>("claimed_by")
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            required_approvers: row.get::<Option<i64>, _>("required_approvers").map(|n| n as u32),
+            approvals: Vec::new(),
+            session_id: row.get("session_id"),
+        })
+    }
+    
+    fn row_to_human_response(row: &SqliteRow) -> Result<HumanResponse, Box<dyn std::error::Error>> {
+        // Responses are stored as JSON; fall back to plain text for anything that isn't
+        let response_str: String = row.get("response");
+        let response = serde_json::from_str(&response_str)
+            .unwrap_or(serde_json::Value::String(response_str));
+        
+        Ok(HumanResponse {
+            request_id: row.get("request_id"),
+            response,
+            additional_context: row.get("additional_context"),
+            responded_by: row.get("responded_by"),
+            timestamp: Self::parse_timestamp(&row.get::<String, _>("timestamp"))?,
+            source: ResponseSource::parse(&row.get::<String, _>("source")).unwrap_or_default(),
+        })
+    }
+    
+    fn row_to_session(row: &SqliteRow) -> Result<Session, Box<dyn std::error::Error>> {
+        let ended_at: Option<String> = row.get("ended_at");
+        
+        Ok(Session {
+            id: row.get("id"),
+            name: row.get("name"),
+            started_by: row.get("started_by"),
+            started_at: Self::parse_timestamp(&row.get::<String, _>("started_at"))?,
+            ended_at: ended_at.as_deref().map(Self::parse_timestamp).transpose()?,
+            status: SessionStatus::parse(&row.get::<String, _>("status")).unwrap_or(SessionStatus::Active),
+            summary: row.get("summary"),
+            metadata: row.get::<Option<String>, _>("metadata")
+                .and_then(|s| serde_json::from_str(&s).ok()),
+        })
+    }
+    
+    fn row_to_tool_call(row: &SqliteRow) -> Result<ToolCall, Box<dyn std::error::Error>> {
+        Ok(ToolCall {
+            span_id: row.get("span_id"),
+            parent_span_id: row.get("parent_span_id"),
+            agent_id: row.get("agent_id"),
+            session_id: row.get("session_id"),
+            tool_name: row.get("tool_name"),
+            arguments: row.get::<Option<String>, _>("arguments")
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            result: row.get::<Option<String>, _>("result")
+                .and_then(|s| serde_json::from_str(&s).ok()),
+            error: row.get("error"),
+            status: match row.get::<String, _>("status").as_str() {
+                "succeeded" => ToolCallStatus::Succeeded,
+                "failed" => ToolCallStatus::Failed,
+                _ => ToolCallStatus::Running,
+            },
+            started_at: Self::parse_timestamp(&row.get::<String, _>("started_at"))?,
+            ended_at: row.get::<Option<String>, _>("ended_at")
+                .map(|t| Self::parse_timestamp(&t))
+                .transpose()?,
+            duration_ms: row.get::<Option<i64>, _>("duration_ms").map(|d| d as u64),
+        })
+    }
+    
+    fn row_to_archive_info(row: &SqliteRow) -> Result<SessionArchiveInfo, Box<dyn std::error::Error>> {
+        let started_at: Option<String> = row.get("started_at");
+        let ended_at: Option<String> = row.get("ended_at");
+        
+        Ok(SessionArchiveInfo {
+            id: row.get("id"),
+            label: row.get("label"),
+            source_path: row.get("source_path"),
+            format_version: row.get::<i64, _>("format_version") as u32,
+            exported_at: Self::parse_timestamp(&row.get::<String, _>("exported_at"))?,
+            imported_at: Self::parse_timestamp(&row.get::<String, _>("imported_at"))?,
+            started_at: started_at.as_deref().map(Self::parse_timestamp).transpose()?,
+            ended_at: ended_at.as_deref().map(Self::parse_timestamp).transpose()?,
+            record_count: row.get::<i64, _>("record_count") as u32,
+        })
     }
 }
\ No newline at end of file
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Limits for plain HTTP requests; agents and GUIs only ever send a WebSocket handshake
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// How long a client gets to send its whole request before the connection is dropped
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A plain HTTP request received on the WebSocket port
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.headers.get("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|v| v.as_str())
+    }
+
+    /// The fields of an `application/x-www-form-urlencoded` body
+    pub fn form(&self) -> HashMap<String, String> {
+        let is_form = self.header("content-type")
+            .is_some_and(|t| t.starts_with("application/x-www-form-urlencoded"));
+        if is_form {
+            parse_query(&String::from_utf8_lossy(&self.body))
+        } else {
+            HashMap::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        HttpResponse { status, content_type: "text/plain; charset=utf-8", body: body.into() }
+    }
+
+    pub fn html(status: u16, body: impl Into<String>) -> Self {
+        HttpResponse { status, content_type: "text/html; charset=utf-8", body: body.into() }
+    }
+
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        HttpResponse { status, content_type: "application/json", body: body.to_string() }
+    }
+
+    pub fn not_found() -> Self {
+        HttpResponse::text(404, "Not found\n")
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Connection closed before the request was complete")]
+    Incomplete,
+
+    #[error("Request is too large")]
+    TooLarge,
+
+    #[error("Malformed request: {0}")]
+    Malformed(String),
+
+    #[error("Timed out waiting for the request")]
+    Timeout,
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(&key.replace('+', " ")), percent_decode(&value.replace('+', " ")))
+        })
+        .collect()
+}
+
+/// Reads a request's head and, unless it is a WebSocket upgrade, its body. Also returns
+/// every byte read, so an upgrade can be replayed into the WebSocket handshake. Clients that
+/// take longer than `timeout` to send it all are cut off, so idle sockets can't pile up.
+pub async fn read_request(stream: &mut TcpStream, timeout: Duration) -> Result<(HttpRequest, Vec<u8>), HttpError> {
+    tokio::time::timeout(timeout, read_request_unbounded(stream)).await
+        .map_err(|_| HttpError::Timeout)?
+}
+
+async fn read_request_unbounded(stream: &mut TcpStream) -> Result<(HttpRequest, Vec<u8>), HttpError> {
+    let mut raw = Vec::new();
+    let mut buf = [0u8; 4096];
+    let head_end = loop {
+        if let Some(pos) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if raw.len() > MAX_HEAD_BYTES {
+            return Err(HttpError::TooLarge);
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(HttpError::Incomplete);
+        }
+        raw.extend_from_slice(&buf[..n]);
+    };
+
+    let head = std::str::from_utf8(&raw[..head_end])
+        .map_err(|_| HttpError::Malformed("request head is not UTF-8".to_string()))?;
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or("");
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(HttpError::Malformed(format!("bad request line '{}'", request_line))),
+    };
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest {
+        method,
+        path: percent_decode(path),
+        query: parse_query(query),
+        headers,
+        body: Vec::new(),
+    };
+
+    if !request.is_websocket_upgrade() {
+        let length: usize = request.header("content-length")
+            .map(|v| v.parse().map_err(|_| HttpError::Malformed(format!("bad content-length '{}'", v))))
+            .transpose()?
+            .unwrap_or(0);
+        if length > MAX_BODY_BYTES {
+            return Err(HttpError::TooLarge);
+        }
+        while raw.len() < head_end + length {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Err(HttpError::Incomplete);
+            }
+            raw.extend_from_slice(&buf[..n]);
+        }
+        request.body = raw[head_end..head_end + length].to_vec();
+    }
+
+    Ok((request, raw))
+}
+
+pub async fn write_response(stream: &mut TcpStream, response: &HttpResponse) -> Result<(), HttpError> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Serves one response per status in `statuses`, in order, and hands back each request. Tests
+/// of outgoing HTTP point their client at the returned URL, which ends in `path`.
+#[cfg(test)]
+pub async fn stub_server(path: &str, statuses: Vec<u16>) -> (String, tokio::sync::mpsc::UnboundedReceiver<HttpRequest>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for status in statuses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (request, _) = read_request(&mut stream, READ_TIMEOUT).await.unwrap();
+            let _ = tx.send(request);
+            write_response(&mut stream, &HttpResponse::text(status, "")).await.unwrap();
+        }
+    });
+    (url, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Connects a client that writes `sent` and then stalls, and returns the server's end
+    async fn connection(sent: &'static [u8]) -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        client.write_all(sent).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn complete_requests_are_parsed() {
+        let (mut server, _client) = connection(
+            b"POST /respond/r1?a=b%20c HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 9\r\n\r\noption=ok",
+        ).await;
+
+        let (request, _) = read_request(&mut server, READ_TIMEOUT).await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/respond/r1");
+        assert_eq!(request.query.get("a").map(String::as_str), Some("b c"));
+        assert_eq!(request.form().get("option").map(String::as_str), Some("ok"));
+    }
+
+    #[tokio::test]
+    async fn clients_that_never_finish_the_head_time_out() {
+        let (mut server, _client) = connection(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n").await;
+        let result = read_request(&mut server, Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(HttpError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn clients_that_never_finish_the_body_time_out() {
+        let (mut server, _client) = connection(b"POST /respond HTTP/1.1\r\nContent-Length: 100\r\n\r\npartial").await;
+        let result = read_request(&mut server, Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(HttpError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn upgrades_return_the_raw_handshake() {
+        let handshake = b"GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+        let (mut server, _client) = connection(handshake).await;
+
+        let (request, raw) = read_request(&mut server, READ_TIMEOUT).await.unwrap();
+        assert!(request.is_websocket_upgrade());
+        assert_eq!(raw, handshake);
+    }
+}
@@ -1,19 +1,49 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tauri::State;
 use tracing::{info, error};
 
 mod websocket_server;
+mod access;
+mod audit;
+mod budget;
 mod agent_protocol;
 mod database;
+mod http;
+mod metrics;
 mod models;
+mod response_validation;
+mod response_links;
+mod config;
+mod policy;
+mod priority;
+mod request_queue;
+mod session_archive;
+mod telemetry;
+mod replay;
+mod usage;
+mod webhooks;
 
 use websocket_server::WebSocketServer;
 use database::Database;
 use models::*;
+use session_archive::{ArchiveHeader, SessionArchive, ARCHIVE_FORMAT, ARCHIVE_FORMAT_VERSION};
+use replay::ReplaySession;
+use config::HudConfig;
+use access::{AccessControl, Permission, DESKTOP_OPERATOR};
+use audit::AuditVerification;
+use budget::{BudgetLimits, BudgetScope, BudgetTracker};
+use policy::{PolicyEngine, PolicyRule};
+use priority::PriorityClassifier;
+use request_queue::{QueueEntry, RequestQueue};
+use response_links::{RequestLinks, ResponseLinks};
+use telemetry::{TraceExporter, TraceSpan};
+use usage::{UsageReport, UsageSnapshot, UsageTracker};
+use webhooks::{DeliveryStatus, WebhookDelivery, WebhookDispatcher};
 
 pub type AppState = Arc<Mutex<AppStateInner>>;
 
@@ -21,7 +51,45 @@ pub struct AppStateInner {
     pub websocket_server: Option<Arc<WebSocketServer>>,
     pub database: Database,
     pub connected_agents: Vec<Agent>,
-    pub human_requests: Vec<HumanInputRequest>,
+    pub request_queue: RequestQueue,
+    pub replay: Option<Arc<ReplaySession>>,
+    pub config: HudConfig,
+    pub policy_engine: PolicyEngine,
+    pub priority_classifier: PriorityClassifier,
+    pub access_control: AccessControl,
+    /// Control commands sent to agents and not yet acknowledged, by control id
+    pub pending_controls: HashMap<String, AgentControl>,
+    pub usage: UsageTracker,
+    pub budgets: BudgetTracker,
+    /// Set when trace export is enabled in the config
+    pub telemetry: Option<TraceExporter>,
+    pub webhooks: WebhookDispatcher,
+    pub response_links: ResponseLinks,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+async fn running_server(state: &State<'_, AppState>) -> Result<Arc<WebSocketServer>, String> {
+    let app_state = state.lock().await;
+    app_state.websocket_server.clone()
+        .ok_or_else(|| "WebSocket server not running".to_string())
+}
+
+/// Checks that the desktop operator may run a control command, and records the command in
+/// the audit log. Commands always act as the desktop operator; GUIs on the WebSocket port
+/// act under the name they authenticated with.
+async fn authorize_control(
+    app_state: &AppStateInner,
+    command: &str,
+    args: serde_json::Value,
+) -> Result<(), String> {
+    app_state.access_control.authorize(DESKTOP_OPERATOR, Permission::Control, None)
+        .map_err(|e| e.to_string())?;
+    
+    audit::record(&app_state.database, "control-command", DESKTOP_OPERATOR, None, serde_json::json!({
+        "command": command,
+        "args": args,
+    })).await;
+    Ok(())
 }
 
 // Tauri commands that can be called from the frontend
@@ -45,6 +113,160 @@ async fn get_agents(state: State<'_, AppState>) -> Result<Vec<Agent>, String> {
     }
 }
 
+/// Agents arranged by parent, optionally limited to one run or to agents carrying a tag.
+/// A tag match keeps the agent's descendants so collapsed groups stay complete.
+#[tauri::command]
+async fn get_agent_tree(
+    state: State<'_, AppState>,
+    run_id: Option<String>,
+    tag: Option<String>,
+) -> Result<Vec<AgentNode>, String> {
+    let app_state = state.lock().await;
+    
+    let agents = match &run_id {
+        Some(run_id) => app_state.database.get_run_agents(run_id).await,
+        None => app_state.database.get_recent_agents(100).await,
+    };
+    let agents = agents.unwrap_or_else(|e| {
+        error!("❌ Database query failed: {}, using in-memory data", e);
+        app_state.connected_agents.iter()
+            .filter(|a| run_id.is_none() || a.run_id == run_id)
+            .cloned()
+            .collect()
+    });
+    
+    let forest = AgentNode::build_forest(agents);
+    Ok(match tag {
+        Some(tag) => AgentNode::filter_by_tag(forest, &tag),
+        None => forest,
+    })
+}
+
+/// Sessions, most recent first, optionally only those with the given status
+#[tauri::command]
+async fn get_sessions(
+    state: State<'_, AppState>,
+    status: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<Session>, String> {
+    let status = status
+        .map(|s| SessionStatus::parse(&s).ok_or_else(|| format!("Unknown session status '{}'", s)))
+        .transpose()?;
+    
+    let app_state = state.lock().await;
+    app_state.database.list_sessions(status, limit.unwrap_or(100)).await
+        .map_err(|e| format!("Failed to list sessions: {}", e))
+}
+
+/// A session with the agents, messages, requests and responses attributed to it
+#[tauri::command]
+async fn get_session(state: State<'_, AppState>, session_id: String) -> Result<SessionDetail, String> {
+    let app_state = state.lock().await;
+    app_state.database.get_session_detail(&session_id).await
+        .map_err(|e| format!("Failed to load session: {}", e))?
+        .ok_or_else(|| format!("Session {} not found", session_id))
+}
+
+#[tauri::command]
+async fn end_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    status: String,
+    summary: Option<String>,
+) -> Result<Session, String> {
+    let status = SessionStatus::parse(&status).ok_or_else(|| format!("Unknown session status '{}'", status))?;
+    authorize_control(&*state.lock().await, "end_session", serde_json::json!({
+        "sessionId": session_id,
+        "status": status,
+    })).await?;
+    
+    let ws_server = running_server(&state).await?;
+    ws_server.end_session(&session_id, status, summary, DESKTOP_OPERATOR).await.map_err(|e| e.to_string())
+}
+
+/// Running usage totals for every agent and session seen since the server started
+#[tauri::command]
+async fn get_usage_totals(state: State<'_, AppState>) -> Result<UsageSnapshot, String> {
+    Ok(state.lock().await.usage.snapshot())
+}
+
+/// Individual usage reports, newest first, optionally for one agent or session
+#[tauri::command]
+async fn get_usage_reports(
+    state: State<'_, AppState>,
+    agent_id: Option<String>,
+    session_id: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<UsageReport>, String> {
+    let app_state = state.lock().await;
+    app_state.database.get_usage_reports(agent_id.as_deref(), session_id.as_deref(), limit.unwrap_or(500)).await
+        .map_err(|e| format!("Failed to load usage reports: {}", e))
+}
+
+/// Signed links that answer a pending request, for sharing outside the desktop app
+#[tauri::command]
+async fn get_response_links(state: State<'_, AppState>, request_id: String) -> Result<Option<RequestLinks>, String> {
+    let app_state = state.lock().await;
+    let request = app_state.request_queue.get(&request_id)
+        .ok_or_else(|| format!("Request {} not found", request_id))?;
+    Ok(app_state.response_links.links_for(request))
+}
+
+/// The webhook delivery log, newest first, optionally for one webhook or delivery status
+#[tauri::command]
+async fn get_webhook_deliveries(
+    state: State<'_, AppState>,
+    webhook: Option<String>,
+    status: Option<String>,
+    limit: Option<i64>,
+) -> Result<Vec<WebhookDelivery>, String> {
+    let status = status
+        .map(|s| DeliveryStatus::parse(&s).ok_or_else(|| format!("Unknown delivery status '{}'", s)))
+        .transpose()?;
+    let app_state = state.lock().await;
+    app_state.database.get_webhook_deliveries(webhook.as_deref(), status, limit.unwrap_or(200)).await
+        .map_err(|e| format!("Failed to load webhook deliveries: {}", e))
+}
+
+/// Sends a `ping` event to one webhook and returns the delivery id to look up in the log
+#[tauri::command]
+async fn send_test_webhook(state: State<'_, AppState>, webhook: String) -> Result<String, String> {
+    let app_state = state.lock().await;
+    authorize_control(&app_state, "send_test_webhook", serde_json::json!({ "webhook": webhook })).await?;
+    app_state.webhooks.ping(&webhook)
+        .ok_or_else(|| format!("No webhook named '{}'", webhook))
+}
+
+/// An agent's tool calls as a tree of spans, optionally only those made in one session
+#[tauri::command]
+async fn get_tool_timeline(
+    state: State<'_, AppState>,
+    agent_id: String,
+    session_id: Option<String>,
+) -> Result<Vec<ToolCallNode>, String> {
+    let app_state = state.lock().await;
+    let calls = app_state.database.get_tool_calls(&agent_id, session_id.as_deref()).await
+        .map_err(|e| format!("Failed to load tool calls: {}", e))?;
+    Ok(ToolCallNode::build_timeline(calls))
+}
+
+/// Replaces the hard limits of one agent's or session's budget, unblocking it if it was over
+#[tauri::command]
+async fn raise_budget(
+    state: State<'_, AppState>,
+    scope: BudgetScope,
+    limits: BudgetLimits,
+) -> Result<(), String> {
+    authorize_control(&*state.lock().await, "raise_budget", serde_json::json!({
+        "scope": scope,
+        "limits": limits,
+    })).await?;
+    
+    let ws_server = running_server(&state).await?;
+    ws_server.raise_budget(scope, limits, DESKTOP_OPERATOR).await;
+    Ok(())
+}
+
 #[tauri::command]
 async fn get_human_requests(state: State<'_, AppState>) -> Result<Vec<HumanInputRequest>, String> {
     info!("🔍 get_human_requests command called");
@@ -58,7 +280,7 @@ async fn get_human_requests(state: State<'_, AppState>) -> Result<Vec<HumanInput
         },
         Err(e) => {
             error!("❌ Database query failed: {}, using in-memory data", e);
-            let in_memory_requests = app_state.human_requests.clone();
+            let in_memory_requests = app_state.request_queue.all();
             info!("📋 In-memory requests: {}", in_memory_requests.len());
             Ok(in_memory_requests)
         }
@@ -69,43 +291,104 @@ async fn get_human_requests(state: State<'_, AppState>) -> Result<Vec<HumanInput
 async fn send_human_response(
     state: State<'_, AppState>,
     request_id: String,
-    response: String,
+    response: serde_json::Value,
     additional_context: Option<String>,
 ) -> Result<(), String> {
-    let (agent_id, ws_server) = {
-        let mut app_state = state.lock().await;
-        
-        // Find the request and mark it as completed
-        if let Some(request) = app_state.human_requests.iter_mut().find(|r| r.id == request_id) {
-            request.status = RequestStatus::Completed;
-            let agent_id = request.agent_id.clone();
-            let ws_server = app_state.websocket_server.as_ref().cloned();
-            (Some(agent_id), ws_server)
-        } else {
-            return Err("Request not found".to_string());
-        }
+    let ws_server = running_server(&state).await?;
+    
+    let response_data = HumanResponse {
+        request_id: request_id.clone(),
+        response,
+        additional_context,
+        responded_by: DESKTOP_OPERATOR.to_string(),
+        timestamp: chrono::Utc::now(),
+        source: ResponseSource::Operator,
     };
     
-    // Send response through WebSocket if server is running
-    if let (Some(agent_id), Some(ws_server)) = (agent_id, ws_server) {
-        let response_data = HumanResponse {
-            request_id: request_id.clone(),
-            response,
-            additional_context,
-            responded_by: "human".to_string(),
-            timestamp: chrono::Utc::now(),
-        };
-        
-        if let Err(e) = ws_server.send_response_to_agent(&agent_id, response_data).await {
-            error!("Failed to send response to agent: {}", e);
-            return Err(format!("Failed to send response: {}", e));
-        }
+    if let Err(e) = ws_server.send_human_response(response_data).await {
+        error!("Failed to send response to agent: {}", e);
+        return Err(format!("Failed to send response: {}", e));
     }
     
     info!("Human response sent for request: {}", request_id);
     Ok(())
 }
 
+#[tauri::command]
+async fn claim_request(
+    state: State<'_, AppState>,
+    request_id: String,
+) -> Result<HumanInputRequest, String> {
+    let ws_server = running_server(&state).await?;
+    ws_server.claim_request(&request_id, DESKTOP_OPERATOR).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn release_request(
+    state: State<'_, AppState>,
+    request_id: String,
+) -> Result<HumanInputRequest, String> {
+    let ws_server = running_server(&state).await?;
+    ws_server.release_request(&request_id, DESKTOP_OPERATOR).await.map_err(|e| e.to_string())
+}
+
+/// Sends a pause, resume, stop or set-verbosity command to a connected agent
+#[tauri::command]
+async fn send_agent_control(
+    state: State<'_, AppState>,
+    agent_id: String,
+    command: ControlCommand,
+) -> Result<AgentControl, String> {
+    let ws_server = running_server(&state).await?;
+    ws_server.send_agent_control(&agent_id, command, DESKTOP_OPERATOR).await.map_err(|e| e.to_string())
+}
+
+/// Sends an instruction to the given agents and/or every connected agent with `tag`
+#[tauri::command]
+async fn send_operator_message(
+    state: State<'_, AppState>,
+    agent_ids: Option<Vec<String>>,
+    tag: Option<String>,
+    message: String,
+    data: Option<serde_json::Value>,
+) -> Result<Vec<AgentMessage>, String> {
+    let ws_server = running_server(&state).await?;
+    ws_server.send_operator_message(
+        &agent_ids.unwrap_or_default(),
+        tag.as_deref(),
+        &message,
+        data.unwrap_or(serde_json::Value::Null),
+        DESKTOP_OPERATOR,
+    ).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_pending_controls(state: State<'_, AppState>) -> Result<Vec<AgentControl>, String> {
+    let app_state = state.lock().await;
+    Ok(app_state.pending_controls.values().cloned().collect())
+}
+
+#[tauri::command]
+async fn get_operators(state: State<'_, AppState>) -> Result<Vec<Operator>, String> {
+    let ws_server = running_server(&state).await?;
+    Ok(ws_server.operators().await)
+}
+
+/// Pending requests in priority order, with remaining time and escalation state
+#[tauri::command]
+async fn get_request_queue(state: State<'_, AppState>) -> Result<Vec<QueueEntry>, String> {
+    let app_state = state.lock().await;
+    Ok(app_state.request_queue.snapshot(chrono::Utc::now(), &app_state.config.queue))
+}
+
+/// Token the app's webview registers with to act as the desktop operator. Only the webview
+/// can call commands, so other clients on the WebSocket port never see it.
+#[tauri::command]
+async fn get_desktop_token(state: State<'_, AppState>) -> Result<String, String> {
+    let app_state = state.lock().await;
+    Ok(app_state.access_control.desktop_token().to_string())
+}
+
 #[tauri::command]
 async fn get_websocket_port(state: State<'_, AppState>) -> Result<u16, String> {
     let app_state = state.lock().await;
@@ -116,21 +399,292 @@ async fn get_websocket_port(state: State<'_, AppState>) -> Result<u16, String> {
     }
 }
 
+#[tauri::command]
+async fn export_session(
+    state: State<'_, AppState>,
+    path: String,
+    filter: Option<ExportFilter>,
+    label: Option<String>,
+) -> Result<usize, String> {
+    let filter = filter.unwrap_or_default();
+    let records = {
+        let app_state = state.lock().await;
+        app_state.database.collect_export_records(&filter).await
+            .map_err(|e| format!("Failed to collect session records: {}", e))?
+    };
+    
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_FORMAT_VERSION,
+        exported_at: chrono::Utc::now(),
+        label,
+        filter,
+    };
+    
+    let file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let count = SessionArchive::write(&mut writer, header, &records)
+        .map_err(|e| format!("Failed to write session archive: {}", e))?;
+    
+    info!("Exported {} records to {}", count, path);
+    Ok(count)
+}
+
+#[tauri::command]
+async fn import_session(state: State<'_, AppState>, path: String) -> Result<SessionArchiveInfo, String> {
+    let file = std::fs::File::open(&path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let (header, records) = SessionArchive::read(std::io::BufReader::new(file))
+        .map_err(|e| format!("Failed to read session archive: {}", e))?;
+    
+    let app_state = state.lock().await;
+    app_state.database.import_archive(&path, &header, &records).await
+        .map_err(|e| format!("Failed to import session archive: {}", e))
+}
+
+#[tauri::command]
+async fn list_imported_sessions(state: State<'_, AppState>) -> Result<Vec<SessionArchiveInfo>, String> {
+    let app_state = state.lock().await;
+    app_state.database.list_session_archives().await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_imported_session(state: State<'_, AppState>, archive_id: String) -> Result<ArchivedSession, String> {
+    let app_state = state.lock().await;
+    app_state.database.get_archived_session(&archive_id).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_imported_session(
+    state: State<'_, AppState>,
+    archive_id: String,
+) -> Result<(), String> {
+    let app_state = state.lock().await;
+    authorize_control(&app_state, "delete_imported_session", serde_json::json!({
+        "archiveId": archive_id,
+    })).await?;
+    app_state.database.delete_session_archive(&archive_id).await
+        .map_err(|e| e.to_string())
+}
+
+/// Replays an imported session, or past live traffic matching `filter`, to all GUI clients
+#[tauri::command]
+async fn start_replay(
+    state: State<'_, AppState>,
+    archive_id: Option<String>,
+    filter: Option<ExportFilter>,
+    speed: Option<f64>,
+) -> Result<ReplayStatus, String> {
+    let mut app_state = state.lock().await;
+    authorize_control(&app_state, "start_replay", serde_json::json!({
+        "archiveId": archive_id,
+        "filter": filter,
+        "speed": speed,
+    })).await?;
+    let ws_server = app_state.websocket_server.clone()
+        .ok_or_else(|| "WebSocket server not running".to_string())?;
+    
+    let (source, records) = match archive_id {
+        Some(archive_id) => {
+            let records = app_state.database.get_archive_records(&archive_id).await
+                .map_err(|e| e.to_string())?;
+            (format!("archive:{}", archive_id), records)
+        }
+        None => {
+            let records = app_state.database.collect_export_records(&filter.unwrap_or_default()).await
+                .map_err(|e| e.to_string())?;
+            ("live".to_string(), records)
+        }
+    };
+    
+    if let Some(previous) = app_state.replay.take() {
+        previous.stop().await;
+    }
+    
+    let replay = ReplaySession::start(source, records, speed.unwrap_or(1.0), ws_server)
+        .map_err(|e| e.to_string())?;
+    let status = replay.status().await;
+    app_state.replay = Some(replay);
+    
+    Ok(status)
+}
+
+#[tauri::command]
+async fn control_replay(
+    state: State<'_, AppState>,
+    action: String,
+    position_ms: Option<u64>,
+    speed: Option<f64>,
+) -> Result<ReplayStatus, String> {
+    let replay = {
+        let app_state = state.lock().await;
+        authorize_control(&app_state, "control_replay", serde_json::json!({
+            "action": action,
+            "positionMs": position_ms,
+            "speed": speed,
+        })).await?;
+        app_state.replay.clone().ok_or_else(|| "No replay in progress".to_string())?
+    };
+    
+    match action.as_str() {
+        "pause" => replay.pause().await,
+        "resume" => replay.resume().await,
+        "stop" => replay.stop().await,
+        "seek" => replay.seek(position_ms.ok_or_else(|| "Missing position_ms".to_string())?).await,
+        "speed" => replay.set_speed(speed.ok_or_else(|| "Missing speed".to_string())?).await
+            .map_err(|e| e.to_string())?,
+        _ => return Err(format!("Unknown replay action: {}", action)),
+    }
+    
+    Ok(replay.status().await)
+}
+
+#[tauri::command]
+async fn get_replay_status(state: State<'_, AppState>) -> Result<Option<ReplayStatus>, String> {
+    let replay = state.lock().await.replay.clone();
+    match replay {
+        Some(replay) => Ok(Some(replay.status().await)),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+async fn get_policy_rules(state: State<'_, AppState>) -> Result<Vec<PolicyRule>, String> {
+    let app_state = state.lock().await;
+    Ok(app_state.policy_engine.rules())
+}
+
+/// Re-reads the config file, keeping the current settings if it is invalid
+#[tauri::command]
+async fn reload_config(state: State<'_, AppState>) -> Result<(), String> {
+    let desktop_token = {
+        let app_state = state.lock().await;
+        authorize_control(&app_state, "reload_config", serde_json::Value::Null).await?;
+        app_state.access_control.desktop_token().to_string()
+    };
+    
+    let config = HudConfig::load().map_err(|e| e.to_string())?;
+    let policy_engine = PolicyEngine::new(config.policies.clone()).map_err(|e| e.to_string())?;
+    let priority_classifier = PriorityClassifier::new(config.priority.clone()).map_err(|e| e.to_string())?;
+    let access_control = AccessControl::new(config.access.clone(), desktop_token).map_err(|e| e.to_string())?;
+    config.response_links.check_access(&access_control)?;
+    // Replacing the exporter stops the old one once it has flushed its buffered spans
+    let telemetry = TraceExporter::start(&config.telemetry);
+    
+    let mut app_state = state.lock().await;
+    app_state.telemetry = telemetry;
+    app_state.webhooks = WebhookDispatcher::new(config.webhooks.clone(), app_state.database.clone());
+    app_state.response_links.set_config(config.response_links.clone());
+    app_state.config = config;
+    app_state.policy_engine = policy_engine;
+    app_state.priority_classifier = priority_classifier;
+    app_state.access_control = access_control;
+    
+    info!("Configuration reloaded");
+    Ok(())
+}
+
+/// Checks the audit log's hash chain for modified, inserted or deleted rows. The log lives
+/// in the in-memory database, so it only covers the current run of the app.
+#[tauri::command]
+async fn verify_audit_log(state: State<'_, AppState>) -> Result<AuditVerification, String> {
+    let app_state = state.lock().await;
+    let entries = app_state.database.get_audit_entries().await
+        .map_err(|e| format!("Failed to read audit log: {}", e))?;
+    let head = app_state.database.get_audit_head().await
+        .map_err(|e| format!("Failed to read audit log head: {}", e))?;
+    Ok(audit::verify_chain(&entries, head.as_ref()))
+}
+
+/// Writes the audit chain to `path` as JSON Lines, one entry per line
+#[tauri::command]
+async fn export_audit_log(state: State<'_, AppState>, path: String) -> Result<usize, String> {
+    let entries = {
+        let app_state = state.lock().await;
+        app_state.database.get_audit_entries().await
+            .map_err(|e| format!("Failed to read audit log: {}", e))?
+    };
+    
+    let file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let count = audit::write_jsonl(&mut writer, &entries)
+        .map_err(|e| format!("Failed to write audit log: {}", e))?;
+    
+    info!("Exported {} audit entries to {}", count, path);
+    Ok(count)
+}
+
+/// Sends a single span straight to the configured collector and reports whether it was
+/// accepted, so the endpoint can be checked before export is enabled
+#[tauri::command]
+async fn send_test_trace(state: State<'_, AppState>) -> Result<String, String> {
+    let config = {
+        let app_state = state.lock().await;
+        authorize_control(&app_state, "send_test_trace", serde_json::Value::Null).await?;
+        app_state.config.telemetry.clone()
+    };
+    
+    let now = chrono::Utc::now();
+    let span = TraceSpan {
+        trace_id: uuid::Uuid::new_v4().simple().to_string(),
+        span_id: uuid::Uuid::new_v4().simple().to_string()[..16].to_string(),
+        parent_span_id: None,
+        name: "test trace".to_string(),
+        start: now,
+        end: now,
+        attributes: Vec::new(),
+        events: Vec::new(),
+        error: None,
+    };
+    
+    let client = reqwest::Client::new();
+    telemetry::send(&client, &config, &[span]).await
+        .map_err(|e| format!("Failed to export to {}: {}", config.endpoint, e))?;
+    Ok(format!("Test span accepted by {}", config.endpoint))
+}
+
 #[tauri::command]
 async fn test_connection() -> Result<String, String> {
     Ok("Connection test successful!".to_string())
 }
 
 async fn setup_app_state() -> Result<AppState, Box<dyn std::error::Error>> {
+    // Load configuration and policy rules
+    let config = HudConfig::load()?;
+    let policy_engine = PolicyEngine::new(config.policies.clone())?;
+    let priority_classifier = PriorityClassifier::new(config.priority.clone())?;
+    // The desktop webview registers as the desktop operator with this token
+    let access_control = AccessControl::new(config.access.clone(), uuid::Uuid::new_v4().simple().to_string())?;
+    config.response_links.check_access(&access_control)?;
+    let telemetry = TraceExporter::start(&config.telemetry);
+    
     // Initialize database
     let database = Database::new().await?;
+    let webhooks = WebhookDispatcher::new(config.webhooks.clone(), database.clone());
+    let response_links = ResponseLinks::new(config.response_links.clone());
     
     // Create initial app state
     let app_state = Arc::new(Mutex::new(AppStateInner {
         websocket_server: None,
         database,
         connected_agents: Vec::new(),
-        human_requests: Vec::new(),
+        request_queue: RequestQueue::new(),
+        replay: None,
+        config,
+        policy_engine,
+        priority_classifier,
+        access_control,
+        pending_controls: HashMap::new(),
+        usage: UsageTracker::new(),
+        budgets: BudgetTracker::new(),
+        telemetry,
+        webhooks,
+        response_links,
+        started_at: chrono::Utc::now(),
     }));
     
     // Start WebSocket server
@@ -169,9 +723,41 @@ async fn main() {
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             get_agents,
+            get_agent_tree,
+            get_sessions,
+            get_session,
+            end_session,
+            get_usage_totals,
+            get_usage_reports,
+            raise_budget,
+            get_tool_timeline,
             get_human_requests,
             send_human_response,
+            get_request_queue,
+            claim_request,
+            release_request,
+            get_operators,
+            send_agent_control,
+            get_pending_controls,
+            send_operator_message,
             get_websocket_port,
+            get_desktop_token,
+            export_session,
+            import_session,
+            list_imported_sessions,
+            get_imported_session,
+            delete_imported_session,
+            start_replay,
+            control_replay,
+            get_replay_status,
+            verify_audit_log,
+            export_audit_log,
+            get_policy_rules,
+            reload_config,
+            send_test_trace,
+            get_webhook_deliveries,
+            send_test_webhook,
+            get_response_links,
             test_connection
         ])
         .setup(|app| {
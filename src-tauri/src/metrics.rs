@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::models::RequestPriority;
+
+/// Counters updated as the server runs, served in the Prometheus text format on `/metrics`.
+/// Gauges are read from the server state at scrape time instead.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds, in seconds, of the response latency buckets
+const LATENCY_BUCKETS: [f64; 11] = [1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0, 14400.0];
+
+// Agents choose message types, so cap how many distinct ones get their own series
+const MAX_LABELS: usize = 64;
+
+struct LabeledCounter(Mutex<BTreeMap<String, u64>>);
+
+impl LabeledCounter {
+    const fn new() -> Self {
+        LabeledCounter(Mutex::new(BTreeMap::new()))
+    }
+
+    fn inc(&self, label: &str) {
+        let mut values = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let label = if values.contains_key(label) || values.len() < MAX_LABELS { label } else { "other" };
+        *values.entry(label.to_string()).or_default() += 1;
+    }
+
+    fn values(&self) -> BTreeMap<String, u64> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn observe_ms(&self, ms: u64) {
+        let seconds = ms as f64 / 1000.0;
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+}
+
+pub struct Metrics {
+    messages: LabeledCounter,
+    content_emissions: LabeledCounter,
+    response_latency: Histogram,
+    timeouts: AtomicU64,
+    auto_approvals: LabeledCounter,
+    dropped_messages: AtomicU64,
+}
+
+/// Values read from the server state when `/metrics` is scraped
+#[derive(Debug, Default)]
+pub struct Gauges {
+    pub agents: usize,
+    pub guis: usize,
+    pub pending_by_priority: BTreeMap<RequestPriority, usize>,
+    /// Pending requests an operator is working on
+    pub claimed_requests: usize,
+    pub pending_controls: usize,
+    pub active_sessions: usize,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            messages: LabeledCounter::new(),
+            content_emissions: LabeledCounter::new(),
+            response_latency: Histogram::new(),
+            timeouts: AtomicU64::new(0),
+            auto_approvals: LabeledCounter::new(),
+            dropped_messages: AtomicU64::new(0),
+        }
+    }
+
+    /// A message received from an agent or GUI
+    pub fn message_received(&self, message_type: &str) {
+        self.messages.inc(message_type);
+    }
+
+    pub fn content_emitted(&self, content_type: &str) {
+        self.content_emissions.inc(content_type);
+    }
+
+    /// Time from a request being raised until a human answered it
+    pub fn request_answered(&self, latency_ms: u64) {
+        self.response_latency.observe_ms(latency_ms);
+    }
+
+    pub fn request_timed_out(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An approval given without a human, by a policy rule or an on_timeout policy
+    pub fn auto_approved(&self, source: &str) {
+        self.auto_approvals.inc(source);
+    }
+
+    /// A message that couldn't be handed to its client because it had disconnected
+    pub fn message_dropped(&self) {
+        self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+
+        gauge(&mut out, "agent_hud_connected_agents", "Agents connected over WebSocket", gauges.agents);
+        gauge(&mut out, "agent_hud_connected_guis", "GUI clients connected over WebSocket", gauges.guis);
+        gauge(&mut out, "agent_hud_active_sessions", "Sessions that haven't ended", gauges.active_sessions);
+
+        header(&mut out, "agent_hud_pending_requests", "Human input requests awaiting an answer", "gauge");
+        for priority in [RequestPriority::Low, RequestPriority::Medium, RequestPriority::High, RequestPriority::Critical] {
+            let count = gauges.pending_by_priority.get(&priority).copied().unwrap_or(0);
+            let _ = writeln!(out, "agent_hud_pending_requests{{priority=\"{}\"}} {}", priority, count);
+        }
+
+        let pending: usize = gauges.pending_by_priority.values().sum();
+        header(&mut out, "agent_hud_request_queue_depth", "Pending requests by whether an operator has claimed them", "gauge");
+        let _ = writeln!(out, "agent_hud_request_queue_depth{{state=\"unclaimed\"}} {}", pending.saturating_sub(gauges.claimed_requests));
+        let _ = writeln!(out, "agent_hud_request_queue_depth{{state=\"claimed\"}} {}", gauges.claimed_requests);
+        gauge(&mut out, "agent_hud_pending_controls", "Control commands sent to agents and not yet acknowledged", gauges.pending_controls);
+
+        labeled_counter(&mut out, "agent_hud_messages_received_total", "Messages received by type", "type", &self.messages);
+        labeled_counter(&mut out, "agent_hud_content_emissions_total", "Rich content emitted by agents by type", "type", &self.content_emissions);
+        labeled_counter(&mut out, "agent_hud_auto_approvals_total", "Requests approved without a human", "source", &self.auto_approvals);
+        counter(&mut out, "agent_hud_request_timeouts_total", "Requests that reached their timeout", self.timeouts.load(Ordering::Relaxed));
+        counter(&mut out, "agent_hud_dropped_messages_total", "Messages that couldn't be delivered to a client", self.dropped_messages.load(Ordering::Relaxed));
+
+        let latency = &self.response_latency;
+        header(&mut out, "agent_hud_request_response_seconds", "Time from a request being raised until a human answered it", "histogram");
+        for (bucket, bound) in latency.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(out, "agent_hud_request_response_seconds_bucket{{le=\"{}\"}} {}", bound, bucket.load(Ordering::Relaxed));
+        }
+        let count = latency.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "agent_hud_request_response_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        let _ = writeln!(out, "agent_hud_request_response_seconds_sum {}", latency.sum_ms.load(Ordering::Relaxed) as f64 / 1000.0);
+        let _ = writeln!(out, "agent_hud_request_response_seconds_count {}", count);
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn labeled_counter(out: &mut String, name: &str, help: &str, label: &str, counter: &LabeledCounter) {
+    header(out, name, help, "counter");
+    for (value, count) in counter.values() {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, count);
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::access::Role;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Agent {
@@ -9,15 +12,78 @@ pub struct Agent {
     pub connected_at: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
     pub metadata: Option<serde_json::Value>,
+    /// The agent that spawned this one, for sub-agents
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The run (orchestrator session) the agent belongs to
+    #[serde(default)]
+    pub run_id: Option<String>,
+}
+
+/// An agent with the sub-agents it spawned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentNode {
+    pub agent: Agent,
+    pub children: Vec<AgentNode>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AgentStatus {
     Connected,
     Active,
+    /// Acknowledged a pause command
+    Paused,
+    /// Acknowledged a stop command and is shutting down
+    Stopping,
     Disconnected,
 }
 
+/// A command an operator sends to a running agent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    Stop,
+    SetVerbosity { level: String },
+}
+
+impl ControlCommand {
+    /// The status an agent moves to once it acknowledges the command
+    pub fn resulting_status(&self) -> Option<AgentStatus> {
+        match self {
+            ControlCommand::Pause => Some(AgentStatus::Paused),
+            ControlCommand::Resume => Some(AgentStatus::Active),
+            ControlCommand::Stop => Some(AgentStatus::Stopping),
+            ControlCommand::SetVerbosity { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentControl {
+    pub id: String,
+    pub agent_id: String,
+    #[serde(flatten)]
+    pub command: ControlCommand,
+    pub issued_by: String,
+    pub issued_at: DateTime<Utc>,
+    pub state: ControlState,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    /// Why the agent could not carry out the command
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ControlState {
+    /// Sent, waiting for the agent to acknowledge
+    Pending,
+    Acknowledged,
+    Failed,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentMessage {
     pub id: String,
@@ -25,6 +91,26 @@ pub struct AgentMessage {
     pub message_type: String,
     pub payload: serde_json::Value,
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub direction: MessageDirection,
+    /// The operator who sent an outbound message
+    #[serde(default)]
+    pub sender: Option<String>,
+    /// When the agent acknowledged an outbound message
+    #[serde(default)]
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// The session the agent was in when the message was sent
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum MessageDirection {
+    /// Sent by the agent to the HUD
+    #[default]
+    Inbound,
+    /// Sent by an operator to the agent
+    Outbound,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,21 +120,145 @@ pub struct HumanInputRequest {
     pub agent_name: String,
     pub request_type: RequestType,
     pub message: String,
-    pub options: Vec<String>,
+    pub options: Vec<RequestOption>,
     pub context: Option<serde_json::Value>,
+    /// JSON Schema describing the fields of a `Form` request
+    #[serde(default)]
+    pub schema: Option<serde_json::Value>,
+    /// Selection bounds for `MultiChoice` requests
+    #[serde(default)]
+    pub min_selections: Option<u32>,
+    #[serde(default)]
+    pub max_selections: Option<u32>,
     pub timeout_seconds: u32,
+    /// What to do when nobody answers within `timeout_seconds`
+    #[serde(default)]
+    pub on_timeout: Option<TimeoutPolicy>,
     pub timestamp: DateTime<Utc>,
     pub status: RequestStatus,
     pub priority: RequestPriority,
+    /// Why the request got its priority, for operators
+    #[serde(default)]
+    pub priority_reason: Option<String>,
+    /// The operator currently working on the request; others can't answer it until released
+    #[serde(default)]
+    pub claimed_by: Option<RequestClaim>,
+    /// Number of distinct operators who must approve before the agent gets an answer
+    #[serde(default)]
+    pub required_approvers: Option<u32>,
+    /// Approvals and denials given so far on a request that needs several approvers
+    #[serde(default)]
+    pub approvals: Vec<Approval>,
+    /// The session the agent was in when it asked
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    pub operator: String,
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestClaim {
+    pub operator: String,
+    /// The GUI connection holding the claim, if it was made over the WebSocket
+    pub client_id: Option<String>,
+    pub claimed_at: DateTime<Utc>,
+}
+
+/// A person answering requests, identified when their GUI registers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operator {
+    pub client_id: String,
+    pub name: String,
+    pub role: Role,
+    pub connected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum TimeoutPolicy {
+    /// Answer with `value`, or the recommended option when no value is given
+    Default { value: Option<serde_json::Value> },
+    Deny,
+    /// Only allowed for low priority requests
+    Approve,
+    /// Raise the priority and give humans another timeout window
+    Escalate,
+}
+
+/// One answer a human can pick. Agents may send a bare string, which becomes both id and label.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawRequestOption")]
+pub struct RequestOption {
+    pub id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub recommended: bool,
+    pub preview: Option<OptionPreview>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptionPreview {
+    /// How to render the content, e.g. "diff", "markdown" or "code"
+    pub format: String,
+    pub content: String,
+    pub language: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRequestOption {
+    Label(String),
+    Detailed {
+        id: Option<String>,
+        label: Option<String>,
+        description: Option<String>,
+        #[serde(default, alias = "default")]
+        recommended: bool,
+        preview: Option<OptionPreview>,
+    },
+}
+
+impl TryFrom<RawRequestOption> for RequestOption {
+    type Error = String;
+
+    fn try_from(raw: RawRequestOption) -> Result<Self, Self::Error> {
+        match raw {
+            RawRequestOption::Label(label) => Ok(RequestOption {
+                id: label.clone(),
+                label,
+                description: None,
+                recommended: false,
+                preview: None,
+            }),
+            RawRequestOption::Detailed { id, label, description, recommended, preview } => {
+                let (id, label) = match (id, label) {
+                    (Some(id), Some(label)) => (id, label),
+                    (Some(id), None) => (id.clone(), id),
+                    (None, Some(label)) => (label.clone(), label),
+                    (None, None) => return Err("option needs an id or a label".to_string()),
+                };
+                Ok(RequestOption { id, label, description, recommended, preview })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum RequestType {
     Input,
     Approval,
     Choice,
     Confirmation,
     Text,
+    Form,
+    MultiChoice,
+    Ranking,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,9 +266,11 @@ pub enum RequestStatus {
     Pending,
     Completed,
     Timeout,
+    /// Withdrawn by the agent before anyone answered
+    Cancelled,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RequestPriority {
     Low,
     Medium,
@@ -69,10 +281,143 @@ pub enum RequestPriority {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HumanResponse {
     pub request_id: String,
-    pub response: String,
+    pub response: serde_json::Value,
     pub additional_context: Option<String>,
     pub responded_by: String,
     pub timestamp: DateTime<Utc>,
+    /// Set by the server only; never read from clients, whatever name they respond under
+    #[serde(default, skip_deserializing)]
+    pub source: ResponseSource,
+}
+
+/// What produced a response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseSource {
+    #[default]
+    Operator,
+    /// A policy rule, recorded as `policy:<rule id>`
+    Policy,
+    /// An `on_timeout` policy, recorded as `system:timeout`
+    Timeout,
+}
+
+/// Prefixes of the names the server records automated actions under. Operators can't
+/// take names starting with them.
+pub const RESERVED_NAME_PREFIXES: [&str; 2] = ["system:", "policy:"];
+
+pub fn is_reserved_name(name: &str) -> bool {
+    let name = name.trim().to_lowercase();
+    RESERVED_NAME_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+impl HumanResponse {
+    /// Whether the response was synthesized by the server rather than given by an operator
+    pub fn is_automated(&self) -> bool {
+        self.source != ResponseSource::Operator
+    }
+}
+
+impl ResponseSource {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "operator" => Some(ResponseSource::Operator),
+            "policy" => Some(ResponseSource::Policy),
+            "timeout" => Some(ResponseSource::Timeout),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ResponseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseSource::Operator => write!(f, "operator"),
+            ResponseSource::Policy => write!(f, "policy"),
+            ResponseSource::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// A run of work shared by one or more agents. An agent starts or joins a session and
+/// everything it sends afterwards is attributed to it; the session id doubles as the
+/// agents' `run_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub name: Option<String>,
+    /// The agent that started the session
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub status: SessionStatus,
+    /// What the session accomplished, given when it ends
+    pub summary: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Active,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl SessionStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "active" => Some(SessionStatus::Active),
+            "completed" => Some(SessionStatus::Completed),
+            "failed" => Some(SessionStatus::Failed),
+            "cancelled" => Some(SessionStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// A session together with everything attributed to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDetail {
+    pub session: Session,
+    pub agents: Vec<Agent>,
+    pub messages: Vec<AgentMessage>,
+    pub human_requests: Vec<HumanInputRequest>,
+    pub human_responses: Vec<HumanResponse>,
+}
+
+/// One tool invocation reported by an agent. Calls made while another call is running
+/// name it as their parent, so an agent's calls form a tree of spans.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub agent_id: String,
+    pub session_id: Option<String>,
+    pub tool_name: String,
+    pub arguments: Option<serde_json::Value>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub status: ToolCallStatus,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolCallStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A tool call with the calls made inside it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallNode {
+    pub call: ToolCall,
+    pub children: Vec<ToolCallNode>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,21 +428,241 @@ pub struct WebSocketMessage {
     pub timestamp: DateTime<Utc>,
 }
 
-impl RequestPriority {
-    pub fn from_request_type_and_message(request_type: &RequestType, message: &str) -> Self {
-        match request_type {
-            RequestType::Approval | RequestType::Confirmation => RequestPriority::High,
-            _ => {
-                let message_lower = message.to_lowercase();
-                if message_lower.contains("critical") || message_lower.contains("urgent") {
-                    RequestPriority::Critical
-                } else if message_lower.contains("optional") || message_lower.contains("suggestion") {
-                    RequestPriority::Low
-                } else {
-                    RequestPriority::Medium
-                }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionArchiveInfo {
+    pub id: String,
+    pub label: String,
+    pub source_path: String,
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub imported_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub record_count: u32,
+}
+
+/// A read-only session loaded from an exported JSONL file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub info: SessionArchiveInfo,
+    pub agents: Vec<Agent>,
+    pub messages: Vec<AgentMessage>,
+    pub human_requests: Vec<HumanInputRequest>,
+    pub human_responses: Vec<HumanResponse>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportFilter {
+    pub agent_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ReplayState {
+    Playing,
+    Paused,
+    Finished,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayStatus {
+    pub id: String,
+    pub source: String,
+    pub state: ReplayState,
+    pub speed: f64,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub records_emitted: usize,
+    pub total_records: usize,
+}
+
+impl AgentNode {
+    /// Arranges agents into trees by `parent_id`. Agents whose parent is not in `agents`
+    /// become roots.
+    pub fn build_forest(agents: Vec<Agent>) -> Vec<AgentNode> {
+        let ids: HashSet<String> = agents.iter().map(|a| a.id.clone()).collect();
+        let mut children: HashMap<String, Vec<Agent>> = HashMap::new();
+        let mut roots = Vec::new();
+        
+        for agent in agents {
+            match agent.parent_id.clone().filter(|p| ids.contains(p) && *p != agent.id) {
+                Some(parent_id) => children.entry(parent_id).or_default().push(agent),
+                None => roots.push(agent),
+            }
+        }
+        
+        // Agents caught in a parent cycle are never reached from a root and are left out
+        roots.into_iter()
+            .map(|agent| Self::attach(agent, &mut children))
+            .collect()
+    }
+    
+    fn attach(agent: Agent, children: &mut HashMap<String, Vec<Agent>>) -> AgentNode {
+        let direct = children.remove(&agent.id).unwrap_or_default();
+        AgentNode {
+            children: direct.into_iter().map(|child| Self::attach(child, children)).collect(),
+            agent,
+        }
+    }
+    
+    /// Keeps the subtrees rooted at agents carrying `tag`, so a tagged agent brings its
+    /// descendants along
+    pub fn filter_by_tag(forest: Vec<AgentNode>, tag: &str) -> Vec<AgentNode> {
+        let mut kept = Vec::new();
+        for node in forest {
+            if node.agent.tags.iter().any(|t| t == tag) {
+                kept.push(node);
+            } else {
+                kept.extend(Self::filter_by_tag(node.children, tag));
             }
         }
+        kept
+    }
+}
+
+impl ToolCallNode {
+    /// Arranges tool calls into trees by `parent_span_id`, each level in start order. Calls
+    /// whose parent is not in `calls` become roots.
+    pub fn build_timeline(mut calls: Vec<ToolCall>) -> Vec<ToolCallNode> {
+        calls.sort_by_key(|c| c.started_at);
+        let ids: HashSet<String> = calls.iter().map(|c| c.span_id.clone()).collect();
+        let mut children: HashMap<String, Vec<ToolCall>> = HashMap::new();
+        let mut roots = Vec::new();
+        
+        for call in calls {
+            match call.parent_span_id.clone().filter(|p| ids.contains(p) && *p != call.span_id) {
+                Some(parent_id) => children.entry(parent_id).or_default().push(call),
+                None => roots.push(call),
+            }
+        }
+        
+        roots.into_iter()
+            .map(|call| Self::attach(call, &mut children))
+            .collect()
+    }
+    
+    fn attach(call: ToolCall, children: &mut HashMap<String, Vec<ToolCall>>) -> ToolCallNode {
+        let direct = children.remove(&call.span_id).unwrap_or_default();
+        ToolCallNode {
+            children: direct.into_iter().map(|child| Self::attach(child, children)).collect(),
+            call,
+        }
+    }
+}
+
+impl HumanInputRequest {
+    pub fn deadline(&self) -> DateTime<Utc> {
+        self.timestamp + chrono::Duration::seconds(self.timeout_seconds as i64)
+    }
+    
+    /// How many distinct approvals the request needs; 1 for ordinary requests
+    pub fn quorum(&self) -> u32 {
+        self.required_approvers.unwrap_or(1).max(1)
+    }
+    
+    /// Only yes/no requests can be signed off by several operators
+    pub fn supports_quorum(&self) -> bool {
+        matches!(self.request_type, RequestType::Approval | RequestType::Confirmation)
+    }
+    
+    /// Operators who have approved so far, in the order they voted
+    pub fn approvers(&self) -> Vec<&Approval> {
+        self.approvals.iter().filter(|a| a.approved).collect()
+    }
+    
+    /// Whether a validated answer approves (`Some(true)`) or denies (`Some(false)`) the request
+    pub fn decision_for(&self, answer: &serde_json::Value) -> Option<bool> {
+        if *answer == self.approval_answer() {
+            return Some(true);
+        }
+        if *answer == self.denial_answer() {
+            return Some(false);
+        }
+        
+        match answer {
+            serde_json::Value::Bool(approved) => Some(*approved),
+            serde_json::Value::String(text) => match text.to_lowercase().as_str() {
+                "approve" | "approved" | "yes" | "y" | "confirm" | "ok" => Some(true),
+                "reject" | "rejected" | "deny" | "denied" | "no" | "n" | "cancel" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    
+    /// The answer that approves this request: its approve-like option, or "approved"
+    pub fn approval_answer(&self) -> serde_json::Value {
+        self.option_or(&["approve", "yes", "confirm", "ok"], "approved")
+    }
+    
+    /// The answer that denies this request: its reject-like option, or "denied"
+    pub fn denial_answer(&self) -> serde_json::Value {
+        self.option_or(&["reject", "deny", "no", "cancel"], "denied")
+    }
+    
+    fn option_or(&self, words: &[&str], fallback: &str) -> serde_json::Value {
+        self.options.iter()
+            .find(|o| words.contains(&o.id.to_lowercase().as_str()) || words.contains(&o.label.to_lowercase().as_str()))
+            .map(|o| serde_json::Value::from(o.id.clone()))
+            .unwrap_or_else(|| serde_json::Value::from(fallback))
+    }
+}
+
+impl TimeoutPolicy {
+    /// The policy to apply when `request` times out. Auto-approval is turned into a denial once
+    /// the request is no longer low priority or needs several approvers.
+    pub fn effective_for(request: &HumanInputRequest) -> Option<TimeoutPolicy> {
+        match &request.on_timeout {
+            Some(TimeoutPolicy::Approve) if request.priority != RequestPriority::Low || request.quorum() > 1 => {
+                Some(TimeoutPolicy::Deny)
+            }
+            policy => policy.clone(),
+        }
+    }
+    
+    /// Answer to deliver when the request times out, or `None` if there is nothing sensible to send
+    pub fn answer_for(&self, request: &HumanInputRequest) -> Option<serde_json::Value> {
+        match self {
+            TimeoutPolicy::Default { value: Some(value) } => Some(value.clone()),
+            TimeoutPolicy::Default { value: None } => request.options.iter()
+                .find(|o| o.recommended)
+                .map(|o| serde_json::Value::from(o.id.clone())),
+            TimeoutPolicy::Deny => Some(request.denial_answer()),
+            TimeoutPolicy::Approve => Some(request.approval_answer()),
+            TimeoutPolicy::Escalate => None,
+        }
+    }
+}
+
+impl RequestPriority {
+    /// The next priority up, saturating at `Critical`
+    pub fn escalated(self) -> Self {
+        match self {
+            RequestPriority::Low => RequestPriority::Medium,
+            RequestPriority::Medium => RequestPriority::High,
+            RequestPriority::High | RequestPriority::Critical => RequestPriority::Critical,
+        }
+    }
+    
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "low" => Some(RequestPriority::Low),
+            "medium" => Some(RequestPriority::Medium),
+            "high" => Some(RequestPriority::High),
+            "critical" => Some(RequestPriority::Critical),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MessageDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageDirection::Inbound => write!(f, "inbound"),
+            MessageDirection::Outbound => write!(f, "outbound"),
+        }
     }
 }
 
@@ -106,6 +671,8 @@ impl std::fmt::Display for AgentStatus {
         match self {
             AgentStatus::Connected => write!(f, "connected"),
             AgentStatus::Active => write!(f, "active"),
+            AgentStatus::Paused => write!(f, "paused"),
+            AgentStatus::Stopping => write!(f, "stopping"),
             AgentStatus::Disconnected => write!(f, "disconnected"),
         }
     }
@@ -117,6 +684,28 @@ impl std::fmt::Display for RequestStatus {
             RequestStatus::Pending => write!(f, "pending"),
             RequestStatus::Completed => write!(f, "completed"),
             RequestStatus::Timeout => write!(f, "timeout"),
+            RequestStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionStatus::Active => write!(f, "active"),
+            SessionStatus::Completed => write!(f, "completed"),
+            SessionStatus::Failed => write!(f, "failed"),
+            SessionStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::fmt::Display for ToolCallStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolCallStatus::Running => write!(f, "running"),
+            ToolCallStatus::Succeeded => write!(f, "succeeded"),
+            ToolCallStatus::Failed => write!(f, "failed"),
         }
     }
 }
@@ -130,4 +719,138 @@ impl std::fmt::Display for RequestPriority {
             RequestPriority::Critical => write!(f, "critical"),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+impl HumanInputRequest {
+    /// A pending request with the given options, for tests
+    pub fn for_test(id: &str, request_type: RequestType, options: &[&str]) -> Self {
+        HumanInputRequest {
+            id: id.to_string(),
+            agent_id: "agent-1".to_string(),
+            agent_name: "Test Agent".to_string(),
+            request_type,
+            message: "Proceed?".to_string(),
+            options: options.iter().map(|id| RequestOption {
+                id: id.to_string(),
+                label: id.to_uppercase(),
+                description: None,
+                recommended: false,
+                preview: None,
+            }).collect(),
+            context: None,
+            schema: None,
+            min_selections: None,
+            max_selections: None,
+            timeout_seconds: 300,
+            on_timeout: None,
+            timestamp: Utc::now(),
+            status: RequestStatus::Pending,
+            priority: RequestPriority::Medium,
+            priority_reason: None,
+            claimed_by: None,
+            required_approvers: None,
+            approvals: Vec::new(),
+            session_id: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl HumanResponse {
+    pub fn for_test(request_id: &str, response: serde_json::Value, responded_by: &str) -> Self {
+        HumanResponse {
+            request_id: request_id.to_string(),
+            response,
+            additional_context: None,
+            responded_by: responded_by.to_string(),
+            timestamp: Utc::now(),
+            source: ResponseSource::Operator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_names_cover_automated_prefixes() {
+        assert!(is_reserved_name("system:timeout"));
+        assert!(is_reserved_name(" Policy:auto-approve"));
+        assert!(!is_reserved_name("alice"));
+        assert!(!is_reserved_name("systems-team"));
+    }
+
+    #[test]
+    fn response_source_is_never_read_from_clients() {
+        let response: HumanResponse = serde_json::from_value(serde_json::json!({
+            "request_id": "r1",
+            "response": "approved",
+            "additional_context": null,
+            "responded_by": "policy:anything",
+            "timestamp": Utc::now().to_rfc3339(),
+            "source": "policy",
+        })).unwrap();
+        assert_eq!(response.source, ResponseSource::Operator);
+        assert!(!response.is_automated());
+    }
+    
+    fn timing_out(policy: TimeoutPolicy, priority: RequestPriority) -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test("r1", RequestType::Approval, &["ship", "no"]);
+        request.on_timeout = Some(policy);
+        request.priority = priority;
+        request
+    }
+    
+    #[test]
+    fn timeout_policies_pick_their_answers() {
+        let deny = timing_out(TimeoutPolicy::Deny, RequestPriority::High);
+        assert_eq!(TimeoutPolicy::Deny.answer_for(&deny), Some(serde_json::json!("no")));
+        
+        let approve = timing_out(TimeoutPolicy::Approve, RequestPriority::Low);
+        // No approve-like option, so the generic answer is used
+        assert_eq!(TimeoutPolicy::Approve.answer_for(&approve), Some(serde_json::json!("approved")));
+        
+        let value = TimeoutPolicy::Default { value: Some(serde_json::json!("later")) };
+        assert_eq!(value.answer_for(&deny), Some(serde_json::json!("later")));
+        assert_eq!(TimeoutPolicy::Escalate.answer_for(&deny), None);
+    }
+    
+    #[test]
+    fn default_timeout_policy_uses_the_recommended_option() {
+        let mut request = timing_out(TimeoutPolicy::Default { value: None }, RequestPriority::Medium);
+        let policy = TimeoutPolicy::Default { value: None };
+        assert_eq!(policy.answer_for(&request), None);
+        
+        request.options[1].recommended = true;
+        assert_eq!(policy.answer_for(&request), Some(serde_json::json!("no")));
+    }
+    
+    #[test]
+    fn auto_approval_on_timeout_only_survives_for_low_priority_single_approver_requests() {
+        let low = timing_out(TimeoutPolicy::Approve, RequestPriority::Low);
+        assert_eq!(TimeoutPolicy::effective_for(&low), Some(TimeoutPolicy::Approve));
+        
+        let escalated = timing_out(TimeoutPolicy::Approve, RequestPriority::Medium);
+        assert_eq!(TimeoutPolicy::effective_for(&escalated), Some(TimeoutPolicy::Deny));
+        
+        let mut quorum = timing_out(TimeoutPolicy::Approve, RequestPriority::Low);
+        quorum.required_approvers = Some(2);
+        assert_eq!(TimeoutPolicy::effective_for(&quorum), Some(TimeoutPolicy::Deny));
+        
+        let escalate = timing_out(TimeoutPolicy::Escalate, RequestPriority::Critical);
+        assert_eq!(TimeoutPolicy::effective_for(&escalate), Some(TimeoutPolicy::Escalate));
+    }
+    
+    #[test]
+    fn decisions_follow_the_request_options() {
+        let request = HumanInputRequest::for_test("r1", RequestType::Approval, &["approve", "reject"]);
+        assert_eq!(request.approval_answer(), serde_json::json!("approve"));
+        assert_eq!(request.denial_answer(), serde_json::json!("reject"));
+        assert_eq!(request.decision_for(&serde_json::json!("approve")), Some(true));
+        assert_eq!(request.decision_for(&serde_json::json!("No")), Some(false));
+        assert_eq!(request.decision_for(&serde_json::json!(true)), Some(true));
+        assert_eq!(request.decision_for(&serde_json::json!("maybe")), None);
+    }
+}
+
@@ -0,0 +1,343 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::models::*;
+
+/// A rule from the policy file. Rules are checked in order: priority changes and approver
+/// requirements accumulate, and the first matching approve/deny rule decides the request
+/// without asking a human. Auto-approve rules are skipped for requests that need several
+/// approvers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "match", default)]
+    pub matcher: RuleMatch,
+    pub action: PolicyAction,
+}
+
+/// Conditions a request must meet for a rule to apply. Empty conditions match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleMatch {
+    /// Regex matched against the agent name
+    pub agent_name: Option<String>,
+    pub request_types: Vec<RequestType>,
+    /// Regex matched against the request message
+    pub message: Option<String>,
+    pub context: Vec<ContextCondition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextCondition {
+    /// JSON pointer (`/command/args`) or dotted path (`command.args`) into the request context
+    pub path: String,
+    #[serde(default)]
+    pub equals: Option<serde_json::Value>,
+    /// Regex matched against a string value
+    #[serde(default)]
+    pub matches: Option<String>,
+    #[serde(default)]
+    pub exists: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PolicyAction {
+    AutoApprove,
+    AutoDeny {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// Raise the priority to `to`, or one level when not given
+    RaisePriority {
+        #[serde(default)]
+        to: Option<RequestPriority>,
+    },
+    /// Require `count` distinct operators to approve the request
+    RequireApprovers { count: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyDecision {
+    Approve,
+    Deny { reason: Option<String> },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PolicyOutcome {
+    pub matched_rules: Vec<String>,
+    /// The last rule that raised the priority and the resulting priority
+    pub priority: Option<(String, RequestPriority)>,
+    /// The rule demanding the most approvers and its approver count
+    pub required_approvers: Option<(String, u32)>,
+    /// The deciding rule id and its decision
+    pub decision: Option<(String, PolicyDecision)>,
+}
+
+/// A `RuleMatch` with its regexes compiled
+pub struct RequestMatcher {
+    matcher: RuleMatch,
+    agent_name: Option<Regex>,
+    message: Option<Regex>,
+    context: Vec<(String, Option<Regex>)>,
+}
+
+impl RequestMatcher {
+    pub fn new(rule_id: &str, matcher: &RuleMatch) -> Result<Self, PolicyError> {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, PolicyError> {
+            pattern.as_deref()
+                .map(|p| Regex::new(p).map_err(|e| PolicyError::InvalidRegex(rule_id.to_string(), e.to_string())))
+                .transpose()
+        };
+
+        let mut context = Vec::with_capacity(matcher.context.len());
+        for condition in &matcher.context {
+            context.push((Self::json_pointer(&condition.path), compile(&condition.matches)?));
+        }
+
+        Ok(RequestMatcher {
+            agent_name: compile(&matcher.agent_name)?,
+            message: compile(&matcher.message)?,
+            context,
+            matcher: matcher.clone(),
+        })
+    }
+
+    pub fn matches(&self, request: &HumanInputRequest) -> bool {
+        if let Some(regex) = &self.agent_name {
+            if !regex.is_match(&request.agent_name) {
+                return false;
+            }
+        }
+        if !self.matcher.request_types.is_empty() && !self.matcher.request_types.contains(&request.request_type) {
+            return false;
+        }
+        if let Some(regex) = &self.message {
+            if !regex.is_match(&request.message) {
+                return false;
+            }
+        }
+
+        let context = request.context.as_ref().unwrap_or(&serde_json::Value::Null);
+        for (condition, (pointer, regex)) in self.matcher.context.iter().zip(&self.context) {
+            let value = context.pointer(pointer);
+
+            if let Some(exists) = condition.exists {
+                if value.is_some() != exists {
+                    return false;
+                }
+            }
+            if let Some(expected) = &condition.equals {
+                if value != Some(expected) {
+                    return false;
+                }
+            }
+            if let Some(regex) = regex {
+                match value.and_then(|v| v.as_str()) {
+                    Some(text) if regex.is_match(text) => {}
+                    _ => return false,
+                }
+            }
+        }
+
+        true
+    }
+
+    fn json_pointer(path: &str) -> String {
+        if path.starts_with('/') || path.is_empty() {
+            path.to_string()
+        } else {
+            format!("/{}", path.replace('.', "/"))
+        }
+    }
+}
+
+struct CompiledRule {
+    rule: PolicyRule,
+    matcher: RequestMatcher,
+}
+
+/// Evaluates policy rules against incoming human input requests
+pub struct PolicyEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl PolicyEngine {
+    pub fn new(rules: Vec<PolicyRule>) -> Result<Self, PolicyError> {
+        let mut compiled = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let matcher = RequestMatcher::new(&rule.id, &rule.matcher)?;
+            compiled.push(CompiledRule { rule, matcher });
+        }
+
+        Ok(PolicyEngine { rules: compiled })
+    }
+
+    pub fn rules(&self) -> Vec<PolicyRule> {
+        self.rules.iter().map(|r| r.rule.clone()).collect()
+    }
+
+    pub fn evaluate(&self, request: &HumanInputRequest) -> PolicyOutcome {
+        let mut outcome = PolicyOutcome::default();
+        let mut priority = request.priority;
+        let mut quorum = if request.supports_quorum() { request.quorum() } else { 1 };
+
+        for compiled in &self.rules {
+            if !compiled.matcher.matches(request) {
+                continue;
+            }
+            outcome.matched_rules.push(compiled.rule.id.clone());
+
+            match &compiled.rule.action {
+                PolicyAction::RaisePriority { to } => {
+                    let raised = to.unwrap_or_else(|| priority.escalated());
+                    if raised > priority {
+                        priority = raised;
+                        outcome.priority = Some((compiled.rule.id.clone(), priority));
+                    }
+                }
+                PolicyAction::RequireApprovers { count } => {
+                    if request.supports_quorum() && *count > quorum {
+                        quorum = *count;
+                        outcome.required_approvers = Some((compiled.rule.id.clone(), quorum));
+                    }
+                }
+                PolicyAction::AutoApprove if quorum > 1 => {}
+                PolicyAction::AutoApprove => {
+                    outcome.decision = Some((compiled.rule.id.clone(), PolicyDecision::Approve));
+                    break;
+                }
+                PolicyAction::AutoDeny { reason } => {
+                    let reason = reason.clone().or_else(|| compiled.rule.description.clone());
+                    outcome.decision = Some((compiled.rule.id.clone(), PolicyDecision::Deny { reason }));
+                    break;
+                }
+            }
+        }
+
+        outcome
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyError {
+    #[error("Invalid regex in rule {0}: {1}")]
+    InvalidRegex(String, String),
+
+    #[error("Operator {0} has no token")]
+    MissingToken(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(value: serde_json::Value) -> PolicyEngine {
+        PolicyEngine::new(serde_json::from_value(value).unwrap()).unwrap()
+    }
+
+    fn shell_request(command: &str) -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test("r1", RequestType::Approval, &["approve", "reject"]);
+        request.agent_name = "deploy-bot".to_string();
+        request.message = format!("Run `{}`?", command);
+        request.context = Some(json!({ "command": { "name": command, "sudo": false } }));
+        request
+    }
+
+    fn matcher(value: serde_json::Value) -> RequestMatcher {
+        RequestMatcher::new("rule", &serde_json::from_value(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn empty_matchers_match_everything() {
+        assert!(matcher(json!({})).matches(&shell_request("ls")));
+    }
+
+    #[test]
+    fn every_condition_must_hold() {
+        let request = shell_request("rm -rf build");
+
+        assert!(matcher(json!({ "agent_name": "^deploy-" })).matches(&request));
+        assert!(!matcher(json!({ "agent_name": "^review-" })).matches(&request));
+        assert!(matcher(json!({ "request_types": ["Approval", "Confirmation"] })).matches(&request));
+        assert!(!matcher(json!({ "request_types": ["Form"] })).matches(&request));
+        assert!(matcher(json!({ "message": "rm -rf" })).matches(&request));
+        assert!(!matcher(json!({ "agent_name": "^deploy-", "message": "^Delete" })).matches(&request));
+    }
+
+    #[test]
+    fn context_conditions_accept_pointers_and_dotted_paths() {
+        let request = shell_request("rm -rf build");
+
+        assert!(matcher(json!({ "context": [{ "path": "command.name", "matches": "^rm " }] })).matches(&request));
+        assert!(matcher(json!({ "context": [{ "path": "/command/sudo", "equals": false }] })).matches(&request));
+        assert!(!matcher(json!({ "context": [{ "path": "command.sudo", "equals": true }] })).matches(&request));
+        assert!(matcher(json!({ "context": [{ "path": "command.cwd", "exists": false }] })).matches(&request));
+        assert!(!matcher(json!({ "context": [{ "path": "command.cwd", "exists": true }] })).matches(&request));
+        // A regex never matches a missing or non-string value
+        assert!(!matcher(json!({ "context": [{ "path": "command.sudo", "matches": "." }] })).matches(&request));
+    }
+
+    #[test]
+    fn invalid_regexes_name_their_rule() {
+        let result = PolicyEngine::new(serde_json::from_value(json!([
+            { "id": "broken", "match": { "message": "(" }, "action": { "type": "auto-approve" } }
+        ])).unwrap());
+        assert!(matches!(result, Err(PolicyError::InvalidRegex(rule, _)) if rule == "broken"));
+    }
+
+    #[test]
+    fn first_matching_decision_wins_and_earlier_rules_accumulate() {
+        let engine = rules(json!([
+            { "id": "raise-rm", "match": { "message": "rm " }, "action": { "type": "raise-priority", "to": "High" } },
+            { "id": "allow-ls", "match": { "message": "`ls`" }, "action": { "type": "auto-approve" } },
+            { "id": "deny-rm", "match": { "message": "rm " }, "action": { "type": "auto-deny", "reason": "no deletes" } },
+            { "id": "allow-all", "action": { "type": "auto-approve" } }
+        ]));
+
+        let outcome = engine.evaluate(&shell_request("rm -rf build"));
+        assert_eq!(outcome.matched_rules, ["raise-rm", "deny-rm"]);
+        assert_eq!(outcome.priority, Some(("raise-rm".to_string(), RequestPriority::High)));
+        assert_eq!(outcome.decision, Some((
+            "deny-rm".to_string(),
+            PolicyDecision::Deny { reason: Some("no deletes".to_string()) },
+        )));
+
+        let outcome = engine.evaluate(&shell_request("ls"));
+        assert_eq!(outcome.matched_rules, ["allow-ls"]);
+        assert_eq!(outcome.decision, Some(("allow-ls".to_string(), PolicyDecision::Approve)));
+    }
+
+    #[test]
+    fn priority_is_never_lowered() {
+        let engine = rules(json!([
+            { "id": "bump", "action": { "type": "raise-priority" } },
+            { "id": "to-low", "action": { "type": "raise-priority", "to": "Low" } }
+        ]));
+
+        let outcome = engine.evaluate(&shell_request("ls"));
+        assert_eq!(outcome.priority, Some(("bump".to_string(), RequestPriority::High)));
+    }
+
+    #[test]
+    fn approver_requirements_block_auto_approval() {
+        let engine = rules(json!([
+            { "id": "two-eyes", "action": { "type": "require-approvers", "count": 2 } },
+            { "id": "one-eye", "action": { "type": "require-approvers", "count": 1 } },
+            { "id": "allow-all", "action": { "type": "auto-approve" } }
+        ]));
+
+        let outcome = engine.evaluate(&shell_request("ls"));
+        assert_eq!(outcome.required_approvers, Some(("two-eyes".to_string(), 2)));
+        assert_eq!(outcome.decision, None);
+
+        // Only yes/no requests take approver requirements
+        let mut text = shell_request("ls");
+        text.request_type = RequestType::Text;
+        let outcome = engine.evaluate(&text);
+        assert_eq!(outcome.required_approvers, None);
+        assert_eq!(outcome.decision, Some(("allow-all".to_string(), PolicyDecision::Approve)));
+    }
+}
@@ -0,0 +1,213 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::models::*;
+use crate::policy::{PolicyError, RequestMatcher, RuleMatch};
+
+/// The `priority` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriorityConfig {
+    /// Whether a `priority` sent by the agent may raise the classified priority. It never
+    /// lowers it, so agents can't push their requests towards low-priority auto-approval.
+    /// When it is ignored, the priority reason says so.
+    pub honor_agent_priority: bool,
+    /// Checked in order; the first matching rule sets the priority
+    pub rules: Vec<PriorityRule>,
+    /// Used when no rule matches, for agents whose name matches `agent_name`
+    pub agent_defaults: Vec<AgentPriorityDefault>,
+    /// Used when neither a rule nor an agent default applies
+    pub type_defaults: Vec<TypePriorityDefault>,
+    pub fallback: RequestPriority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityRule {
+    pub id: String,
+    #[serde(rename = "match", default)]
+    pub matcher: RuleMatch,
+    pub priority: RequestPriority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentPriorityDefault {
+    /// Regex matched against the agent name
+    pub agent_name: String,
+    pub priority: RequestPriority,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypePriorityDefault {
+    pub request_type: RequestType,
+    pub priority: RequestPriority,
+}
+
+impl Default for PriorityConfig {
+    /// Mirrors the original keyword heuristics
+    fn default() -> Self {
+        PriorityConfig {
+            honor_agent_priority: true,
+            rules: vec![
+                PriorityRule {
+                    id: "urgent-keywords".to_string(),
+                    matcher: RuleMatch {
+                        request_types: Self::non_approval_types(),
+                        message: Some("(?i)critical|urgent".to_string()),
+                        ..Default::default()
+                    },
+                    priority: RequestPriority::Critical,
+                },
+                PriorityRule {
+                    id: "optional-keywords".to_string(),
+                    matcher: RuleMatch {
+                        request_types: Self::non_approval_types(),
+                        message: Some("(?i)optional|suggestion".to_string()),
+                        ..Default::default()
+                    },
+                    priority: RequestPriority::Low,
+                },
+            ],
+            agent_defaults: Vec::new(),
+            type_defaults: vec![
+                TypePriorityDefault { request_type: RequestType::Approval, priority: RequestPriority::High },
+                TypePriorityDefault { request_type: RequestType::Confirmation, priority: RequestPriority::High },
+            ],
+            fallback: RequestPriority::Medium,
+        }
+    }
+}
+
+impl PriorityConfig {
+    fn non_approval_types() -> Vec<RequestType> {
+        vec![
+            RequestType::Input,
+            RequestType::Choice,
+            RequestType::Text,
+            RequestType::Form,
+            RequestType::MultiChoice,
+            RequestType::Ranking,
+        ]
+    }
+}
+
+/// A priority together with the reason it was chosen, shown to operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityClassification {
+    pub priority: RequestPriority,
+    pub reason: String,
+}
+
+/// Decides the priority of incoming human input requests
+pub struct PriorityClassifier {
+    config: PriorityConfig,
+    rules: Vec<RequestMatcher>,
+    agent_defaults: Vec<Regex>,
+}
+
+impl PriorityClassifier {
+    pub fn new(config: PriorityConfig) -> Result<Self, PolicyError> {
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in &config.rules {
+            rules.push(RequestMatcher::new(&rule.id, &rule.matcher)?);
+        }
+
+        let mut agent_defaults = Vec::with_capacity(config.agent_defaults.len());
+        for default in &config.agent_defaults {
+            let regex = Regex::new(&default.agent_name)
+                .map_err(|e| PolicyError::InvalidRegex(format!("agent default {}", default.agent_name), e.to_string()))?;
+            agent_defaults.push(regex);
+        }
+
+        Ok(PriorityClassifier { config, rules, agent_defaults })
+    }
+
+    pub fn classify(&self, request: &HumanInputRequest, requested: Option<RequestPriority>) -> PriorityClassification {
+        let classified = self.classify_request(request);
+        match requested {
+            Some(priority) if self.config.honor_agent_priority && priority > classified.priority => {
+                PriorityClassification { priority, reason: "raised by the agent".to_string() }
+            }
+            Some(priority) if priority != classified.priority => PriorityClassification {
+                reason: format!("{} (agent priority {} ignored)", classified.reason, priority),
+                ..classified
+            },
+            _ => classified,
+        }
+    }
+
+    fn classify_request(&self, request: &HumanInputRequest) -> PriorityClassification {
+        if let Some((rule, _)) = self.config.rules.iter().zip(&self.rules).find(|(_, m)| m.matches(request)) {
+            return PriorityClassification {
+                priority: rule.priority,
+                reason: format!("matched priority rule '{}'", rule.id),
+            };
+        }
+
+        if let Some((default, _)) = self.config.agent_defaults.iter()
+            .zip(&self.agent_defaults)
+            .find(|(_, regex)| regex.is_match(&request.agent_name))
+        {
+            return PriorityClassification {
+                priority: default.priority,
+                reason: format!("default for agents matching '{}'", default.agent_name),
+            };
+        }
+
+        if let Some(default) = self.config.type_defaults.iter().find(|d| d.request_type == request.request_type) {
+            return PriorityClassification {
+                priority: default.priority,
+                reason: format!("default for {:?} requests", request.request_type),
+            };
+        }
+
+        PriorityClassification {
+            priority: self.config.fallback,
+            reason: "no rule matched".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classifier(honor_agent_priority: bool) -> PriorityClassifier {
+        PriorityClassifier::new(PriorityConfig { honor_agent_priority, ..PriorityConfig::default() }).unwrap()
+    }
+
+    fn request(message: &str) -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test("r1", RequestType::Choice, &["a", "b"]);
+        request.message = message.to_string();
+        request
+    }
+
+    #[test]
+    fn agent_priority_is_honored_by_default() {
+        let classifier = PriorityClassifier::new(PriorityConfig::default()).unwrap();
+        let raised = classifier.classify(&request("Pick one"), Some(RequestPriority::High));
+        assert_eq!(raised.priority, RequestPriority::High);
+    }
+
+    #[test]
+    fn ignored_agent_priority_is_noted_in_the_reason() {
+        let classification = classifier(false).classify(&request("Pick one"), Some(RequestPriority::Critical));
+        assert_eq!(classification.priority, RequestPriority::Medium);
+        assert!(classification.reason.ends_with("(agent priority critical ignored)"));
+
+        let matching = classifier(false).classify(&request("Pick one"), Some(RequestPriority::Medium));
+        assert!(!matching.reason.contains("ignored"));
+    }
+
+    #[test]
+    fn agents_can_raise_but_not_lower_priority() {
+        let classifier = classifier(true);
+        let lowered = classifier.classify(&request("This is urgent"), Some(RequestPriority::Low));
+        assert_eq!(lowered.priority, RequestPriority::Critical);
+        assert_eq!(lowered.reason, "matched priority rule 'urgent-keywords' (agent priority low ignored)");
+
+        let baseline = classifier.classify(&request("Pick one"), None).priority;
+        let raised = classifier.classify(&request("Pick one"), Some(RequestPriority::Critical));
+        assert!(baseline < RequestPriority::Critical);
+        assert_eq!(raised.priority, RequestPriority::Critical);
+        assert_eq!(raised.reason, "raised by the agent");
+    }
+}
@@ -0,0 +1,265 @@
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::Instant;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::models::*;
+use crate::session_archive::ArchiveRecord;
+use crate::websocket_server::WebSocketServer;
+
+/// Plays recorded session traffic back to GUI clients with its original relative timing
+pub struct ReplaySession {
+    id: String,
+    source: String,
+    records: Vec<ArchiveRecord>,
+    offsets: Vec<Duration>,
+    playback: Mutex<Playback>,
+    wake: Notify,
+    server: Arc<WebSocketServer>,
+}
+
+struct Playback {
+    state: ReplayState,
+    speed: f64,
+    // Virtual position in the recording at the moment `anchor` was taken
+    offset: Duration,
+    anchor: Instant,
+    next_index: usize,
+    resync: bool,
+}
+
+enum Step {
+    Emit(Range<usize>),
+    Wait(Duration),
+    Idle,
+    Finished,
+    Stop,
+}
+
+impl Playback {
+    fn position(&self) -> Duration {
+        if self.state == ReplayState::Playing {
+            self.offset + self.anchor.elapsed().mul_f64(self.speed)
+        } else {
+            self.offset
+        }
+    }
+
+    fn rebase(&mut self) {
+        self.offset = self.position();
+        self.anchor = Instant::now();
+    }
+}
+
+impl ReplaySession {
+    /// Starts playback of `records`, which must already be in chronological order
+    pub fn start(
+        source: String,
+        records: Vec<ArchiveRecord>,
+        speed: f64,
+        server: Arc<WebSocketServer>,
+    ) -> Result<Arc<Self>, Box<dyn std::error::Error>> {
+        let records: Vec<ArchiveRecord> = records.into_iter()
+            .filter(|r| !matches!(r, ArchiveRecord::Header(_)))
+            .collect();
+        let first = records.first().ok_or("Nothing to replay")?.timestamp();
+        Self::validate_speed(speed)?;
+
+        let offsets = records.iter()
+            .map(|r| (r.timestamp() - first).to_std().unwrap_or_default())
+            .collect();
+
+        let session = Arc::new(ReplaySession {
+            id: Uuid::new_v4().to_string(),
+            source,
+            records,
+            offsets,
+            playback: Mutex::new(Playback {
+                state: ReplayState::Playing,
+                speed,
+                offset: Duration::ZERO,
+                anchor: Instant::now(),
+                next_index: 0,
+                resync: false,
+            }),
+            wake: Notify::new(),
+            server,
+        });
+
+        info!("Starting replay {} of {} ({} records)", session.id, session.source, session.records.len());
+        tokio::spawn(session.clone().run());
+
+        Ok(session)
+    }
+
+    pub async fn status(&self) -> ReplayStatus {
+        let playback = self.playback.lock().await;
+        ReplayStatus {
+            id: self.id.clone(),
+            source: self.source.clone(),
+            state: playback.state.clone(),
+            speed: playback.speed,
+            position_ms: playback.position().min(self.duration()).as_millis() as u64,
+            duration_ms: self.duration().as_millis() as u64,
+            records_emitted: playback.next_index,
+            total_records: self.records.len(),
+        }
+    }
+
+    pub async fn pause(&self) {
+        self.update(|playback| {
+            if playback.state == ReplayState::Playing {
+                playback.rebase();
+                playback.state = ReplayState::Paused;
+            }
+        }).await;
+    }
+
+    pub async fn resume(&self) {
+        self.update(|playback| {
+            if playback.state == ReplayState::Paused {
+                playback.anchor = Instant::now();
+                playback.state = ReplayState::Playing;
+            }
+        }).await;
+    }
+
+    pub async fn set_speed(&self, speed: f64) -> Result<(), Box<dyn std::error::Error>> {
+        Self::validate_speed(speed)?;
+        self.update(|playback| {
+            playback.rebase();
+            playback.speed = speed;
+        }).await;
+        Ok(())
+    }
+
+    /// Moves playback to `position_ms` from the start of the recording. GUIs receive a
+    /// `replay-reset` followed by every record before that point, so their view matches a
+    /// straight playback up to the same position.
+    pub async fn seek(&self, position_ms: u64) {
+        let target = Duration::from_millis(position_ms).min(self.duration());
+        let next_index = self.offsets.partition_point(|offset| *offset < target);
+
+        self.server.broadcast("replay-reset", &serde_json::json!({ "replayId": self.id })).await;
+        self.update(|playback| {
+            playback.offset = target;
+            playback.anchor = Instant::now();
+            playback.next_index = next_index;
+            playback.resync = true;
+            if playback.state == ReplayState::Finished {
+                playback.state = ReplayState::Playing;
+            }
+        }).await;
+    }
+
+    pub async fn stop(&self) {
+        self.update(|playback| {
+            playback.rebase();
+            playback.state = ReplayState::Stopped;
+        }).await;
+    }
+
+    fn duration(&self) -> Duration {
+        self.offsets.last().copied().unwrap_or_default()
+    }
+
+    fn validate_speed(speed: f64) -> Result<(), Box<dyn std::error::Error>> {
+        if speed.is_finite() && speed > 0.0 {
+            Ok(())
+        } else {
+            Err(format!("Invalid replay speed: {}", speed).into())
+        }
+    }
+
+    async fn update<F: FnOnce(&mut Playback)>(&self, change: F) {
+        {
+            let mut playback = self.playback.lock().await;
+            if playback.state == ReplayState::Stopped {
+                return;
+            }
+            change(&mut playback);
+        }
+
+        self.wake.notify_one();
+        self.broadcast_status().await;
+    }
+
+    async fn broadcast_status(&self) {
+        let status = self.status().await;
+        self.server.broadcast("replay-status", &status).await;
+    }
+
+    async fn run(self: Arc<Self>) {
+        self.broadcast_status().await;
+
+        loop {
+            let step = {
+                let mut playback = self.playback.lock().await;
+                match playback.state {
+                    ReplayState::Stopped => Step::Stop,
+                    ReplayState::Paused | ReplayState::Finished => Step::Idle,
+                    ReplayState::Playing if playback.resync => {
+                        playback.resync = false;
+                        Step::Emit(0..playback.next_index)
+                    }
+                    ReplayState::Playing if playback.next_index >= self.records.len() => {
+                        playback.offset = self.duration();
+                        playback.state = ReplayState::Finished;
+                        Step::Finished
+                    }
+                    ReplayState::Playing => {
+                        let position = playback.position();
+                        let due = self.offsets[playback.next_index];
+                        if due <= position {
+                            let start = playback.next_index;
+                            let end = self.offsets.partition_point(|offset| *offset <= position);
+                            playback.next_index = end;
+                            Step::Emit(start..end)
+                        } else {
+                            Step::Wait((due - position).div_f64(playback.speed))
+                        }
+                    }
+                }
+            };
+
+            match step {
+                Step::Emit(range) => {
+                    for record in &self.records[range] {
+                        self.emit(record).await;
+                    }
+                }
+                Step::Wait(delay) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = self.wake.notified() => {}
+                    }
+                }
+                Step::Idle => self.wake.notified().await,
+                Step::Finished => self.broadcast_status().await,
+                Step::Stop => break,
+            }
+        }
+
+        info!("Replay {} stopped", self.id);
+    }
+
+    /// Sends a record as the live message it was recorded from, marked with the replay's id
+    async fn emit(&self, record: &ArchiveRecord) {
+        let server = &self.server;
+        match record {
+            ArchiveRecord::Agent(agent) => server.broadcast_replayed(&self.id, "agent-connected", agent).await,
+            ArchiveRecord::Message(message) => server.broadcast_replayed(&self.id, "agent-update", message).await,
+            ArchiveRecord::Content(message) => {
+                server.broadcast_replayed(&self.id, &message.message_type, &message.payload).await
+            }
+            ArchiveRecord::Request(request) => server.broadcast_replayed(&self.id, "human-input-request", request).await,
+            ArchiveRecord::Response(response) => {
+                server.broadcast_replayed(&self.id, "human-input-response", response).await
+            }
+            ArchiveRecord::Header(_) => {}
+        }
+    }
+}
@@ -0,0 +1,501 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use crate::models::*;
+
+/// The `queue` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// How long a request may wait at each priority before it is escalated
+    pub sla_seconds: SlaSeconds,
+    /// How long answered and timed out requests stay in memory; they remain in the database
+    pub retention_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlaSeconds {
+    pub low: u64,
+    pub medium: u64,
+    pub high: u64,
+    pub critical: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            sla_seconds: SlaSeconds::default(),
+            retention_seconds: 600,
+        }
+    }
+}
+
+impl Default for SlaSeconds {
+    fn default() -> Self {
+        SlaSeconds { low: 1800, medium: 600, high: 180, critical: 60 }
+    }
+}
+
+impl SlaSeconds {
+    pub fn for_priority(&self, priority: RequestPriority) -> Duration {
+        let seconds = match priority {
+            RequestPriority::Low => self.low,
+            RequestPriority::Medium => self.medium,
+            RequestPriority::High => self.high,
+            RequestPriority::Critical => self.critical,
+        };
+        Duration::seconds(seconds as i64)
+    }
+}
+
+struct QueuedRequest {
+    request: HumanInputRequest,
+    // Start of the SLA window for the current priority
+    sla_started_at: DateTime<Utc>,
+    escalations: u32,
+    sla_breached: bool,
+    resolved_at: Option<DateTime<Utc>>,
+    resolved_by: Option<String>,
+}
+
+/// A snapshot of one pending request's place in the queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub request: HumanInputRequest,
+    /// 1-based position, highest priority first
+    pub position: usize,
+    pub remaining_seconds: i64,
+    pub sla_remaining_seconds: i64,
+    pub escalations: u32,
+    /// Set once a critical request outlives its SLA and can't be escalated further
+    pub sla_breached: bool,
+}
+
+/// Human input requests known to the server. Pending requests are ordered by priority, then
+/// by deadline; resolved ones are dropped after the retention period.
+#[derive(Default)]
+pub struct RequestQueue {
+    requests: Vec<QueuedRequest>,
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        RequestQueue::default()
+    }
+
+    pub fn push(&mut self, request: HumanInputRequest) {
+        self.requests.push(QueuedRequest {
+            sla_started_at: request.timestamp,
+            request,
+            escalations: 0,
+            sla_breached: false,
+            resolved_at: None,
+            resolved_by: None,
+        });
+    }
+
+    /// Whether the queue holds a request with this id, pending or recently resolved
+    pub fn contains(&self, request_id: &str) -> bool {
+        self.requests.iter().any(|q| q.request.id == request_id)
+    }
+
+    pub fn get(&self, request_id: &str) -> Option<&HumanInputRequest> {
+        self.requests.iter().find(|q| q.request.id == request_id).map(|q| &q.request)
+    }
+
+    pub fn get_mut(&mut self, request_id: &str) -> Option<&mut HumanInputRequest> {
+        self.requests.iter_mut().find(|q| q.request.id == request_id).map(|q| &mut q.request)
+    }
+
+    /// Like `get_mut`, but only for requests still waiting for an answer
+    pub fn pending_mut(&mut self, request_id: &str) -> Option<&mut HumanInputRequest> {
+        self.get_mut(request_id).filter(|r| matches!(r.status, RequestStatus::Pending))
+    }
+
+    /// Returns the pending request `response` answers, provided its responder may answer it.
+    /// Claims only hold back other operators, and don't apply to requests that need several
+    /// approvers; automated responses always go through.
+    pub fn check_answerable(&self, response: &HumanResponse) -> Result<&HumanInputRequest, QueueError> {
+        let queued = self.requests.iter()
+            .find(|q| q.request.id == response.request_id)
+            .ok_or_else(|| QueueError::NotFound(response.request_id.clone()))?;
+
+        if !matches!(queued.request.status, RequestStatus::Pending) {
+            return Err(match &queued.resolved_by {
+                Some(responder) => QueueError::AlreadyAnswered(responder.clone()),
+                None => QueueError::NotPending(queued.request.status.clone()),
+            });
+        }
+
+        if let Some(claim) = &queued.request.claimed_by {
+            if !response.is_automated() && queued.request.quorum() <= 1 && claim.operator != response.responded_by {
+                return Err(QueueError::ClaimedBy(claim.operator.clone()));
+            }
+        }
+
+        Ok(&queued.request)
+    }
+
+    /// Adds an operator's approval or denial to a pending request. Each operator gets one vote.
+    pub fn record_approval(&mut self, request_id: &str, approval: Approval) -> Result<HumanInputRequest, QueueError> {
+        let request = self.pending_request_mut(request_id)?;
+        if request.approvals.iter().any(|a| a.operator == approval.operator) {
+            return Err(QueueError::AlreadyVoted(approval.operator));
+        }
+
+        request.approvals.push(approval);
+        Ok(request.clone())
+    }
+
+    /// Records that a request is no longer pending so it can be pruned later
+    pub fn mark_resolved(&mut self, request_id: &str, status: RequestStatus, responded_by: Option<String>) {
+        if let Some(queued) = self.requests.iter_mut().find(|q| q.request.id == request_id) {
+            queued.request.status = status;
+            queued.request.claimed_by = None;
+            queued.resolved_at = Some(Utc::now());
+            queued.resolved_by = responded_by;
+        }
+    }
+
+    /// Locks a pending request to `operator`. Claiming a request the operator already holds
+    /// refreshes the claim.
+    pub fn claim(&mut self, request_id: &str, operator: &str, client_id: Option<&str>) -> Result<HumanInputRequest, QueueError> {
+        let request = self.pending_request_mut(request_id)?;
+        if let Some(claim) = &request.claimed_by {
+            if claim.operator != operator {
+                return Err(QueueError::ClaimedBy(claim.operator.clone()));
+            }
+        }
+
+        request.claimed_by = Some(RequestClaim {
+            operator: operator.to_string(),
+            client_id: client_id.map(|id| id.to_string()),
+            claimed_at: Utc::now(),
+        });
+        Ok(request.clone())
+    }
+
+    pub fn release(&mut self, request_id: &str, operator: &str) -> Result<HumanInputRequest, QueueError> {
+        let request = self.pending_request_mut(request_id)?;
+        match &request.claimed_by {
+            Some(claim) if claim.operator == operator => {
+                request.claimed_by = None;
+                Ok(request.clone())
+            }
+            Some(claim) => Err(QueueError::ClaimedBy(claim.operator.clone())),
+            None => Err(QueueError::NotClaimed(request_id.to_string())),
+        }
+    }
+
+    /// Releases every claim held through a GUI connection, returning the released requests
+    pub fn release_client(&mut self, client_id: &str) -> Vec<HumanInputRequest> {
+        let mut released = Vec::new();
+        for queued in self.requests.iter_mut() {
+            let held = queued.request.claimed_by.as_ref()
+                .is_some_and(|claim| claim.client_id.as_deref() == Some(client_id));
+            if held {
+                queued.request.claimed_by = None;
+                released.push(queued.request.clone());
+            }
+        }
+        released
+    }
+
+    fn pending_request_mut(&mut self, request_id: &str) -> Result<&mut HumanInputRequest, QueueError> {
+        let request = self.get_mut(request_id)
+            .ok_or_else(|| QueueError::NotFound(request_id.to_string()))?;
+        if !matches!(request.status, RequestStatus::Pending) {
+            return Err(QueueError::NotPending(request.status.clone()));
+        }
+        Ok(request)
+    }
+
+    /// Moves a request up one priority level and restarts its SLA clock
+    pub fn escalate(&mut self, request_id: &str, reason: String) -> Option<HumanInputRequest> {
+        let queued = self.requests.iter_mut()
+            .find(|q| q.request.id == request_id && matches!(q.request.status, RequestStatus::Pending))?;
+        queued.request.priority = queued.request.priority.escalated();
+        queued.request.priority_reason = Some(reason);
+        queued.sla_started_at = Utc::now();
+        queued.escalations += 1;
+        Some(queued.request.clone())
+    }
+
+    pub fn all(&self) -> Vec<HumanInputRequest> {
+        self.requests.iter().map(|q| q.request.clone()).collect()
+    }
+
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<HumanInputRequest> {
+        self.requests.iter()
+            .filter(|q| matches!(q.request.status, RequestStatus::Pending) && q.request.deadline() <= now)
+            .map(|q| q.request.clone())
+            .collect()
+    }
+
+    /// Escalates pending requests that have waited past the SLA for their priority and
+    /// returns the ones that changed. Critical requests are flagged as breached instead.
+    pub fn age(&mut self, now: DateTime<Utc>, config: &QueueConfig) -> Vec<HumanInputRequest> {
+        let mut changed = Vec::new();
+
+        for queued in self.requests.iter_mut() {
+            if !matches!(queued.request.status, RequestStatus::Pending) {
+                continue;
+            }
+
+            let sla = config.sla_seconds.for_priority(queued.request.priority);
+            if now - queued.sla_started_at < sla {
+                continue;
+            }
+
+            if queued.request.priority == RequestPriority::Critical {
+                if !queued.sla_breached {
+                    queued.sla_breached = true;
+                    changed.push(queued.request.clone());
+                }
+                continue;
+            }
+
+            let previous = queued.request.priority;
+            queued.request.priority = previous.escalated();
+            queued.request.priority_reason = Some(format!(
+                "escalated after waiting longer than the {} priority SLA ({}s)",
+                previous,
+                sla.num_seconds()
+            ));
+            queued.sla_started_at = now;
+            queued.escalations += 1;
+            changed.push(queued.request.clone());
+        }
+
+        changed
+    }
+
+    /// Drops resolved requests older than the retention period
+    pub fn prune(&mut self, now: DateTime<Utc>, config: &QueueConfig) {
+        let retention = Duration::seconds(config.retention_seconds as i64);
+        self.requests.retain(|q| q.resolved_at.is_none_or(|resolved| now - resolved < retention));
+    }
+
+    /// Pending requests in the order operators should handle them
+    pub fn snapshot(&self, now: DateTime<Utc>, config: &QueueConfig) -> Vec<QueueEntry> {
+        let mut pending: Vec<&QueuedRequest> = self.requests.iter()
+            .filter(|q| matches!(q.request.status, RequestStatus::Pending))
+            .collect();
+        pending.sort_by_key(|q| (Reverse(q.request.priority), q.request.deadline(), q.request.timestamp));
+
+        pending.into_iter()
+            .enumerate()
+            .map(|(index, q)| {
+                let sla_deadline = q.sla_started_at + config.sla_seconds.for_priority(q.request.priority);
+                QueueEntry {
+                    request: q.request.clone(),
+                    position: index + 1,
+                    remaining_seconds: (q.request.deadline() - now).num_seconds().max(0),
+                    sla_remaining_seconds: (sla_deadline - now).num_seconds().max(0),
+                    escalations: q.escalations,
+                    sla_breached: q.sla_breached,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum QueueError {
+    #[error("Request {0} not found")]
+    NotFound(String),
+
+    #[error("Request is no longer pending ({0})")]
+    NotPending(RequestStatus),
+
+    #[error("Request was already answered by {0}")]
+    AlreadyAnswered(String),
+
+    #[error("Request is claimed by {0}")]
+    ClaimedBy(String),
+
+    #[error("Request {0} is not claimed")]
+    NotClaimed(String),
+
+    #[error("{0} has already approved or denied this request")]
+    AlreadyVoted(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ResponseSource;
+
+    fn request(id: &str, priority: RequestPriority, timeout_seconds: u32, age_seconds: i64) -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test(id, RequestType::Approval, &["approve", "reject"]);
+        request.priority = priority;
+        request.timeout_seconds = timeout_seconds;
+        request.timestamp = Utc::now() - Duration::seconds(age_seconds);
+        request
+    }
+
+    fn approval(operator: &str, approved: bool) -> Approval {
+        Approval { operator: operator.to_string(), approved, comment: None, timestamp: Utc::now() }
+    }
+
+    fn claimed_queue() -> RequestQueue {
+        let mut queue = RequestQueue::new();
+        queue.push(HumanInputRequest::for_test("r1", RequestType::Approval, &["approve", "reject"]));
+        queue.claim("r1", "alice", None).unwrap();
+        queue
+    }
+
+    #[test]
+    fn claims_hold_back_operators_with_automated_looking_names() {
+        let queue = claimed_queue();
+        let response = HumanResponse::for_test("r1", serde_json::json!("approve"), "system:timeout");
+        assert!(matches!(queue.check_answerable(&response), Err(QueueError::ClaimedBy(op)) if op == "alice"));
+    }
+
+    #[test]
+    fn server_timeouts_go_through_claims() {
+        let queue = claimed_queue();
+        let mut response = HumanResponse::for_test("r1", serde_json::json!("reject"), "system:timeout");
+        response.source = ResponseSource::Timeout;
+        assert!(queue.check_answerable(&response).is_ok());
+    }
+
+    #[test]
+    fn snapshot_orders_by_priority_then_deadline() {
+        let mut queue = RequestQueue::new();
+        queue.push(request("low", RequestPriority::Low, 60, 0));
+        queue.push(request("medium-late", RequestPriority::Medium, 900, 0));
+        queue.push(request("medium-soon", RequestPriority::Medium, 120, 0));
+        queue.push(request("critical", RequestPriority::Critical, 900, 0));
+        queue.push(request("answered", RequestPriority::Critical, 60, 0));
+        queue.mark_resolved("answered", RequestStatus::Completed, Some("alice".to_string()));
+
+        let snapshot = queue.snapshot(Utc::now(), &QueueConfig::default());
+        let order: Vec<&str> = snapshot.iter().map(|e| e.request.id.as_str()).collect();
+        assert_eq!(order, ["critical", "medium-soon", "medium-late", "low"]);
+        assert_eq!(snapshot.iter().map(|e| e.position).collect::<Vec<_>>(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn aging_escalates_one_level_and_restarts_the_sla() {
+        let config = QueueConfig::default();
+        let mut queue = RequestQueue::new();
+        queue.push(request("waited", RequestPriority::Medium, 3600, 601));
+        queue.push(request("fresh", RequestPriority::Medium, 3600, 10));
+
+        let now = Utc::now();
+        let changed = queue.age(now, &config);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].id, "waited");
+        assert_eq!(changed[0].priority, RequestPriority::High);
+        assert!(changed[0].priority_reason.as_deref().unwrap().contains("medium priority SLA (600s)"));
+
+        // The clock restarts at the new level, so nothing changes until the high SLA runs out
+        assert!(queue.age(now + Duration::seconds(179), &config).is_empty());
+        let entry = queue.snapshot(now, &config).into_iter().find(|e| e.request.id == "waited").unwrap();
+        assert_eq!(entry.escalations, 1);
+        assert_eq!(entry.sla_remaining_seconds, 180);
+
+        let changed = queue.age(now + Duration::seconds(180), &config);
+        assert_eq!(changed[0].priority, RequestPriority::Critical);
+    }
+
+    #[test]
+    fn critical_requests_are_flagged_once_when_their_sla_runs_out() {
+        let config = QueueConfig::default();
+        let mut queue = RequestQueue::new();
+        queue.push(request("r1", RequestPriority::Critical, 3600, 61));
+
+        let now = Utc::now();
+        assert_eq!(queue.age(now, &config).len(), 1);
+        assert!(queue.age(now + Duration::seconds(120), &config).is_empty());
+
+        let entry = &queue.snapshot(now, &config)[0];
+        assert_eq!(entry.request.priority, RequestPriority::Critical);
+        assert!(entry.sla_breached);
+        assert_eq!(entry.escalations, 0);
+    }
+
+    #[test]
+    fn resolved_requests_are_neither_aged_nor_kept_past_retention() {
+        let config = QueueConfig::default();
+        let mut queue = RequestQueue::new();
+        queue.push(request("done", RequestPriority::Low, 3600, 7200));
+        queue.push(request("pending", RequestPriority::Low, 3600, 0));
+        queue.mark_resolved("done", RequestStatus::Timeout, None);
+
+        assert!(queue.age(Utc::now(), &config).is_empty());
+        queue.prune(Utc::now(), &config);
+        assert!(queue.get("done").is_some());
+        queue.prune(Utc::now() + Duration::seconds(601), &config);
+        assert!(queue.get("done").is_none());
+        assert!(queue.get("pending").is_some());
+    }
+
+    #[test]
+    fn ids_stay_known_until_pruned() {
+        let config = QueueConfig::default();
+        let mut queue = RequestQueue::new();
+        queue.push(request("r1", RequestPriority::Medium, 300, 0));
+        assert!(queue.contains("r1"));
+        assert!(!queue.contains("r2"));
+
+        queue.mark_resolved("r1", RequestStatus::Completed, Some("alice".to_string()));
+        assert!(queue.contains("r1"));
+        queue.prune(Utc::now() + Duration::seconds(601), &config);
+        assert!(!queue.contains("r1"));
+    }
+
+    #[test]
+    fn claims_lock_out_other_operators_until_released() {
+        let mut queue = claimed_queue();
+        assert!(matches!(queue.claim("r1", "bob", None), Err(QueueError::ClaimedBy(op)) if op == "alice"));
+        assert!(matches!(queue.release("r1", "bob"), Err(QueueError::ClaimedBy(_))));
+
+        let response = HumanResponse::for_test("r1", serde_json::json!("approve"), "bob");
+        assert!(matches!(queue.check_answerable(&response), Err(QueueError::ClaimedBy(_))));
+        assert!(queue.check_answerable(&HumanResponse::for_test("r1", serde_json::json!("approve"), "alice")).is_ok());
+
+        queue.release("r1", "alice").unwrap();
+        assert!(matches!(queue.release("r1", "alice"), Err(QueueError::NotClaimed(_))));
+        assert_eq!(queue.claim("r1", "bob", Some("gui-2")).unwrap().claimed_by.unwrap().operator, "bob");
+        assert_eq!(queue.release_client("gui-2").len(), 1);
+        assert!(queue.check_answerable(&response).is_ok());
+    }
+
+    #[test]
+    fn claims_do_not_block_votes_on_quorum_requests() {
+        let mut queue = RequestQueue::new();
+        let mut quorum = request("r1", RequestPriority::Medium, 300, 0);
+        quorum.required_approvers = Some(2);
+        queue.push(quorum);
+        queue.claim("r1", "alice", None).unwrap();
+
+        let response = HumanResponse::for_test("r1", serde_json::json!("approve"), "bob");
+        assert!(queue.check_answerable(&response).is_ok());
+    }
+
+    #[test]
+    fn each_operator_gets_one_vote() {
+        let mut queue = RequestQueue::new();
+        let mut quorum = request("r1", RequestPriority::Medium, 300, 0);
+        quorum.required_approvers = Some(3);
+        queue.push(quorum);
+
+        queue.record_approval("r1", approval("alice", true)).unwrap();
+        assert!(matches!(queue.record_approval("r1", approval("alice", false)), Err(QueueError::AlreadyVoted(op)) if op == "alice"));
+        queue.record_approval("r1", approval("bob", false)).unwrap();
+        let request = queue.record_approval("r1", approval("carol", true)).unwrap();
+
+        assert_eq!(request.approvals.len(), 3);
+        let approvers: Vec<&str> = request.approvers().iter().map(|a| a.operator.as_str()).collect();
+        assert_eq!(approvers, ["alice", "carol"]);
+        assert!((approvers.len() as u32) < request.quorum());
+
+        queue.mark_resolved("r1", RequestStatus::Completed, Some("alice, carol".to_string()));
+        assert!(matches!(queue.record_approval("r1", approval("dave", true)), Err(QueueError::NotPending(_))));
+        let late = HumanResponse::for_test("r1", serde_json::json!("approve"), "dave");
+        assert!(matches!(queue.check_answerable(&late), Err(QueueError::AlreadyAnswered(by)) if by == "alice, carol"));
+    }
+}
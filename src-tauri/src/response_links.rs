@@ -0,0 +1,335 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::access::{AccessControl, Permission};
+use crate::models::{is_reserved_name, HumanInputRequest, RequestType};
+
+/// The `response_links` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseLinkConfig {
+    pub enabled: bool,
+    /// Address the links point at, e.g. a reverse proxy in front of the HUD's WebSocket
+    /// port. Required when links are enabled.
+    pub base_url: Option<String>,
+    /// Key the links are signed with. Without one, a key is generated at startup and links
+    /// stop working when the HUD restarts.
+    pub secret: Option<String>,
+    /// How long links stay valid; by default until the request times out
+    pub ttl_seconds: Option<u64>,
+    /// The operator answers given through a link are attributed to. It must be allowed to
+    /// answer requests: list it in `access.operators` as a responder, or raise the default role.
+    pub operator: String,
+}
+
+impl ResponseLinkConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.base_url.as_deref().is_none_or(|url| url.trim().is_empty()) {
+            return Err("response links are enabled but no base_url is set".to_string());
+        }
+        if is_reserved_name(&self.operator) {
+            return Err(format!("response link operator '{}' is a reserved name", self.operator));
+        }
+        Ok(())
+    }
+
+    /// Checks that the link operator may answer requests, since otherwise every link that
+    /// goes out would be refused when followed
+    pub fn check_access(&self, access: &AccessControl) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        access.authorize(&self.operator, Permission::Respond, None)
+            .map_err(|e| format!("response links are enabled, but {}", e))
+    }
+}
+
+impl Default for ResponseLinkConfig {
+    fn default() -> Self {
+        ResponseLinkConfig {
+            enabled: false,
+            base_url: None,
+            secret: None,
+            ttl_seconds: None,
+            operator: "link".to_string(),
+        }
+    }
+}
+
+/// What following a link answers
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkAction {
+    Approve,
+    Deny,
+    Choice(String),
+}
+
+/// Links for answering one request without the desktop app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLinks {
+    pub approve: Option<String>,
+    pub deny: Option<String>,
+    pub choices: Vec<ChoiceLink>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChoiceLink {
+    pub option_id: String,
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    #[error("Response links are disabled")]
+    Disabled,
+
+    #[error("Invalid link: {0}")]
+    Malformed(String),
+
+    #[error("Link signature doesn't match")]
+    BadSignature,
+
+    #[error("Link has expired")]
+    Expired,
+
+    #[error("A link for this request has already been used")]
+    AlreadyUsed,
+}
+
+/// Signs and checks links that answer a request. Opening a link only shows a confirmation
+/// page; the answer is given by posting the link's parameters back. Every link for a request
+/// is spent as soon as one of them is used, which the database keeps track of.
+pub struct ResponseLinks {
+    config: ResponseLinkConfig,
+    generated_secret: String,
+}
+
+impl ResponseLinks {
+    pub fn new(config: ResponseLinkConfig) -> Self {
+        ResponseLinks {
+            config,
+            generated_secret: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        }
+    }
+
+    /// Applies a reloaded config. The generated key is kept, so links already sent out
+    /// keep working unless a key has been configured since.
+    pub fn set_config(&mut self, config: ResponseLinkConfig) {
+        self.config = config;
+    }
+
+    pub fn operator(&self) -> &str {
+        &self.config.operator
+    }
+
+    fn mac(&self, request_id: &str, action: &str, option: &str, expires: i64) -> Hmac<Sha256> {
+        let secret = self.config.secret.as_deref().unwrap_or(&self.generated_secret);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}\n{}\n{}\n{}", request_id, action, option, expires).as_bytes());
+        mac
+    }
+
+    fn url(&self, base_url: &str, request_id: &str, action: &str, option: &str, expires: i64) -> String {
+        let signature: String = self.mac(request_id, action, option, expires)
+            .finalize().into_bytes().iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let mut url = format!("{}/respond/{}?action={}&expires={}&sig={}",
+            base_url.trim_end_matches('/'), encode(request_id), action, expires, signature);
+        if !option.is_empty() {
+            url.push_str(&format!("&option={}", encode(option)));
+        }
+        url
+    }
+
+    /// Links for a request, or `None` when links are disabled or the request needs more than
+    /// a click to answer
+    pub fn links_for(&self, request: &HumanInputRequest) -> Option<RequestLinks> {
+        let base_url = self.config.base_url.as_deref().filter(|_| self.config.enabled)?;
+        let ttl = self.config.ttl_seconds.unwrap_or(request.timeout_seconds as u64);
+        let expires_at = request.timestamp + Duration::seconds(ttl as i64);
+        let expires = expires_at.timestamp();
+
+        match request.request_type {
+            RequestType::Approval | RequestType::Confirmation => Some(RequestLinks {
+                approve: Some(self.url(base_url, &request.id, "approve", "", expires)),
+                deny: Some(self.url(base_url, &request.id, "deny", "", expires)),
+                choices: Vec::new(),
+                expires_at,
+            }),
+            RequestType::Choice => Some(RequestLinks {
+                approve: None,
+                deny: None,
+                choices: request.options.iter().map(|o| ChoiceLink {
+                    option_id: o.id.clone(),
+                    label: o.label.clone(),
+                    url: self.url(base_url, &request.id, "choice", &o.id, expires),
+                }).collect(),
+                expires_at,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Checks a link's signature and expiry and returns what it answers. Whether the link has
+    /// been used is up to the caller.
+    pub fn verify(&self, request_id: &str, params: &HashMap<String, String>) -> Result<LinkAction, LinkError> {
+        if !self.config.enabled {
+            return Err(LinkError::Disabled);
+        }
+
+        let param = |name: &str| params.get(name).map(|s| s.as_str())
+            .ok_or_else(|| LinkError::Malformed(format!("missing '{}'", name)));
+        let action = param("action")?;
+        let option = params.get("option").map(|s| s.as_str()).unwrap_or("");
+        let expires: i64 = param("expires")?.parse()
+            .map_err(|_| LinkError::Malformed("bad expiry".to_string()))?;
+        let signature = decode_hex(param("sig")?)
+            .ok_or_else(|| LinkError::Malformed("bad signature".to_string()))?;
+
+        self.mac(request_id, action, option, expires)
+            .verify_slice(&signature)
+            .map_err(|_| LinkError::BadSignature)?;
+        if Utc::now().timestamp() > expires {
+            return Err(LinkError::Expired);
+        }
+
+        match action {
+            "approve" => Ok(LinkAction::Approve),
+            "deny" => Ok(LinkAction::Deny),
+            "choice" if !option.is_empty() => Ok(LinkAction::Choice(option.to_string())),
+            other => Err(LinkError::Malformed(format!("unknown action '{}'", other))),
+        }
+    }
+}
+
+fn encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len()).step_by(2)
+        .map(|i| value.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links() -> ResponseLinks {
+        ResponseLinks::new(ResponseLinkConfig {
+            enabled: true,
+            base_url: Some("https://hud.example.com/".to_string()),
+            secret: Some("test-secret".to_string()),
+            ..ResponseLinkConfig::default()
+        })
+    }
+
+    /// The query parameters of a link, as the confirmation page posts them back
+    fn params_of(url: &str) -> HashMap<String, String> {
+        let (_, query) = url.split_once('?').unwrap();
+        query.split('&')
+            .map(|pair| pair.split_once('=').unwrap())
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn approval_links_round_trip() {
+        let links = links();
+        let request = HumanInputRequest::for_test("r1", RequestType::Approval, &["approve", "reject"]);
+        let generated = links.links_for(&request).unwrap();
+        let approve = generated.approve.unwrap();
+        assert!(approve.starts_with("https://hud.example.com/respond/r1?action=approve&"));
+
+        assert_eq!(links.verify("r1", &params_of(&approve)).unwrap(), LinkAction::Approve);
+        assert_eq!(links.verify("r1", &params_of(&generated.deny.unwrap())).unwrap(), LinkAction::Deny);
+    }
+
+    #[test]
+    fn choice_links_carry_the_option_id() {
+        let links = links();
+        let request = HumanInputRequest::for_test("r1", RequestType::Choice, &["red", "blue"]);
+        let generated = links.links_for(&request).unwrap();
+        assert_eq!(generated.choices.len(), 2);
+        assert_eq!(links.verify("r1", &params_of(&generated.choices[1].url)).unwrap(), LinkAction::Choice("blue".to_string()));
+    }
+
+    #[test]
+    fn tampered_links_are_rejected() {
+        let links = links();
+        let request = HumanInputRequest::for_test("r1", RequestType::Approval, &[]);
+        let mut params = params_of(&links.links_for(&request).unwrap().deny.unwrap());
+
+        assert!(matches!(links.verify("r2", &params), Err(LinkError::BadSignature)));
+        params.insert("action".to_string(), "approve".to_string());
+        assert!(matches!(links.verify("r1", &params), Err(LinkError::BadSignature)));
+        params.remove("sig");
+        assert!(matches!(links.verify("r1", &params), Err(LinkError::Malformed(_))));
+    }
+
+    #[test]
+    fn expired_links_are_rejected() {
+        let links = links();
+        let mut request = HumanInputRequest::for_test("r1", RequestType::Approval, &[]);
+        request.timestamp = Utc::now() - Duration::seconds(request.timeout_seconds as i64 + 60);
+        let params = params_of(&links.links_for(&request).unwrap().approve.unwrap());
+        assert!(matches!(links.verify("r1", &params), Err(LinkError::Expired)));
+    }
+
+    #[test]
+    fn enabled_links_need_a_base_url() {
+        let config = ResponseLinkConfig { enabled: true, ..ResponseLinkConfig::default() };
+        assert!(config.validate().is_err());
+        assert!(ResponseLinkConfig::default().validate().is_ok());
+        assert!(ResponseLinkConfig { operator: "system:links".to_string(), ..ResponseLinkConfig::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn enabled_links_need_an_operator_that_may_answer() {
+        use crate::access::{AccessConfig, OperatorAccess, Role};
+
+        let config = ResponseLinkConfig { enabled: true, ..ResponseLinkConfig::default() };
+        let access = |operators: Vec<OperatorAccess>| {
+            AccessControl::new(AccessConfig { operators, ..AccessConfig::default() }, "desktop-token".to_string()).unwrap()
+        };
+        let link_operator = |role| OperatorAccess {
+            name: "link".to_string(),
+            role,
+            token: "link-token".to_string(),
+            agents: Vec::new(),
+            tags: Vec::new(),
+        };
+
+        assert!(config.check_access(&access(Vec::new())).is_err());
+        assert!(config.check_access(&access(vec![link_operator(Role::Viewer)])).is_err());
+        assert!(config.check_access(&access(vec![link_operator(Role::Responder)])).is_ok());
+        assert!(ResponseLinkConfig::default().check_access(&access(Vec::new())).is_ok());
+    }
+
+    #[tokio::test]
+    async fn links_are_spent_once_in_the_database() {
+        let database = crate::database::Database::new().await.unwrap();
+        assert!(!database.response_link_used("r1").await.unwrap());
+        assert!(database.consume_response_link("r1").await.unwrap());
+        assert!(!database.consume_response_link("r1").await.unwrap());
+        assert!(database.response_link_used("r1").await.unwrap());
+
+        database.restore_response_link("r1").await.unwrap();
+        assert!(database.consume_response_link("r1").await.unwrap());
+    }
+}
@@ -0,0 +1,538 @@
+use regex::Regex;
+use serde_json::Value;
+use crate::models::*;
+
+/// Checks human responses against the request they answer before they reach the agent
+pub struct ResponseValidator;
+
+impl ResponseValidator {
+    /// Checks that a request is well-formed when it is created
+    pub fn validate_request(request: &HumanInputRequest) -> Result<(), ValidationError> {
+        if request.on_timeout == Some(TimeoutPolicy::Approve) && request.priority != RequestPriority::Low {
+            return Err(ValidationError::InvalidRequest(
+                "auto-approve on timeout is only allowed for low priority requests".to_string(),
+            ));
+        }
+
+        if request.quorum() > 1 {
+            if !request.supports_quorum() {
+                return Err(ValidationError::InvalidRequest(
+                    "multiple approvers are only supported for approval and confirmation requests".to_string(),
+                ));
+            }
+            if request.on_timeout == Some(TimeoutPolicy::Approve) {
+                return Err(ValidationError::InvalidRequest(
+                    "requests that need several approvers cannot be auto-approved on timeout".to_string(),
+                ));
+            }
+        }
+        
+        for (index, option) in request.options.iter().enumerate() {
+            if option.id.is_empty() {
+                return Err(ValidationError::InvalidRequest("option ids must not be empty".to_string()));
+            }
+            if request.options[..index].iter().any(|o| o.id == option.id) {
+                return Err(ValidationError::InvalidRequest(format!("duplicate option id '{}'", option.id)));
+            }
+        }
+
+        match request.request_type {
+            RequestType::Form => {
+                let schema = request.schema.as_ref()
+                    .ok_or_else(|| ValidationError::InvalidRequest("form requests require a schema".to_string()))?;
+                FormSchema::check(schema)?;
+            }
+            RequestType::MultiChoice | RequestType::Ranking => {
+                if request.options.is_empty() {
+                    return Err(ValidationError::InvalidRequest("options are required".to_string()));
+                }
+                let (min, max) = Self::selection_bounds(request);
+                if min > max || max as usize > request.options.len() {
+                    return Err(ValidationError::InvalidRequest(format!(
+                        "selection bounds {}..={} do not fit {} options", min, max, request.options.len()
+                    )));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Validates `response` for `request`, returning the value that should be delivered to the agent
+    pub fn validate_response(request: &HumanInputRequest, response: &Value) -> Result<Value, ValidationError> {
+        match request.request_type {
+            RequestType::Form => {
+                // GUIs may submit the form as a JSON-encoded string
+                let value = match response {
+                    Value::String(text) => serde_json::from_str(text)
+                        .map_err(|e| ValidationError::InvalidResponse(format!("form response is not JSON: {}", e)))?,
+                    other => other.clone(),
+                };
+
+                if let Some(schema) = &request.schema {
+                    FormSchema::validate(schema, &value)?;
+                }
+                Ok(value)
+            }
+            RequestType::Choice if !request.options.is_empty() => {
+                let answer = response.as_str()
+                    .ok_or_else(|| ValidationError::InvalidResponse("expected one of the options".to_string()))?;
+                let option = Self::find_option(request, answer)
+                    .ok_or_else(|| ValidationError::InvalidResponse(format!("'{}' is not one of the options", answer)))?;
+                Ok(Value::from(option.id.clone()))
+            }
+            RequestType::MultiChoice => {
+                let selected = Self::option_list(request, response)?;
+                let (min, max) = Self::selection_bounds(request);
+                let count = selected.len() as u32;
+                if count < min || count > max {
+                    return Err(ValidationError::InvalidResponse(format!(
+                        "expected between {} and {} selections, got {}", min, max, count
+                    )));
+                }
+                Ok(Value::from(selected))
+            }
+            RequestType::Ranking => {
+                let ranked = Self::option_list(request, response)?;
+                if ranked.len() != request.options.len() {
+                    return Err(ValidationError::InvalidResponse(format!(
+                        "ranking must order all {} options, got {}", request.options.len(), ranked.len()
+                    )));
+                }
+                Ok(Value::from(ranked))
+            }
+            // Free-form answers still map onto an option id when they name one
+            _ => match response.as_str().and_then(|answer| Self::find_option(request, answer)) {
+                Some(option) => Ok(Value::from(option.id.clone())),
+                None => Ok(response.clone()),
+            },
+        }
+    }
+
+    /// Looks an answer up by option id. Options an agent sent as bare strings use the string
+    /// as both id and label, so answering those with the label still works.
+    fn find_option<'a>(request: &'a HumanInputRequest, answer: &str) -> Option<&'a RequestOption> {
+        request.options.iter().find(|o| o.id == answer)
+    }
+
+    /// Minimum and maximum number of selections for a `MultiChoice` request. Rankings always
+    /// cover every option.
+    fn selection_bounds(request: &HumanInputRequest) -> (u32, u32) {
+        let option_count = request.options.len() as u32;
+        match request.request_type {
+            RequestType::Ranking => (option_count, option_count),
+            _ => (
+                request.min_selections.unwrap_or(1),
+                request.max_selections.unwrap_or(option_count),
+            ),
+        }
+    }
+
+    /// Parses a response made of distinct options, keeping its order and returning option ids
+    fn option_list(request: &HumanInputRequest, response: &Value) -> Result<Vec<String>, ValidationError> {
+        let parsed;
+        let response = match response {
+            Value::String(text) => {
+                parsed = serde_json::from_str::<Value>(text)
+                    .map_err(|_| ValidationError::InvalidResponse("expected a list of options".to_string()))?;
+                &parsed
+            }
+            other => other,
+        };
+
+        let items = response.as_array()
+            .ok_or_else(|| ValidationError::InvalidResponse("expected a list of options".to_string()))?;
+
+        let mut selected: Vec<String> = Vec::with_capacity(items.len());
+        for item in items {
+            let answer = item.as_str()
+                .ok_or_else(|| ValidationError::InvalidResponse(format!("{} is not an option", item)))?;
+            let option = Self::find_option(request, answer)
+                .ok_or_else(|| ValidationError::InvalidResponse(format!("'{}' is not one of the options", answer)))?;
+            if selected.contains(&option.id) {
+                return Err(ValidationError::InvalidResponse(format!("'{}' was selected more than once", answer)));
+            }
+            selected.push(option.id.clone());
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Validator for the subset of JSON Schema used to describe forms: `type`, `enum`, `const`,
+/// `properties`, `required`, `additionalProperties`, `items`, `minItems`, `maxItems`,
+/// `uniqueItems`, `minLength`, `maxLength`, `pattern`, `minimum`, `maximum`,
+/// `exclusiveMinimum` and `exclusiveMaximum`, plus annotations such as `title`. Schemas using
+/// any other keyword are rejected when the request arrives, rather than the keyword being
+/// silently ignored when the response is checked.
+pub struct FormSchema;
+
+const SUPPORTED_KEYWORDS: [&str; 17] = [
+    "type", "enum", "const", "properties", "required", "additionalProperties", "items",
+    "minItems", "maxItems", "uniqueItems", "minLength", "maxLength", "pattern",
+    "minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum",
+];
+
+/// Keywords that only describe the field and never make a value invalid
+const ANNOTATION_KEYWORDS: [&str; 11] = [
+    "$schema", "$id", "$comment", "title", "description", "default", "examples",
+    "format", "readOnly", "writeOnly", "deprecated",
+];
+
+const TYPE_NAMES: [&str; 7] = ["object", "array", "string", "boolean", "null", "number", "integer"];
+
+impl FormSchema {
+    pub fn check(schema: &Value) -> Result<(), ValidationError> {
+        Self::check_at(schema, "")
+    }
+
+    fn check_at(schema: &Value, path: &str) -> Result<(), ValidationError> {
+        let at = if path.is_empty() { "/" } else { path };
+        let invalid = |message: String| Err(ValidationError::InvalidSchema(format!("{}: {}", at, message)));
+        let Some(keywords) = schema.as_object() else {
+            return invalid("schema must be a JSON object".to_string());
+        };
+
+        for (keyword, value) in keywords {
+            if !SUPPORTED_KEYWORDS.contains(&keyword.as_str()) && !ANNOTATION_KEYWORDS.contains(&keyword.as_str()) {
+                return invalid(format!("unsupported keyword '{}'", keyword));
+            }
+            let well_formed = match keyword.as_str() {
+                "type" => match value {
+                    Value::String(name) => TYPE_NAMES.contains(&name.as_str()),
+                    Value::Array(names) => names.iter().all(|n| n.as_str().is_some_and(|n| TYPE_NAMES.contains(&n))),
+                    _ => false,
+                },
+                "enum" => value.is_array(),
+                "properties" | "items" => value.is_object(),
+                "required" => value.as_array().is_some_and(|names| names.iter().all(|n| n.is_string())),
+                "additionalProperties" => value.is_object() || value.is_boolean(),
+                "minItems" | "maxItems" | "minLength" | "maxLength" => value.is_u64(),
+                "uniqueItems" => value.is_boolean(),
+                "pattern" => value.is_string(),
+                "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" => value.is_number(),
+                _ => true,
+            };
+            if !well_formed {
+                return invalid(format!("invalid value for '{}': {}", keyword, value));
+            }
+        }
+
+        if let Some(pattern) = schema["pattern"].as_str() {
+            if let Err(e) = Regex::new(pattern) {
+                return invalid(format!("invalid pattern {}: {}", pattern, e));
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (name, property) in properties {
+                Self::check_at(property, &format!("{}/properties/{}", path, name))?;
+            }
+        }
+        if schema["items"].is_object() {
+            Self::check_at(&schema["items"], &format!("{}/items", path))?;
+        }
+        if schema["additionalProperties"].is_object() {
+            Self::check_at(&schema["additionalProperties"], &format!("{}/additionalProperties", path))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(schema: &Value, value: &Value) -> Result<(), ValidationError> {
+        let mut errors = Vec::new();
+        Self::validate_at(schema, value, "", &mut errors);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError::SchemaMismatch(errors))
+        }
+    }
+
+    fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+        let at = if path.is_empty() { "/" } else { path };
+
+        if let Some(expected) = schema.get("type") {
+            let matches = match expected {
+                Value::String(name) => Self::has_type(value, name),
+                Value::Array(names) => names.iter().filter_map(|n| n.as_str()).any(|n| Self::has_type(value, n)),
+                _ => true,
+            };
+            if !matches {
+                errors.push(format!("{}: expected type {}", at, expected));
+                return;
+            }
+        }
+
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                errors.push(format!("{}: must be one of {}", at, schema["enum"]));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                errors.push(format!("{}: must equal {}", at, expected));
+            }
+        }
+
+        match value {
+            Value::Object(fields) => {
+                if let Some(required) = schema["required"].as_array() {
+                    for name in required.iter().filter_map(|n| n.as_str()) {
+                        if !fields.contains_key(name) {
+                            errors.push(format!("{}: missing required field '{}'", at, name));
+                        }
+                    }
+                }
+
+                let properties = schema["properties"].as_object();
+                for (name, field) in fields {
+                    let field_path = format!("{}/{}", path, name);
+                    match properties.and_then(|p| p.get(name)) {
+                        Some(field_schema) => Self::validate_at(field_schema, field, &field_path, errors),
+                        None => match &schema["additionalProperties"] {
+                            Value::Bool(false) => errors.push(format!("{}: unexpected field", field_path)),
+                            extra @ Value::Object(_) => Self::validate_at(extra, field, &field_path, errors),
+                            _ => {}
+                        },
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min) = schema["minItems"].as_u64() {
+                    if (items.len() as u64) < min {
+                        errors.push(format!("{}: expected at least {} items", at, min));
+                    }
+                }
+                if let Some(max) = schema["maxItems"].as_u64() {
+                    if (items.len() as u64) > max {
+                        errors.push(format!("{}: expected at most {} items", at, max));
+                    }
+                }
+                if schema["uniqueItems"].as_bool() == Some(true) {
+                    for (index, item) in items.iter().enumerate() {
+                        if items[..index].contains(item) {
+                            errors.push(format!("{}/{}: duplicate item", path, index));
+                        }
+                    }
+                }
+                if schema["items"].is_object() {
+                    for (index, item) in items.iter().enumerate() {
+                        Self::validate_at(&schema["items"], item, &format!("{}/{}", path, index), errors);
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = schema["minLength"].as_u64() {
+                    if length < min {
+                        errors.push(format!("{}: expected at least {} characters", at, min));
+                    }
+                }
+                if let Some(max) = schema["maxLength"].as_u64() {
+                    if length > max {
+                        errors.push(format!("{}: expected at most {} characters", at, max));
+                    }
+                }
+                if let Some(pattern) = schema["pattern"].as_str() {
+                    if let Ok(regex) = Regex::new(pattern) {
+                        if !regex.is_match(text) {
+                            errors.push(format!("{}: does not match pattern {}", at, pattern));
+                        }
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                if let Some(min) = schema["minimum"].as_f64() {
+                    if number < min {
+                        errors.push(format!("{}: must be >= {}", at, min));
+                    }
+                }
+                if let Some(max) = schema["maximum"].as_f64() {
+                    if number > max {
+                        errors.push(format!("{}: must be <= {}", at, max));
+                    }
+                }
+                if let Some(min) = schema["exclusiveMinimum"].as_f64() {
+                    if number <= min {
+                        errors.push(format!("{}: must be > {}", at, min));
+                    }
+                }
+                if let Some(max) = schema["exclusiveMaximum"].as_f64() {
+                    if number >= max {
+                        errors.push(format!("{}: must be < {}", at, max));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn has_type(value: &Value, name: &str) -> bool {
+        match name {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ValidationError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Invalid schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("Response does not match schema: {}", .0.join("; "))]
+    SchemaMismatch(Vec<String>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn form_schema() -> Value {
+        json!({
+            "title": "Deploy",
+            "type": "object",
+            "required": ["environment"],
+            "additionalProperties": false,
+            "properties": {
+                "environment": { "type": "string", "enum": ["staging", "production"] },
+                "replicas": { "type": "integer", "minimum": 1, "maximum": 10 },
+                "tags": { "type": "array", "items": { "type": "string", "pattern": "^[a-z]+$" }, "uniqueItems": true }
+            }
+        })
+    }
+
+    fn choice_request() -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test("r1", RequestType::Choice, &["a"]);
+        request.options = serde_json::from_value(json!([
+            { "id": "deploy", "label": "Deploy now" },
+            "Wait"
+        ])).unwrap();
+        request
+    }
+
+    #[test]
+    fn choices_are_answered_by_option_id() {
+        let request = choice_request();
+        assert_eq!(ResponseValidator::validate_response(&request, &json!("deploy")).unwrap(), json!("deploy"));
+        assert!(ResponseValidator::validate_response(&request, &json!("Deploy now")).is_err());
+    }
+
+    #[test]
+    fn bare_string_options_match_their_label() {
+        let request = choice_request();
+        assert_eq!(ResponseValidator::validate_response(&request, &json!("Wait")).unwrap(), json!("Wait"));
+    }
+
+    fn multi_choice(min: Option<u32>, max: Option<u32>) -> HumanInputRequest {
+        let mut request = HumanInputRequest::for_test("r1", RequestType::MultiChoice, &["a", "b", "c"]);
+        request.min_selections = min;
+        request.max_selections = max;
+        request
+    }
+
+    #[test]
+    fn multi_choice_selections_respect_the_bounds() {
+        let request = multi_choice(Some(2), Some(2));
+        assert!(ResponseValidator::validate_request(&request).is_ok());
+        assert_eq!(ResponseValidator::validate_response(&request, &json!(["c", "a"])).unwrap(), json!(["c", "a"]));
+        assert!(ResponseValidator::validate_response(&request, &json!(["a"])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!(["a", "b", "c"])).is_err());
+
+        // Without bounds, anything from one option to all of them goes
+        let request = multi_choice(None, None);
+        assert!(ResponseValidator::validate_response(&request, &json!([])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!(["b"])).is_ok());
+        assert!(ResponseValidator::validate_response(&request, &json!("[\"a\",\"b\",\"c\"]")).is_ok());
+    }
+
+    #[test]
+    fn multi_choice_selections_must_be_distinct_options() {
+        let request = multi_choice(None, None);
+        assert!(ResponseValidator::validate_response(&request, &json!(["a", "a"])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!(["a", "z"])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!(["A"])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!([1])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!("a")).is_err());
+    }
+
+    #[test]
+    fn multi_choice_bounds_must_fit_the_options() {
+        assert!(ResponseValidator::validate_request(&multi_choice(Some(3), Some(2))).is_err());
+        assert!(ResponseValidator::validate_request(&multi_choice(None, Some(4))).is_err());
+        let mut empty = multi_choice(None, None);
+        empty.options.clear();
+        assert!(ResponseValidator::validate_request(&empty).is_err());
+    }
+
+    #[test]
+    fn rankings_order_every_option_once() {
+        let mut request = multi_choice(Some(1), Some(1));
+        request.request_type = RequestType::Ranking;
+
+        assert_eq!(ResponseValidator::validate_response(&request, &json!(["b", "c", "a"])).unwrap(), json!(["b", "c", "a"]));
+        assert!(ResponseValidator::validate_response(&request, &json!(["b", "c"])).is_err());
+        assert!(ResponseValidator::validate_response(&request, &json!(["b", "c", "b"])).is_err());
+    }
+
+    #[test]
+    fn supported_schemas_pass_the_check() {
+        assert!(FormSchema::check(&form_schema()).is_ok());
+    }
+
+    #[test]
+    fn unsupported_keywords_are_rejected() {
+        for schema in [
+            json!({ "type": "object", "oneOf": [] }),
+            json!({ "properties": { "name": { "type": "string", "format": "email", "minProperties": 1 } } }),
+            json!({ "type": "array", "items": { "$ref": "#/definitions/item" } }),
+        ] {
+            assert!(matches!(FormSchema::check(&schema), Err(ValidationError::InvalidSchema(_))), "{}", schema);
+        }
+    }
+
+    #[test]
+    fn malformed_keywords_are_rejected() {
+        for schema in [
+            json!({ "type": "text" }),
+            json!({ "items": [{ "type": "string" }] }),
+            json!({ "required": "name" }),
+            json!({ "minimum": "1" }),
+            json!({ "pattern": "(" }),
+            json!({ "properties": { "name": true } }),
+        ] {
+            assert!(FormSchema::check(&schema).is_err(), "{}", schema);
+        }
+    }
+
+    #[test]
+    fn responses_are_checked_against_the_schema() {
+        let schema = form_schema();
+        assert!(FormSchema::validate(&schema, &json!({ "environment": "staging", "replicas": 3, "tags": ["web"] })).is_ok());
+
+        let Err(ValidationError::SchemaMismatch(errors)) = FormSchema::validate(&schema, &json!({
+            "replicas": 0,
+            "tags": ["web", "web", "API"],
+            "extra": 1
+        })) else {
+            panic!("expected a schema mismatch");
+        };
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use std::io::{BufRead, Write};
+use crate::models::*;
+
+pub const ARCHIVE_FORMAT: &str = "agent-hud-session";
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// Message types that are stored as content emissions rather than plain agent messages
+pub const CONTENT_MESSAGE_TYPES: &[&str] = &["markdown-content", "code-content", "image-content"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub label: Option<String>,
+    pub filter: ExportFilter,
+}
+
+/// One line of a session archive. The first line of every file is a `Header`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "data", rename_all = "kebab-case")]
+pub enum ArchiveRecord {
+    Header(ArchiveHeader),
+    Agent(Agent),
+    Message(AgentMessage),
+    Content(AgentMessage),
+    Request(Box<HumanInputRequest>),
+    Response(HumanResponse),
+}
+
+impl ArchiveRecord {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ArchiveRecord::Header(_) => "header",
+            ArchiveRecord::Agent(_) => "agent",
+            ArchiveRecord::Message(_) => "message",
+            ArchiveRecord::Content(_) => "content",
+            ArchiveRecord::Request(_) => "request",
+            ArchiveRecord::Response(_) => "response",
+        }
+    }
+
+    pub fn agent_id(&self) -> Option<&str> {
+        match self {
+            ArchiveRecord::Header(_) | ArchiveRecord::Response(_) => None,
+            ArchiveRecord::Agent(agent) => Some(&agent.id),
+            ArchiveRecord::Message(message) | ArchiveRecord::Content(message) => Some(&message.agent_id),
+            ArchiveRecord::Request(request) => Some(&request.agent_id),
+        }
+    }
+
+    /// Time the recorded event originally happened, used to order records on import and replay
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            ArchiveRecord::Header(header) => header.exported_at,
+            ArchiveRecord::Agent(agent) => agent.connected_at,
+            ArchiveRecord::Message(message) | ArchiveRecord::Content(message) => message.timestamp,
+            ArchiveRecord::Request(request) => request.timestamp,
+            ArchiveRecord::Response(response) => response.timestamp,
+        }
+    }
+
+    pub fn from_message(message: AgentMessage) -> Self {
+        if CONTENT_MESSAGE_TYPES.contains(&message.message_type.as_str()) {
+            ArchiveRecord::Content(message)
+        } else {
+            ArchiveRecord::Message(message)
+        }
+    }
+}
+
+/// Reader and writer for the versioned JSONL session archive format
+pub struct SessionArchive;
+
+impl SessionArchive {
+    pub fn write<W: Write>(
+        writer: &mut W,
+        header: ArchiveHeader,
+        records: &[ArchiveRecord],
+    ) -> Result<usize, ArchiveError> {
+        let header = ArchiveRecord::Header(header);
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+
+        for record in records {
+            writeln!(writer, "{}", serde_json::to_string(record)?)?;
+        }
+
+        writer.flush()?;
+        Ok(records.len())
+    }
+
+    /// Parses an archive, returning its header and the remaining records in chronological order
+    pub fn read<R: BufRead>(reader: R) -> Result<(ArchiveHeader, Vec<ArchiveRecord>), ArchiveError> {
+        let mut lines = reader.lines().enumerate();
+
+        let header = match lines.next() {
+            Some((_, line)) => match serde_json::from_str::<ArchiveRecord>(&line?) {
+                Ok(ArchiveRecord::Header(header)) => header,
+                _ => return Err(ArchiveError::MissingHeader),
+            },
+            None => return Err(ArchiveError::MissingHeader),
+        };
+
+        if header.format != ARCHIVE_FORMAT {
+            return Err(ArchiveError::UnknownFormat(header.format));
+        }
+        if header.version > ARCHIVE_FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(header.version));
+        }
+
+        let mut records = Vec::new();
+        for (index, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: ArchiveRecord = serde_json::from_str(&line)
+                .map_err(|e| ArchiveError::InvalidRecord(index + 1, e.to_string()))?;
+            if matches!(record, ArchiveRecord::Header(_)) {
+                return Err(ArchiveError::InvalidRecord(index + 1, "unexpected header".to_string()));
+            }
+            records.push(record);
+        }
+
+        records.sort_by_key(|r| r.timestamp());
+        Ok((header, records))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Archive is missing its header line")]
+    MissingHeader,
+
+    #[error("Unknown archive format: {0}")]
+    UnknownFormat(String),
+
+    #[error("Unsupported archive version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Invalid record on line {0}: {1}")]
+    InvalidRecord(usize, String),
+}
@@ -0,0 +1,471 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::models::{HumanInputRequest, HumanResponse, RequestStatus, Session, SessionStatus, ToolCall, ToolCallStatus};
+
+/// The `telemetry` section of the config file. Spans are sent as OTLP/HTTP JSON, which
+/// every OpenTelemetry collector accepts on its HTTP receiver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// Full URL of the collector's trace endpoint
+    pub endpoint: String,
+    pub service_name: String,
+    /// Extra HTTP headers, e.g. for collector authentication
+    pub headers: HashMap<String, String>,
+    /// Spans are sent once this many are buffered, or every `flush_interval_seconds`
+    pub batch_size: usize,
+    pub flush_interval_seconds: u64,
+    /// Include request and response bodies and tool call arguments and results as span
+    /// attributes; off by default since they may hold sensitive data
+    pub include_payloads: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            enabled: false,
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "agent-hud".to_string(),
+            headers: HashMap::new(),
+            batch_size: 64,
+            flush_interval_seconds: 5,
+            include_payloads: false,
+        }
+    }
+}
+
+/// A finished span, ready to be encoded for OTLP
+#[derive(Debug, Clone)]
+pub struct TraceSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub attributes: Vec<(String, Value)>,
+    pub events: Vec<SpanEvent>,
+    /// Error message for spans that failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpanEvent {
+    pub name: String,
+    pub time: DateTime<Utc>,
+    pub attributes: Vec<(String, Value)>,
+}
+
+// OTLP ids are derived from our own ids, so spans exported at different times (a tool call
+// before the session it belongs to ends) still link up in the collector
+fn derive_id(seed: &str, bytes: usize) -> String {
+    let digest = Sha256::digest(seed.as_bytes());
+    digest[..bytes].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn trace_id(session_id: Option<&str>, agent_id: &str) -> String {
+    match session_id {
+        Some(session_id) => derive_id(&format!("session:{}", session_id), 16),
+        None => derive_id(&format!("agent:{}", agent_id), 16),
+    }
+}
+
+fn session_span_id(session_id: &str) -> String {
+    derive_id(&format!("session-span:{}", session_id), 8)
+}
+
+fn tool_span_id(span_id: &str) -> String {
+    derive_id(&format!("tool-call:{}", span_id), 8)
+}
+
+fn payload(value: &Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(s.clone()),
+        other => Value::String(other.to_string()),
+    }
+}
+
+/// One span covering a session from start to end
+pub fn session_span(session: &Session) -> TraceSpan {
+    let mut attributes = vec![
+        ("hud.session.id".to_string(), json!(session.id)),
+        ("hud.session.started_by".to_string(), json!(session.started_by)),
+        ("hud.session.status".to_string(), json!(session.status.to_string())),
+    ];
+    if let Some(name) = &session.name {
+        attributes.push(("hud.session.name".to_string(), json!(name)));
+    }
+    if let Some(summary) = &session.summary {
+        attributes.push(("hud.session.summary".to_string(), json!(summary)));
+    }
+
+    TraceSpan {
+        trace_id: derive_id(&format!("session:{}", session.id), 16),
+        span_id: session_span_id(&session.id),
+        parent_span_id: None,
+        name: format!("session {}", session.name.as_deref().unwrap_or(&session.id)),
+        start: session.started_at,
+        end: session.ended_at.unwrap_or_else(Utc::now),
+        attributes,
+        events: Vec::new(),
+        error: (session.status == SessionStatus::Failed)
+            .then(|| session.summary.clone().unwrap_or_else(|| "session failed".to_string())),
+    }
+}
+
+/// A finished tool call, parented to the call that made it or else to its session
+pub fn tool_call_span(call: &ToolCall, include_payloads: bool) -> TraceSpan {
+    let mut attributes = vec![
+        ("hud.agent.id".to_string(), json!(call.agent_id)),
+        ("hud.tool.name".to_string(), json!(call.tool_name)),
+        ("hud.tool.span_id".to_string(), json!(call.span_id)),
+        ("hud.tool.status".to_string(), json!(call.status.to_string())),
+    ];
+    if let Some(duration_ms) = call.duration_ms {
+        attributes.push(("hud.tool.duration_ms".to_string(), json!(duration_ms)));
+    }
+    if include_payloads {
+        if let Some(arguments) = &call.arguments {
+            attributes.push(("hud.tool.arguments".to_string(), payload(arguments)));
+        }
+        if let Some(result) = &call.result {
+            attributes.push(("hud.tool.result".to_string(), payload(result)));
+        }
+    }
+
+    let parent_span_id = match (&call.parent_span_id, &call.session_id) {
+        (Some(parent), _) => Some(tool_span_id(parent)),
+        (None, Some(session_id)) => Some(session_span_id(session_id)),
+        (None, None) => None,
+    };
+    let end = call.ended_at.unwrap_or_else(Utc::now);
+    let start = call.duration_ms
+        .map(|ms| end - chrono::Duration::milliseconds(ms as i64))
+        .unwrap_or(call.started_at);
+
+    TraceSpan {
+        trace_id: trace_id(call.session_id.as_deref(), &call.agent_id),
+        span_id: tool_span_id(&call.span_id),
+        parent_span_id,
+        name: format!("tool {}", call.tool_name),
+        start,
+        end,
+        attributes,
+        events: Vec::new(),
+        error: (call.status == ToolCallStatus::Failed)
+            .then(|| call.error.clone().unwrap_or_else(|| "tool call failed".to_string())),
+    }
+}
+
+/// The time an agent spent waiting on a human, from the request until it was answered,
+/// cancelled or expired. The answer is recorded as a `response` event.
+pub fn human_input_span(
+    request: &HumanInputRequest,
+    response: Option<&HumanResponse>,
+    include_payloads: bool,
+) -> TraceSpan {
+    let end = response.map(|r| r.timestamp).unwrap_or_else(Utc::now);
+    let request_type = serde_json::to_value(&request.request_type)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default();
+
+    let mut attributes = vec![
+        ("hud.agent.id".to_string(), json!(request.agent_id)),
+        ("hud.agent.name".to_string(), json!(request.agent_name)),
+        ("hud.request.id".to_string(), json!(request.id)),
+        ("hud.request.type".to_string(), json!(request_type)),
+        ("hud.request.priority".to_string(), json!(request.priority.to_string())),
+        ("hud.request.status".to_string(), json!(request.status.to_string())),
+        ("hud.request.wait_ms".to_string(), json!((end - request.timestamp).num_milliseconds().max(0))),
+    ];
+    if include_payloads {
+        attributes.push(("hud.request.message".to_string(), json!(request.message)));
+    }
+
+    let events = response.map(|r| {
+        let mut attributes = vec![
+            ("hud.response.responded_by".to_string(), json!(r.responded_by)),
+            ("hud.response.automated".to_string(), json!(r.is_automated())),
+        ];
+        if include_payloads {
+            attributes.push(("hud.response.value".to_string(), payload(&r.response)));
+            if let Some(context) = &r.additional_context {
+                attributes.push(("hud.response.context".to_string(), json!(context)));
+            }
+        }
+        SpanEvent { name: "response".to_string(), time: r.timestamp, attributes }
+    }).into_iter().collect();
+
+    let error = match (&request.status, response) {
+        (RequestStatus::Timeout, None) => Some("request expired without an answer".to_string()),
+        (RequestStatus::Cancelled, _) => Some("request cancelled by the agent".to_string()),
+        _ => None,
+    };
+
+    TraceSpan {
+        trace_id: trace_id(request.session_id.as_deref(), &request.agent_id),
+        span_id: derive_id(&format!("request:{}", request.id), 8),
+        parent_span_id: request.session_id.as_deref().map(session_span_id),
+        name: format!("human-input {}", request_type),
+        start: request.timestamp,
+        end,
+        attributes,
+        events,
+        error,
+    }
+}
+
+fn encode_attributes(attributes: &[(String, Value)]) -> Vec<Value> {
+    attributes.iter().map(|(key, value)| {
+        let value = match value {
+            Value::Bool(b) => json!({ "boolValue": b }),
+            // OTLP JSON carries 64-bit integers as strings
+            Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+            Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+            Value::String(s) => json!({ "stringValue": s }),
+            other => json!({ "stringValue": other.to_string() }),
+        };
+        json!({ "key": key, "value": value })
+    }).collect()
+}
+
+fn unix_nanos(time: &DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().max(0).to_string()
+}
+
+/// Encodes spans as an OTLP/HTTP JSON `ExportTraceServiceRequest`
+pub fn encode(service_name: &str, spans: &[TraceSpan]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(|span| {
+        let status = match &span.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({ "code": 1 }),
+        };
+        json!({
+            "traceId": span.trace_id,
+            "spanId": span.span_id,
+            "parentSpanId": span.parent_span_id.clone().unwrap_or_default(),
+            "name": span.name,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(&span.start),
+            "endTimeUnixNano": unix_nanos(&span.end),
+            "attributes": encode_attributes(&span.attributes),
+            "events": span.events.iter().map(|event| json!({
+                "name": event.name,
+                "timeUnixNano": unix_nanos(&event.time),
+                "attributes": encode_attributes(&event.attributes),
+            })).collect::<Vec<_>>(),
+            "status": status,
+        })
+    }).collect();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": encode_attributes(&[("service.name".to_string(), json!(service_name))]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": "agent-hud", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Collector returned {0}: {1}")]
+    Rejected(u16, String),
+}
+
+/// POSTs one batch of spans to the collector
+pub async fn send(client: &reqwest::Client, config: &TelemetryConfig, spans: &[TraceSpan]) -> Result<(), TelemetryError> {
+    let mut request = client.post(&config.endpoint).json(&encode(&config.service_name, spans));
+    for (name, value) in &config.headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(TelemetryError::Rejected(status.as_u16(), body));
+    }
+    Ok(())
+}
+
+/// Hands spans to a background task that batches them and sends them to the collector,
+/// so exporting never blocks message handling. Dropping the exporter flushes what is left.
+pub struct TraceExporter {
+    config: TelemetryConfig,
+    sender: mpsc::UnboundedSender<TraceSpan>,
+}
+
+impl TraceExporter {
+    /// Starts the exporter, or returns `None` when telemetry is disabled
+    pub fn start(config: &TelemetryConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run_exporter(config.clone(), receiver));
+        info!("Exporting traces to {}", config.endpoint);
+        Some(TraceExporter { config: config.clone(), sender })
+    }
+
+    pub fn include_payloads(&self) -> bool {
+        self.config.include_payloads
+    }
+
+    pub fn export(&self, span: TraceSpan) {
+        if self.sender.send(span).is_err() {
+            warn!("Trace exporter has stopped, dropping span");
+        }
+    }
+}
+
+async fn run_exporter(config: TelemetryConfig, mut receiver: mpsc::UnboundedReceiver<TraceSpan>) {
+    let client = match reqwest::Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Could not create HTTP client for trace export: {}", e);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(config.flush_interval_seconds.max(1)));
+    let mut batch = Vec::new();
+
+    loop {
+        let closed = tokio::select! {
+            span = receiver.recv() => match span {
+                Some(span) => {
+                    batch.push(span);
+                    if batch.len() < config.batch_size.max(1) {
+                        continue;
+                    }
+                    false
+                }
+                None => true,
+            },
+            _ = interval.tick() => false,
+        };
+
+        if !batch.is_empty() {
+            match send(&client, &config, &batch).await {
+                Ok(()) => debug!("Exported {} spans to {}", batch.len(), config.endpoint),
+                Err(e) => warn!("Failed to export {} spans to {}: {}", batch.len(), config.endpoint, e),
+            }
+            batch.clear();
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stub_server;
+
+    fn session(status: SessionStatus) -> Session {
+        let started_at = Utc::now();
+        Session {
+            id: "session-1".to_string(),
+            name: Some("nightly".to_string()),
+            started_by: "agent-1".to_string(),
+            started_at,
+            ended_at: Some(started_at + chrono::Duration::seconds(5)),
+            status,
+            summary: Some("Ended when its last agent disconnected".to_string()),
+            metadata: None,
+        }
+    }
+
+    fn config(endpoint: String) -> TelemetryConfig {
+        let mut headers = HashMap::new();
+        headers.insert("x-collector-key".to_string(), "secret".to_string());
+        TelemetryConfig { enabled: true, endpoint, headers, batch_size: 1, ..TelemetryConfig::default() }
+    }
+
+    fn attribute<'a>(span: &'a Value, key: &str) -> Option<&'a Value> {
+        span["attributes"].as_array()?.iter().find(|a| a["key"] == key).map(|a| &a["value"])
+    }
+
+    #[test]
+    fn session_span_is_encoded_as_otlp() {
+        let session = session(SessionStatus::Cancelled);
+        let body = encode("agent-hud", &[session_span(&session)]);
+
+        let resource = &body["resourceSpans"][0];
+        assert_eq!(resource["resource"]["attributes"][0]["value"]["stringValue"], "agent-hud");
+        let span = &resource["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(span["parentSpanId"], "");
+        assert_eq!(span["name"], "session nightly");
+        assert_eq!(span["status"]["code"], 1);
+        assert_eq!(attribute(span, "hud.session.status").unwrap()["stringValue"], "cancelled");
+
+        let start: i64 = span["startTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        let end: i64 = span["endTimeUnixNano"].as_str().unwrap().parse().unwrap();
+        assert_eq!(end - start, 5_000_000_000);
+    }
+
+    #[test]
+    fn failed_sessions_carry_an_error_status() {
+        let body = encode("agent-hud", &[session_span(&session(SessionStatus::Failed))]);
+        let status = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["status"];
+        assert_eq!(status["code"], 2);
+        assert_eq!(status["message"], "Ended when its last agent disconnected");
+    }
+
+    #[tokio::test]
+    async fn send_posts_the_batch_to_the_collector() {
+        let (url, mut requests) = stub_server("/v1/traces", vec![200]).await;
+        let span = session_span(&session(SessionStatus::Cancelled));
+
+        send(&reqwest::Client::new(), &config(url), std::slice::from_ref(&span)).await.unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/traces");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert_eq!(request.header("x-collector-key"), Some("secret"));
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, encode("agent-hud", &[span]));
+    }
+
+    #[tokio::test]
+    async fn send_reports_a_rejected_batch() {
+        let (url, _requests) = stub_server("/v1/traces", vec![500]).await;
+        let span = session_span(&session(SessionStatus::Completed));
+
+        let result = send(&reqwest::Client::new(), &config(url), &[span]).await;
+        assert!(matches!(result, Err(TelemetryError::Rejected(500, _))));
+    }
+
+    #[tokio::test]
+    async fn exporter_flushes_full_batches() {
+        let (url, mut requests) = stub_server("/v1/traces", vec![200]).await;
+        let exporter = TraceExporter::start(&config(url)).unwrap();
+
+        exporter.export(session_span(&session(SessionStatus::Cancelled)));
+
+        let request = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"], "session nightly");
+    }
+}
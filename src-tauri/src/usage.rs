@@ -0,0 +1,148 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One LLM call as reported by an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub id: String,
+    pub agent_id: String,
+    pub session_id: Option<String>,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+    pub latency_ms: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub reports: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+    pub latency_ms: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, report: &UsageReport) {
+        self.reports = self.reports.saturating_add(1);
+        self.input_tokens = self.input_tokens.saturating_add(report.input_tokens);
+        self.output_tokens = self.output_tokens.saturating_add(report.output_tokens);
+        self.cost += report.cost;
+        self.latency_ms = self.latency_ms.saturating_add(report.latency_ms);
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens.saturating_add(self.output_tokens)
+    }
+}
+
+/// Running usage totals per agent and per session
+#[derive(Default)]
+pub struct UsageTracker {
+    agents: HashMap<String, UsageTotals>,
+    sessions: HashMap<String, UsageTotals>,
+}
+
+/// Running totals by agent id and by session id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageSnapshot {
+    pub agents: HashMap<String, UsageTotals>,
+    pub sessions: HashMap<String, UsageTotals>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        UsageTracker::default()
+    }
+
+    /// Adds a report to its agent's and session's totals and returns the new totals
+    pub fn record(&mut self, report: &UsageReport) -> (UsageTotals, Option<UsageTotals>) {
+        let agent_totals = self.agents.entry(report.agent_id.clone()).or_default();
+        agent_totals.add(report);
+        let agent_totals = agent_totals.clone();
+
+        let session_totals = report.session_id.as_ref().map(|session_id| {
+            let totals = self.sessions.entry(session_id.clone()).or_default();
+            totals.add(report);
+            totals.clone()
+        });
+
+        (agent_totals, session_totals)
+    }
+
+    pub fn agent(&self, agent_id: &str) -> UsageTotals {
+        self.agents.get(agent_id).cloned().unwrap_or_default()
+    }
+
+    pub fn session(&self, session_id: &str) -> UsageTotals {
+        self.sessions.get(session_id).cloned().unwrap_or_default()
+    }
+
+    pub fn snapshot(&self) -> UsageSnapshot {
+        UsageSnapshot {
+            agents: self.agents.clone(),
+            sessions: self.sessions.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(input_tokens: u64, cost: f64) -> UsageReport {
+        UsageReport {
+            id: "u1".to_string(),
+            agent_id: "agent-1".to_string(),
+            session_id: None,
+            model: "test".to_string(),
+            input_tokens,
+            output_tokens: 10,
+            cost,
+            latency_ms: 5,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn totals_accumulate() {
+        let mut totals = UsageTotals::default();
+        totals.add(&report(100, 0.5));
+        totals.add(&report(50, 0.25));
+        assert_eq!(totals.reports, 2);
+        assert_eq!(totals.total_tokens(), 170);
+        assert_eq!(totals.cost, 0.75);
+        assert_eq!(totals.latency_ms, 10);
+    }
+
+    #[tokio::test]
+    async fn resent_reports_are_stored_once() {
+        let database = crate::database::Database::new().await.unwrap();
+        database.save_agent(&crate::models::Agent {
+            id: "agent-1".to_string(),
+            name: "Test Agent".to_string(),
+            status: crate::models::AgentStatus::Connected,
+            connected_at: Utc::now(),
+            last_activity: Utc::now(),
+            metadata: None,
+            parent_id: None,
+            tags: Vec::new(),
+            run_id: None,
+        }).await.unwrap();
+        assert!(database.save_usage_report(&report(100, 0.5)).await.unwrap());
+        assert!(!database.save_usage_report(&report(100, 0.5)).await.unwrap());
+        assert_eq!(database.get_usage_reports(Some("agent-1"), None, 10).await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn huge_token_counts_saturate() {
+        let mut totals = UsageTotals::default();
+        totals.add(&report(u64::MAX, 0.0));
+        totals.add(&report(u64::MAX, 0.0));
+        assert_eq!(totals.input_tokens, u64::MAX);
+        assert_eq!(totals.total_tokens(), u64::MAX);
+    }
+}
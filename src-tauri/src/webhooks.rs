@@ -0,0 +1,384 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::database::Database;
+use crate::models::RequestPriority;
+
+/// Header carrying `sha256=<hex HMAC of "<timestamp>.<body>">` when the webhook has a secret
+pub const SIGNATURE_HEADER: &str = "X-Agent-Hud-Signature";
+/// Header carrying the Unix time the attempt was signed at. Receivers should refuse
+/// deliveries whose timestamp is too old, so a captured request can't be replayed later.
+pub const TIMESTAMP_HEADER: &str = "X-Agent-Hud-Timestamp";
+
+/// An outbound webhook from the `webhooks` section of the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    /// Events to send; all of them when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Only send request events for requests of at least this priority
+    #[serde(default)]
+    pub min_priority: Option<RequestPriority>,
+    /// Key for signing each attempt's timestamp and body with HMAC-SHA256
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Attempts before a delivery is given up on
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; it doubles after every failed attempt
+    #[serde(default = "default_retry_delay_seconds")]
+    pub retry_delay_seconds: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_retry_delay_seconds() -> u64 {
+    2
+}
+
+// Upper bound on the delay between two attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookEvent {
+    HumanInputRequest,
+    RequestTimeout,
+    RequestCompleted,
+    AgentConnected,
+    AgentDisconnected,
+    /// Sent by `send_test_webhook`, whatever the webhook's event filter
+    Ping,
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::HumanInputRequest => write!(f, "human-input-request"),
+            WebhookEvent::RequestTimeout => write!(f, "request-timeout"),
+            WebhookEvent::RequestCompleted => write!(f, "request-completed"),
+            WebhookEvent::AgentConnected => write!(f, "agent-connected"),
+            WebhookEvent::AgentDisconnected => write!(f, "agent-disconnected"),
+            WebhookEvent::Ping => write!(f, "ping"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not yet sent, or waiting to be retried
+    Pending,
+    Delivered,
+    /// Gave up after `max_attempts`
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryStatus::Pending => write!(f, "pending"),
+            DeliveryStatus::Delivered => write!(f, "delivered"),
+            DeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// One event sent to one webhook, with the outcome of its latest attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook: String,
+    pub event: WebhookEvent,
+    pub url: String,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookConfig {
+    fn accepts(&self, event: WebhookEvent, priority: Option<RequestPriority>) -> bool {
+        if !self.events.is_empty() && !self.events.contains(&event) {
+            return false;
+        }
+        match (self.min_priority, priority) {
+            (Some(min), Some(priority)) => priority >= min,
+            _ => true,
+        }
+    }
+
+    fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+        Duration::from_secs(self.retry_delay_seconds.saturating_mul(factor)).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `<timestamp>.<body>` under `secret`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    format!("sha256={}", digest.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+/// Sends events to the configured webhooks. Each delivery runs in its own task, retrying
+/// with exponential backoff, and every attempt is recorded in the delivery log.
+pub struct WebhookDispatcher {
+    webhooks: Vec<WebhookConfig>,
+    client: reqwest::Client,
+    database: Database,
+}
+
+impl WebhookDispatcher {
+    pub fn new(webhooks: Vec<WebhookConfig>, database: Database) -> Self {
+        if !webhooks.is_empty() {
+            info!("{} webhooks configured", webhooks.len());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        WebhookDispatcher { webhooks, client, database }
+    }
+
+    /// Queues `data` for every webhook whose filter matches. `priority` is the priority of the
+    /// request the event is about, if any.
+    pub fn dispatch(&self, event: WebhookEvent, priority: Option<RequestPriority>, data: serde_json::Value) {
+        for webhook in self.webhooks.iter().filter(|w| w.accepts(event, priority)) {
+            self.spawn_delivery(webhook.clone(), event, data.clone());
+        }
+    }
+
+    /// Queues a ping for one webhook, ignoring its event filter, and returns the delivery id
+    pub fn ping(&self, name: &str) -> Option<String> {
+        let webhook = self.webhooks.iter().find(|w| w.name == name)?;
+        Some(self.spawn_delivery(webhook.clone(), WebhookEvent::Ping, serde_json::json!({ "webhook": name })))
+    }
+
+    fn spawn_delivery(&self, webhook: WebhookConfig, event: WebhookEvent, data: serde_json::Value) -> String {
+        let delivery = new_delivery(&webhook, event, data);
+        let id = delivery.id.clone();
+        tokio::spawn(deliver(self.client.clone(), self.database.clone(), webhook, delivery));
+        id
+    }
+}
+
+fn new_delivery(webhook: &WebhookConfig, event: WebhookEvent, data: serde_json::Value) -> WebhookDelivery {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    WebhookDelivery {
+        id: id.clone(),
+        webhook: webhook.name.clone(),
+        event,
+        url: webhook.url.clone(),
+        payload: serde_json::json!({
+            "id": id,
+            "event": event,
+            "timestamp": now.to_rfc3339(),
+            "data": data,
+        }),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        response_status: None,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+        next_attempt_at: Some(now),
+    }
+}
+
+async fn deliver(client: reqwest::Client, database: Database, webhook: WebhookConfig, mut delivery: WebhookDelivery) {
+    // The body is fixed up front; each attempt is signed with its own timestamp
+    let body = delivery.payload.to_string();
+    if let Err(e) = database.save_webhook_delivery(&delivery).await {
+        warn!("Failed to record webhook delivery {}: {}", delivery.id, e);
+    }
+
+    loop {
+        let mut request = client.post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Agent-Hud-Event", delivery.event.to_string())
+            .header("X-Agent-Hud-Delivery", &delivery.id)
+            .body(body.clone());
+        if let Some(secret) = &webhook.secret {
+            let timestamp = Utc::now().timestamp();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, sign(secret, timestamp, body.as_bytes()));
+        }
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+
+        delivery.attempts += 1;
+        delivery.updated_at = Utc::now();
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                delivery.response_status = Some(response.status().as_u16());
+                delivery.last_error = None;
+                delivery.status = DeliveryStatus::Delivered;
+            }
+            Ok(response) => {
+                delivery.response_status = Some(response.status().as_u16());
+                delivery.last_error = Some(format!("{} returned {}", webhook.url, response.status()));
+            }
+            Err(e) => {
+                delivery.response_status = None;
+                delivery.last_error = Some(e.to_string());
+            }
+        }
+
+        let delay = webhook.retry_delay(delivery.attempts);
+        if delivery.status == DeliveryStatus::Pending && delivery.attempts >= webhook.max_attempts {
+            delivery.status = DeliveryStatus::Failed;
+        }
+        delivery.next_attempt_at = (delivery.status == DeliveryStatus::Pending)
+            .then(|| delivery.updated_at + chrono::Duration::from_std(delay).unwrap_or_default());
+
+        if let Err(e) = database.save_webhook_delivery(&delivery).await {
+            warn!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+
+        match delivery.status {
+            DeliveryStatus::Delivered => {
+                debug!("Delivered {} to webhook {} (attempt {})", delivery.event, webhook.name, delivery.attempts);
+                return;
+            }
+            DeliveryStatus::Failed => {
+                warn!("Giving up on {} for webhook {} after {} attempts: {}", delivery.event, webhook.name,
+                    delivery.attempts, delivery.last_error.as_deref().unwrap_or(""));
+                return;
+            }
+            DeliveryStatus::Pending => {
+                debug!("Webhook {} attempt {} failed, retrying in {:?}", webhook.name, delivery.attempts, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::stub_server;
+
+    fn webhook(url: String, max_attempts: u32) -> WebhookConfig {
+        WebhookConfig {
+            name: "ops".to_string(),
+            url,
+            events: Vec::new(),
+            min_priority: None,
+            secret: Some("hook-secret".to_string()),
+            headers: HashMap::new(),
+            max_attempts,
+            retry_delay_seconds: 0,
+        }
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("hook-secret", 1_700_000_000, b"{\"a\":1}");
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"hook-secret").unwrap();
+        mac.update(b"1700000000.{\"a\":1}");
+        let expected: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(signature, format!("sha256={}", expected));
+
+        assert_ne!(signature, sign("hook-secret", 1_700_000_001, b"{\"a\":1}"));
+        assert_ne!(signature, sign("hook-secret", 1_700_000_000, b"{\"a\":2}"));
+        assert_ne!(signature, sign("other-secret", 1_700_000_000, b"{\"a\":1}"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        let webhook = WebhookConfig { retry_delay_seconds: 2, ..webhook(String::new(), 5) };
+        let delays: Vec<u64> = (1..=4).map(|attempt| webhook.retry_delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [2, 4, 8, 16]);
+        assert_eq!(webhook.retry_delay(30), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn filters_by_event_and_priority() {
+        let webhook = WebhookConfig {
+            events: vec![WebhookEvent::HumanInputRequest],
+            min_priority: Some(RequestPriority::High),
+            ..webhook(String::new(), 5)
+        };
+        assert!(webhook.accepts(WebhookEvent::HumanInputRequest, Some(RequestPriority::Critical)));
+        assert!(!webhook.accepts(WebhookEvent::HumanInputRequest, Some(RequestPriority::Medium)));
+        assert!(!webhook.accepts(WebhookEvent::AgentConnected, None));
+    }
+
+    #[tokio::test]
+    async fn retries_until_delivered_and_logs_the_outcome() {
+        let (url, mut requests) = stub_server("/hook", vec![500, 200]).await;
+        let database = Database::new().await.unwrap();
+        let webhook = webhook(url, 3);
+        let delivery = new_delivery(&webhook, WebhookEvent::Ping, serde_json::json!({ "webhook": "ops" }));
+        let id = delivery.id.clone();
+
+        deliver(reqwest::Client::new(), database.clone(), webhook, delivery).await;
+
+        for _ in 0..2 {
+            let request = requests.recv().await.unwrap();
+            assert_eq!(request.header("x-agent-hud-event"), Some("ping"));
+            assert_eq!(request.header("x-agent-hud-delivery"), Some(id.as_str()));
+            let timestamp: i64 = request.header("x-agent-hud-timestamp").unwrap().parse().unwrap();
+            assert_eq!(request.header("x-agent-hud-signature").unwrap(), sign("hook-secret", timestamp, &request.body));
+        }
+
+        let log = database.get_webhook_deliveries(Some("ops"), None, 10).await.unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].id, id);
+        assert_eq!(log[0].status, DeliveryStatus::Delivered);
+        assert_eq!(log[0].attempts, 2);
+        assert_eq!(log[0].response_status, Some(200));
+        assert!(log[0].next_attempt_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, _requests) = stub_server("/hook", vec![503, 503]).await;
+        let database = Database::new().await.unwrap();
+        let webhook = webhook(url, 2);
+        let delivery = new_delivery(&webhook, WebhookEvent::Ping, serde_json::Value::Null);
+
+        deliver(reqwest::Client::new(), database.clone(), webhook, delivery).await;
+
+        let log = database.get_webhook_deliveries(None, Some(DeliveryStatus::Failed), 10).await.unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].attempts, 2);
+        assert_eq!(log[0].response_status, Some(503));
+        assert!(log[0].last_error.as_deref().unwrap().contains("503"));
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::AsyncReadExt;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::RwLock;
 use tokio_tungstenite::{accept_async, tungstenite::Message};
@@ -10,10 +12,29 @@ use uuid::Uuid;
 use chrono::Utc;
 
 use crate::models::*;
-use crate::AppState;
+use crate::response_validation::{ResponseValidator, ValidationError};
+use crate::policy::PolicyDecision;
+use crate::request_queue::QueueError;
+use crate::response_links::{LinkAction, LinkError};
+use crate::access::{AccessError, Permission};
+use crate::audit;
+use crate::http::{self, HttpRequest, HttpResponse};
+use crate::metrics::{Gauges, METRICS};
+use crate::budget::{BudgetAction, BudgetBreach, BudgetLevel, BudgetLimits, BudgetScope, BudgetUsage};
+use crate::telemetry::{self, TraceSpan};
+use crate::usage::UsageReport;
+use crate::webhooks::WebhookEvent;
+use crate::{AppState, AppStateInner};
+
+/// Recorded as the issuer of control commands and requests raised by budget enforcement
+const BUDGET_ACTOR: &str = "system:budget";
+/// Who ends a session whose last agent disconnected without ending it
+const DISCONNECT_ACTOR: &str = "system:disconnect";
 
 pub struct WebSocketServer {
     port: u16,
+    // Cleared if the accept loop stops
+    listening: Arc<AtomicBool>,
     connected_clients: Arc<RwLock<HashMap<String, ClientConnection>>>,
     app_state: AppState,
 }
@@ -23,6 +44,7 @@ struct ClientConnection {
     client_type: ClientType,
     sender: tokio::sync::mpsc::UnboundedSender<Message>,
     agent_info: Option<Agent>,
+    operator: Option<Operator>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +60,7 @@ impl WebSocketServer {
         
         let server = WebSocketServer {
             port,
+            listening: Arc::new(AtomicBool::new(false)),
             connected_clients: Arc::new(RwLock::new(HashMap::new())),
             app_state,
         };
@@ -52,6 +75,10 @@ impl WebSocketServer {
         self.port
     }
     
+    pub fn is_listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+    
     async fn find_available_port() -> Result<u16, Box<dyn std::error::Error>> {
         for port in 8080..8200 {
             if let Ok(listener) = TcpListener::bind(format!("127.0.0.1:{}", port)).await {
@@ -69,9 +96,21 @@ impl WebSocketServer {
         
         let clients = self.connected_clients.clone();
         let app_state = self.app_state.clone();
+        let listening = self.listening.clone();
+        listening.store(true, Ordering::Relaxed);
+        
+        Self::spawn_request_monitor(clients.clone(), app_state.clone());
         
         tokio::spawn(async move {
-            while let Ok((stream, peer_addr)) = listener.accept().await {
+            loop {
+                let (stream, peer_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("WebSocket server stopped accepting connections: {}", e);
+                        listening.store(false, Ordering::Relaxed);
+                        break;
+                    }
+                };
                 info!("New connection from: {}", peer_addr);
                 
                 let clients_clone = clients.clone();
@@ -89,11 +128,23 @@ impl WebSocketServer {
     }
     
     async fn handle_connection(
-        stream: TcpStream,
+        mut stream: TcpStream,
         peer_addr: SocketAddr,
         clients: Arc<RwLock<HashMap<String, ClientConnection>>>,
         app_state: AppState,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        // Plain HTTP requests (metrics and the like) share the port with WebSocket clients
+        let (request, head) = http::read_request(&mut stream, http::READ_TIMEOUT).await?;
+        if !request.is_websocket_upgrade() {
+            let response = Self::handle_http(&request, &clients, &app_state).await;
+            debug!("{} {} from {}: {}", request.method, request.path, peer_addr, response.status);
+            http::write_response(&mut stream, &response).await?;
+            return Ok(());
+        }
+        
+        // Hand the handshake bytes already read back to the WebSocket layer
+        let (reader, writer) = stream.into_split();
+        let stream = tokio::io::join(std::io::Cursor::new(head).chain(reader), writer);
         let ws_stream = accept_async(stream).await?;
         let (mut ws_sender, mut ws_receiver) = ws_stream.split();
         let client_id = Uuid::new_v4().to_string();
@@ -109,6 +160,7 @@ impl WebSocketServer {
             while let Some(message) = rx.recv().await {
                 if let Err(e) = ws_sender.send(message).await {
                     error!("Failed to send message to client: {}", e);
+                    METRICS.message_dropped();
                     break;
                 }
             }
@@ -157,13 +209,14 @@ impl WebSocketServer {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let parsed_message: serde_json::Value = serde_json::from_str(message)?;
         let message_type = parsed_message["type"].as_str().unwrap_or("unknown");
+        METRICS.message_received(message_type);
         
         match message_type {
             "register-agent" => {
                 Self::handle_agent_registration(client_id, &parsed_message, clients, app_state, sender).await?;
             }
             "register-gui" => {
-                Self::handle_gui_registration(client_id, clients, sender).await?;
+                Self::handle_gui_registration(client_id, &parsed_message, clients, app_state, sender).await?;
             }
             "agent-message" => {
                 Self::handle_agent_message(client_id, &parsed_message, clients, app_state).await?;
@@ -172,16 +225,49 @@ impl WebSocketServer {
                 Self::handle_human_input_request(client_id, &parsed_message, clients, app_state).await?;
             }
             "human-input-response" => {
-                Self::handle_human_input_response(&parsed_message, clients, app_state).await?;
+                Self::handle_human_input_response(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "agent-control" => {
+                Self::handle_control_request(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "agent-control-ack" => {
+                Self::handle_control_ack(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "operator-message" => {
+                Self::handle_operator_message(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "operator-message-ack" => {
+                Self::handle_operator_message_ack(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "poll-operator-messages" => {
+                Self::handle_operator_message_poll(client_id, clients, app_state).await?;
+            }
+            "cancel-request" => {
+                Self::handle_request_cancellation(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "tool-call-start" => {
+                Self::handle_tool_call_start(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "tool-call-end" => {
+                Self::handle_tool_call_end(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "usage-report" => {
+                Self::handle_usage_report(client_id, &parsed_message, clients, app_state).await?;
+            }
+            "start-session" | "join-session" | "end-session" => {
+                Self::handle_session_message(client_id, &parsed_message, clients, app_state, message_type).await?;
+            }
+            "claim-request" | "release-request" => {
+                Self::handle_claim_message(client_id, &parsed_message, clients, app_state, message_type).await?;
             }
             "markdown-content" => {
-                Self::handle_content_emission(client_id, &parsed_message, clients, "markdown-content").await?;
+                Self::handle_content_emission(client_id, &parsed_message, clients, app_state, "markdown-content").await?;
             }
             "code-content" => {
-                Self::handle_content_emission(client_id, &parsed_message, clients, "code-content").await?;
+                Self::handle_content_emission(client_id, &parsed_message, clients, app_state, "code-content").await?;
             }
             "image-content" => {
-                Self::handle_content_emission(client_id, &parsed_message, clients, "image-content").await?;
+                Self::handle_content_emission(client_id, &parsed_message, clients, app_state, "image-content").await?;
             }
             _ => {
                 warn!("Unknown message type: {}", message_type);
@@ -200,6 +286,9 @@ impl WebSocketServer {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let agent_name = message["name"].as_str().unwrap_or("Unknown Agent");
         let metadata = message["metadata"].clone();
+        let tags = message["tags"].as_array()
+            .map(|tags| tags.iter().filter_map(|t| t.as_str()).map(|t| t.to_string()).collect())
+            .unwrap_or_default();
         
         let agent = Agent {
             id: client_id.to_string(),
@@ -208,6 +297,11 @@ impl WebSocketServer {
             connected_at: Utc::now(),
             last_activity: Utc::now(),
             metadata: if metadata.is_null() { None } else { Some(metadata) },
+            parent_id: message["parentId"].as_str().map(|s| s.to_string()),
+            tags,
+            run_id: message["runId"].as_str()
+                .or_else(|| message["sessionId"].as_str())
+                .map(|s| s.to_string()),
         };
         
         // Add to connected clients
@@ -218,19 +312,49 @@ impl WebSocketServer {
                 client_type: ClientType::Agent,
                 sender,
                 agent_info: Some(agent.clone()),
+                operator: None,
             });
         }
         
         // Add to app state
-        {
+        let mut started_session = None;
+        let tree = {
             let mut app_state_lock = app_state.lock().await;
+            if let Some(parent_id) = &agent.parent_id {
+                if !app_state_lock.connected_agents.iter().any(|a| &a.id == parent_id) {
+                    warn!("Agent {} names unknown parent {}", client_id, parent_id);
+                }
+            }
             app_state_lock.connected_agents.push(agent.clone());
             
+            // An agent registering with a run id joins that session, starting it if needed
+            if let Some(run_id) = &agent.run_id {
+                let existing = app_state_lock.database.get_session(run_id).await.map_err(|e| e.to_string());
+                match existing {
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        let session = Self::new_session(run_id.clone(), None, None, client_id);
+                        if let Err(e) = app_state_lock.database.save_session(&session).await {
+                            error!("Failed to save session to database: {}", e);
+                        }
+                        audit::record(&app_state_lock.database, "session-started", &agent.name, Some(run_id),
+                            serde_json::to_value(&session)?).await;
+                        started_session = Some(session);
+                    }
+                    Err(e) => error!("Failed to look up session {}: {}", run_id, e),
+                }
+            }
+            
             // Also save to database
             if let Err(e) = app_state_lock.database.save_agent(&agent).await {
                 error!("Failed to save agent to database: {}", e);
             }
-        }
+            audit::record(&app_state_lock.database, "agent-registered", &agent.name, Some(client_id),
+                serde_json::to_value(&agent)?).await;
+            app_state_lock.webhooks.dispatch(WebhookEvent::AgentConnected, None, serde_json::to_value(&agent)?);
+            
+            AgentNode::build_forest(app_state_lock.connected_agents.clone())
+        };
         
         info!("Agent registered: {} ({})", agent_name, client_id);
         
@@ -251,15 +375,63 @@ impl WebSocketServer {
         
         // Notify GUI clients about new agent
         Self::broadcast_to_guis(clients, "agent-connected", &agent).await;
+        Self::broadcast_to_guis(clients, "agent-tree", &tree).await;
+        if let Some(session) = started_session {
+            Self::broadcast_to_guis(clients, "session-started", &session).await;
+        }
         
         Ok(())
     }
     
     async fn handle_gui_registration(
         client_id: &str,
+        message: &serde_json::Value,
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
         sender: tokio::sync::mpsc::UnboundedSender<Message>,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        // Operators may identify themselves with a name or a `{ "name": ..., "token": ... }` object
+        let operator_name = message["operator"].as_str()
+            .or_else(|| message["operator"]["name"].as_str())
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("operator-{}", &client_id[..8]));
+        let token = message["token"].as_str().or_else(|| message["operator"]["token"].as_str());
+        
+        // Reserved names mark answers the server gave itself, which skip claims and votes
+        let role = if is_reserved_name(&operator_name) {
+            Err(format!("'{}' is reserved for automated responses", operator_name))
+        } else {
+            app_state.lock().await.access_control.authenticate(&operator_name, token)
+                .map_err(|e| e.to_string())
+        };
+        let role = match role {
+            Ok(role) => role,
+            Err(reason) => {
+                let _ = sender.send(Message::Text(serde_json::json!({
+                    "type": "registration-ack",
+                    "success": false,
+                    "error": reason,
+                    "timestamp": Utc::now().to_rfc3339()
+                }).to_string()));
+                return Err(format!("GUI {} failed to register as {}: {}", client_id, operator_name, reason).into());
+            }
+        };
+        
+        let operator = {
+            let app_state_lock = app_state.lock().await;
+            let operator = Operator {
+                client_id: client_id.to_string(),
+                role,
+                name: operator_name,
+                connected_at: Utc::now(),
+            };
+            audit::record(&app_state_lock.database, "operator-connected", &operator.name, Some(client_id),
+                serde_json::to_value(&operator)?).await;
+            operator
+        };
+        
         // Add GUI client
         {
             let mut clients_lock = clients.write().await;
@@ -268,10 +440,21 @@ impl WebSocketServer {
                 client_type: ClientType::GUI,
                 sender,
                 agent_info: None,
+                operator: Some(operator.clone()),
             });
         }
         
-        info!("GUI client registered: {}", client_id);
+        info!("GUI client registered: {} (operator {}, {})", client_id, operator.name, operator.role);
+        
+        Self::send_to_client(clients, client_id, &serde_json::json!({
+            "type": "registration-ack",
+            "success": true,
+            "clientId": client_id,
+            "operator": operator,
+            "serverTime": Utc::now().to_rfc3339()
+        })).await;
+        Self::broadcast_to_guis(clients, "operator-connected", &operator).await;
+        
         Ok(())
     }
     
@@ -281,12 +464,16 @@ impl WebSocketServer {
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
         app_state: &AppState,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let agent_message = AgentMessage {
+        let mut agent_message = AgentMessage {
             id: message["id"].as_str().unwrap_or(&Uuid::new_v4().to_string()).to_string(),
             agent_id: client_id.to_string(),
             message_type: message["type"].as_str().unwrap_or("unknown").to_string(),
             payload: message["payload"].clone(),
             timestamp: Utc::now(),
+            direction: MessageDirection::Inbound,
+            sender: None,
+            delivered_at: None,
+            session_id: None,
         };
         
         // Update agent last activity
@@ -294,7 +481,15 @@ impl WebSocketServer {
             let mut app_state_lock = app_state.lock().await;
             if let Some(agent) = app_state_lock.connected_agents.iter_mut().find(|a| a.id == client_id) {
                 agent.last_activity = Utc::now();
-                agent.status = AgentStatus::Active;
+                agent_message.session_id = agent.run_id.clone();
+                // Paused and stopping agents may still report progress; keep their status
+                if matches!(agent.status, AgentStatus::Connected) {
+                    agent.status = AgentStatus::Active;
+                }
+            }
+            
+            if let Err(e) = app_state_lock.database.save_message(&agent_message).await {
+                error!("Failed to save agent message to database: {}", e);
             }
         }
         
@@ -314,14 +509,14 @@ impl WebSocketServer {
             .map(|s| s.to_string())
             .unwrap_or_else(|| Uuid::new_v4().to_string());
         
-        // Get agent name
-        let agent_name = {
+        // Get agent name and session
+        let (agent_name, session_id) = {
             let app_state_lock = app_state.lock().await;
             app_state_lock.connected_agents
                 .iter()
                 .find(|a| a.id == client_id)
-                .map(|a| a.name.clone())
-                .unwrap_or_else(|| "Unknown Agent".to_string())
+                .map(|a| (a.name.clone(), a.run_id.clone()))
+                .unwrap_or_else(|| ("Unknown Agent".to_string(), None))
         };
         
         let request_type_str = message["inputType"].as_str().unwrap_or("input");
@@ -330,11 +525,40 @@ impl WebSocketServer {
             "choice" => RequestType::Choice,
             "confirmation" => RequestType::Confirmation,
             "text" => RequestType::Text,
+            "form" => RequestType::Form,
+            "multi-choice" | "multi_choice" => RequestType::MultiChoice,
+            "ranking" => RequestType::Ranking,
             _ => RequestType::Input,
         };
         
         let request_message = message["message"].as_str().unwrap_or("").to_string();
-        let priority = RequestPriority::from_request_type_and_message(&request_type, &request_message);
+        let requested_priority = message["priority"].as_str().and_then(RequestPriority::parse);
+        
+        let options = if message["options"].is_null() {
+            Vec::new()
+        } else {
+            match serde_json::from_value::<Vec<RequestOption>>(message["options"].clone()) {
+                Ok(options) => options,
+                Err(e) => {
+                    Self::reject_request(clients, app_state, client_id, &request_id, &format!("Invalid options: {}", e)).await;
+                    return Ok(());
+                }
+            }
+        };
+        
+        // Accept either a bare action name or a full policy object
+        let on_timeout = match &message["onTimeout"] {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(action) => Some(serde_json::json!({ "action": action })),
+            policy => Some(policy.clone()),
+        };
+        let on_timeout = match on_timeout.map(serde_json::from_value::<TimeoutPolicy>).transpose() {
+            Ok(policy) => policy,
+            Err(e) => {
+                Self::reject_request(clients, app_state, client_id, &request_id, &format!("Invalid onTimeout policy: {}", e)).await;
+                return Ok(());
+            }
+        };
         
         let human_request = HumanInputRequest {
             id: request_id.clone(),
@@ -342,176 +566,2065 @@ impl WebSocketServer {
             agent_name,
             request_type,
             message: request_message,
-            options: message["options"].as_array()
-                .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
-                .unwrap_or_default(),
+            options,
             context: if message["context"].is_null() { None } else { Some(message["context"].clone()) },
+            schema: if message["schema"].is_null() { None } else { Some(message["schema"].clone()) },
+            min_selections: message["minSelections"].as_u64().map(|n| n as u32),
+            max_selections: message["maxSelections"].as_u64().map(|n| n as u32),
             timeout_seconds: message["timeout"].as_u64().unwrap_or(300) as u32,
+            on_timeout,
             timestamp: Utc::now(),
             status: RequestStatus::Pending,
-            priority,
+            priority: RequestPriority::Medium,
+            priority_reason: None,
+            claimed_by: None,
+            required_approvers: message["requiredApprovers"].as_u64().map(|n| n as u32),
+            approvals: Vec::new(),
+            session_id,
         };
         
-        // Add to app state
-        {
+        let mut human_request = human_request;
+        
+        // Classify, validate, check budgets and apply policy rules before anyone sees the
+        // request. Agents over a hard budget limit can't ask for more until an operator
+        // raises it.
+        let (outcome, breaches) = {
             let mut app_state_lock = app_state.lock().await;
-            app_state_lock.human_requests.push(human_request.clone());
             
-            // Also save to database
-            if let Err(e) = app_state_lock.database.save_human_request(&human_request).await {
-                error!("Failed to save human request to database: {}", e);
+            // Agents choose their own request ids; reusing one would send answers to the earlier
+            // request and overwrite it in the database, including pruned ones
+            let known = app_state_lock.request_queue.contains(&request_id) || {
+                match app_state_lock.database.human_request_exists(&request_id).await {
+                    Ok(exists) => exists,
+                    Err(e) => {
+                        error!("Failed to look up request {} in database: {}", request_id, e);
+                        false
+                    }
+                }
+            };
+            
+            let classification = app_state_lock.priority_classifier.classify(&human_request, requested_priority);
+            human_request.priority = classification.priority;
+            human_request.priority_reason = Some(classification.reason);
+            
+            let admission = if known {
+                Err((format!("request id '{}' is already in use", request_id), Vec::new()))
+            } else {
+                match ResponseValidator::validate_request(&human_request) {
+                    Err(e) => Err((e.to_string(), Vec::new())),
+                    Ok(()) => Self::admit_budgeted_request(&mut app_state_lock, client_id, human_request.session_id.as_deref()).await,
+                }
+            };
+            match admission {
+                Err((reason, breaches)) => (Err(reason), breaches),
+                Ok(breaches) => {
+                    let outcome = app_state_lock.policy_engine.evaluate(&human_request);
+                    if let Some((rule_id, priority)) = &outcome.priority {
+                        info!("Policy rule {} raised request {} to {} priority", rule_id, request_id, priority);
+                        human_request.priority = *priority;
+                        human_request.priority_reason = Some(format!("raised by policy rule '{}'", rule_id));
+                    }
+                    if let Some((rule_id, count)) = &outcome.required_approvers {
+                        info!("Policy rule {} requires {} approvers for request {}", rule_id, count, request_id);
+                        human_request.required_approvers = Some(*count);
+                    }
+                    
+                    app_state_lock.request_queue.push(human_request.clone());
+                    
+                    // Also save to database
+                    if let Err(e) = app_state_lock.database.save_human_request(&human_request).await {
+                        error!("Failed to save human request to database: {}", e);
+                    }
+                    audit::record(&app_state_lock.database, "request-created", &human_request.agent_name,
+                        Some(&human_request.id), serde_json::to_value(&human_request).unwrap_or_default()).await;
+                    
+                    (Ok(outcome), breaches)
+                }
             }
+        };
+        
+        for breach in breaches {
+            Self::enforce_budget(clients, app_state, client_id, breach).await;
         }
         
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(reason) => {
+                Self::reject_request(clients, app_state, client_id, &request_id, &reason).await;
+                return Ok(());
+            }
+        };
+        
         info!("Human input request created: {} from agent {}", request_id, client_id);
         
+        if let Some((rule_id, decision)) = outcome.decision {
+            if Self::apply_policy_decision(clients, app_state, &human_request, &rule_id, decision).await {
+                return Ok(());
+            }
+        }
+        
+        {
+            // Include signed links so the webhook's recipient can answer straight away
+            let app_state_lock = app_state.lock().await;
+            let mut data = serde_json::to_value(&human_request)?;
+            if let Some(links) = app_state_lock.response_links.links_for(&human_request) {
+                data["responseLinks"] = serde_json::to_value(links)?;
+            }
+            app_state_lock.webhooks.dispatch(WebhookEvent::HumanInputRequest, Some(human_request.priority), data);
+        }
+        
         // Broadcast to GUI clients
         Self::broadcast_to_guis(clients, "human-input-request", &human_request).await;
         
         Ok(())
     }
     
+    /// Answers a request on behalf of a policy rule. The response is recorded with
+    /// `responded_by = "policy:<rule id>"`. Returns false if the request still needs a human.
+    async fn apply_policy_decision(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        request: &HumanInputRequest,
+        rule_id: &str,
+        decision: PolicyDecision,
+    ) -> bool {
+        let approved = matches!(decision, PolicyDecision::Approve);
+        let (answer, reason) = match decision {
+            PolicyDecision::Approve => (request.approval_answer(), None),
+            PolicyDecision::Deny { reason } => (request.denial_answer(), reason),
+        };
+        
+        let response = HumanResponse {
+            request_id: request.id.clone(),
+            response: answer,
+            additional_context: reason,
+            responded_by: format!("policy:{}", rule_id),
+            timestamp: Utc::now(),
+            source: ResponseSource::Policy,
+        };
+        
+        let result = Self::complete_request(clients, app_state, response.clone()).await
+            .map_err(|e| e.to_string());
+        match result {
+            Ok(_) => {
+                info!("Request {} decided by policy rule {}", request.id, rule_id);
+                if approved {
+                    METRICS.auto_approved("policy");
+                }
+                Self::broadcast_to_guis(clients, "policy-decision", &serde_json::json!({
+                    "ruleId": rule_id,
+                    "request": request,
+                    "response": response,
+                })).await;
+                true
+            }
+            Err(e) => {
+                warn!("Policy rule {} could not decide request {}: {}", rule_id, request.id, e);
+                false
+            }
+        }
+    }
+    
+    /// Tells an agent that its request was refused instead of being shown to humans
+    async fn reject_request(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        client_id: &str,
+        request_id: &str,
+        reason: &str,
+    ) {
+        warn!("Rejected human input request {} from agent {}: {}", request_id, client_id, reason);
+        {
+            let app_state_lock = app_state.lock().await;
+            audit::record(&app_state_lock.database, "request-rejected", client_id, Some(request_id),
+                serde_json::json!({ "reason": reason })).await;
+        }
+        Self::send_to_client(clients, client_id, &serde_json::json!({
+            "type": "request-rejected",
+            "requestId": request_id,
+            "reason": reason,
+            "timestamp": Utc::now().to_rfc3339()
+        })).await;
+    }
+    
     async fn handle_human_input_response(
+        client_id: &str,
         message: &serde_json::Value,
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
         app_state: &AppState,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let request_id = message["requestId"].as_str().unwrap_or("");
-        let response = message["response"].as_str().unwrap_or("");
+        let operator = Self::gui_operator(clients, client_id).await?;
         
-        // Update request status in app state
-        let agent_id = {
-            let mut app_state_lock = app_state.lock().await;
-            if let Some(request) = app_state_lock.human_requests.iter_mut().find(|r| r.id == request_id) {
-                request.status = RequestStatus::Completed;
-                request.agent_id.clone()
-            } else {
-                return Err("Request not found".into());
-            }
+        let human_response = HumanResponse {
+            request_id: request_id.to_string(),
+            response: message["response"].clone(),
+            additional_context: message["additionalContext"].as_str().map(|s| s.to_string()),
+            responded_by: operator,
+            timestamp: Utc::now(),
+            source: ResponseSource::Operator,
         };
         
-        // Send response to agent
-        let response_message = serde_json::json!({
-            "type": "human-input-response",
-            "requestId": request_id,
-            "response": response,
-            "additionalContext": message["additionalContext"],
-            "timestamp": Utc::now().to_rfc3339()
-        });
-        
-        if let Ok(response_text) = serde_json::to_string(&response_message) {
-            let clients_lock = clients.read().await;
-            if let Some(client) = clients_lock.get(&agent_id) {
-                let _ = client.sender.send(Message::Text(response_text));
+        // Let the responding GUI know whether its answer was taken, and if not, why. When another
+        // operator got there first, say who so the GUI can show the winning answer.
+        let result = Self::complete_request(clients, app_state, human_response).await
+            .map_err(|e| {
+                let winner = match e.downcast_ref::<QueueError>() {
+                    Some(QueueError::AlreadyAnswered(responder)) => Some(responder.clone()),
+                    _ => None,
+                };
+                (e.to_string(), winner)
+            });
+        match result {
+            Ok(request) => {
+                Self::send_to_client(clients, client_id, &serde_json::json!({
+                    "type": "response-accepted",
+                    "requestId": request_id,
+                    "request": request,
+                    "timestamp": Utc::now().to_rfc3339()
+                })).await;
+                Ok(())
+            }
+            Err((reason, winner)) => {
+                Self::send_to_client(clients, client_id, &serde_json::json!({
+                    "type": "response-rejected",
+                    "requestId": request_id,
+                    "reason": reason,
+                    "conflict": winner.is_some(),
+                    "respondedBy": winner,
+                    "timestamp": Utc::now().to_rfc3339()
+                })).await;
+                Err(reason.into())
             }
         }
+    }
+    
+    /// A GUI asking to pause, resume, stop or change the verbosity of an agent
+    async fn handle_control_request(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let agent_id = message["agentId"].as_str().unwrap_or("");
+        let operator = Self::gui_operator(clients, client_id).await?;
+        
+        let result = match serde_json::from_value::<ControlCommand>(message.clone()) {
+            Ok(command) => Self::issue_control(clients, app_state, agent_id, command, &operator).await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(format!("Invalid control command: {}", e)),
+        };
         
-        info!("Human response sent to agent {}: {}", agent_id, response);
+        if let Err(reason) = result {
+            Self::send_to_client(clients, client_id, &serde_json::json!({
+                "type": "control-rejected",
+                "agentId": agent_id,
+                "reason": reason,
+                "timestamp": Utc::now().to_rfc3339()
+            })).await;
+            return Err(reason.into());
+        }
         
         Ok(())
     }
     
-    async fn handle_content_emission(
+    /// Sends a control command to an agent and tracks it until the agent acknowledges it
+    async fn issue_control(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        agent_id: &str,
+        command: ControlCommand,
+        operator: &str,
+    ) -> Result<AgentControl, Box<dyn std::error::Error>> {
+        {
+            let app_state_lock = app_state.lock().await;
+            let agent = app_state_lock.connected_agents.iter()
+                .find(|a| a.id == agent_id)
+                .ok_or_else(|| format!("Agent {} is not connected", agent_id))?;
+            app_state_lock.access_control.authorize(operator, Permission::Control, Some(agent))?;
+        }
+        
+        Self::send_control(clients, app_state, agent_id, command, operator).await
+    }
+    
+    /// Sends a control command without checking permissions; used directly only for
+    /// commands the server issues itself
+    async fn send_control(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        agent_id: &str,
+        command: ControlCommand,
+        operator: &str,
+    ) -> Result<AgentControl, Box<dyn std::error::Error>> {
+        let control = {
+            let mut app_state_lock = app_state.lock().await;
+            if !app_state_lock.connected_agents.iter().any(|a| a.id == agent_id) {
+                return Err(format!("Agent {} is not connected", agent_id).into());
+            }
+            
+            let control = AgentControl {
+                id: Uuid::new_v4().to_string(),
+                agent_id: agent_id.to_string(),
+                command,
+                issued_by: operator.to_string(),
+                issued_at: Utc::now(),
+                state: ControlState::Pending,
+                acknowledged_at: None,
+                error: None,
+            };
+            app_state_lock.pending_controls.insert(control.id.clone(), control.clone());
+            audit::record(&app_state_lock.database, "control-command", operator, Some(agent_id),
+                serde_json::to_value(&control)?).await;
+            control
+        };
+        
+        let mut message = serde_json::to_value(&control.command)?;
+        message["type"] = serde_json::Value::from("agent-control");
+        message["controlId"] = serde_json::Value::from(control.id.clone());
+        message["issuedBy"] = serde_json::Value::from(control.issued_by.clone());
+        message["timestamp"] = serde_json::Value::from(control.issued_at.to_rfc3339());
+        Self::send_to_client(clients, agent_id, &message).await;
+        
+        info!("Sent {:?} to agent {} on behalf of {}", control.command, agent_id, operator);
+        Self::broadcast_to_guis(clients, "agent-control", &control).await;
+        Ok(control)
+    }
+    
+    /// An agent confirming (or refusing) a control command
+    async fn handle_control_ack(
         client_id: &str,
         message: &serde_json::Value,
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
-        content_type: &str,
+        app_state: &AppState,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Content emission received: {} from agent {}", content_type, client_id);
+        let control_id = message["controlId"].as_str().unwrap_or("");
+        let success = message["success"].as_bool().unwrap_or(true);
         
-        // Simply forward the content emission to GUI clients
-        let forwarded_message = serde_json::json!({
-            "type": content_type,
-            "data": message["data"],
-            "timestamp": Utc::now().to_rfc3339()
-        });
-        
-        if let Ok(message_text) = serde_json::to_string(&forwarded_message) {
-            let clients_lock = clients.read().await;
-            for client in clients_lock.values() {
-                if matches!(client.client_type, ClientType::GUI) {
-                    let _ = client.sender.send(Message::Text(message_text.clone()));
+        let (control, agent) = {
+            let mut app_state_lock = app_state.lock().await;
+            let mut control = match app_state_lock.pending_controls.get(control_id) {
+                Some(control) if control.agent_id == client_id => control.clone(),
+                _ => return Err(format!("Unknown control command {}", control_id).into()),
+            };
+            app_state_lock.pending_controls.remove(control_id);
+            
+            control.acknowledged_at = Some(Utc::now());
+            if success {
+                control.state = ControlState::Acknowledged;
+            } else {
+                control.state = ControlState::Failed;
+                control.error = Some(message["error"].as_str().unwrap_or("refused by agent").to_string());
+            }
+            
+            let mut updated_agent = None;
+            if let Some(status) = control.command.resulting_status().filter(|_| success) {
+                if let Some(agent) = app_state_lock.connected_agents.iter_mut().find(|a| a.id == client_id) {
+                    agent.status = status;
+                    agent.last_activity = Utc::now();
+                    updated_agent = Some(agent.clone());
+                }
+            }
+            if let Some(agent) = &updated_agent {
+                if let Err(e) = app_state_lock.database.save_agent(agent).await {
+                    error!("Failed to save agent to database: {}", e);
                 }
             }
+            
+            audit::record(&app_state_lock.database, "control-ack", client_id, Some(&control.id),
+                serde_json::to_value(&control)?).await;
+            (control, updated_agent)
+        };
+        
+        info!("Agent {} acknowledged control {} ({:?})", client_id, control.id, control.state);
+        Self::broadcast_to_guis(clients, "agent-control-ack", &control).await;
+        if let Some(agent) = agent {
+            Self::broadcast_to_guis(clients, "agent-status", &agent).await;
         }
         
         Ok(())
     }
     
-    async fn broadcast_to_guis<T: serde::Serialize>(
+    /// A GUI sending an instruction to one agent, a list of agents or every agent with a tag
+    async fn handle_operator_message(
+        client_id: &str,
+        message: &serde_json::Value,
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
-        message_type: &str,
-        data: &T,
-    ) {
-        let message = serde_json::json!({
-            "type": message_type,
-            "data": data,
-            "timestamp": Utc::now().to_rfc3339()
-        });
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let operator = Self::gui_operator(clients, client_id).await?;
         
-        if let Ok(message_text) = serde_json::to_string(&message) {
-            let clients_lock = clients.read().await;
-            for client in clients_lock.values() {
-                if matches!(client.client_type, ClientType::GUI) {
-                    let _ = client.sender.send(Message::Text(message_text.clone()));
-                }
-            }
+        let mut agent_ids: Vec<String> = message["agentIds"].as_array()
+            .map(|ids| ids.iter().filter_map(|id| id.as_str()).map(|id| id.to_string()).collect())
+            .unwrap_or_default();
+        if let Some(agent_id) = message["agentId"].as_str() {
+            agent_ids.push(agent_id.to_string());
+        }
+        let tag = message["tag"].as_str();
+        let text = message["message"].as_str().unwrap_or("");
+        
+        let result = Self::deliver_operator_message(
+            clients, app_state, &agent_ids, tag, text, message["data"].clone(), &operator,
+        ).await.map_err(|e| e.to_string());
+        
+        if let Err(reason) = result {
+            Self::send_to_client(clients, client_id, &serde_json::json!({
+                "type": "operator-message-rejected",
+                "reason": reason,
+                "timestamp": Utc::now().to_rfc3339()
+            })).await;
+            return Err(reason.into());
         }
+        
+        Ok(())
     }
     
-    async fn cleanup_client(
-        client_id: &str,
+    /// Records an operator message for each targeted agent and sends it. Agents acknowledge
+    /// with `operator-message-ack`; until then the message stays undelivered and is returned
+    /// when the agent polls.
+    async fn deliver_operator_message(
         clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
         app_state: &AppState,
-    ) {
-        // Remove from clients
-        let client_info = {
-            let mut clients_lock = clients.write().await;
-            clients_lock.remove(client_id)
-        };
+        agent_ids: &[String],
+        tag: Option<&str>,
+        text: &str,
+        data: serde_json::Value,
+        operator: &str,
+    ) -> Result<Vec<AgentMessage>, Box<dyn std::error::Error>> {
+        if text.trim().is_empty() {
+            return Err("Operator messages must not be empty".into());
+        }
         
-        // If it was an agent, remove from app state and notify GUIs
-        if let Some(client) = client_info {
-            if matches!(client.client_type, ClientType::Agent) {
-                {
-                    let mut app_state_lock = app_state.lock().await;
-                    app_state_lock.connected_agents.retain(|a| a.id != client_id);
-                }
-                
-                // Notify GUI clients
-                let disconnect_message = serde_json::json!({
-                    "agentId": client_id,
-                    "name": client.agent_info.as_ref().map(|a| &a.name).unwrap_or(&"Unknown".to_string())
-                });
-                
-                Self::broadcast_to_guis(clients, "agent-disconnected", &disconnect_message).await;
+        let messages = {
+            let app_state_lock = app_state.lock().await;
+            let targets: Vec<&Agent> = app_state_lock.connected_agents.iter()
+                .filter(|a| agent_ids.contains(&a.id) || tag.is_some_and(|t| a.tags.iter().any(|at| at == t)))
+                .collect();
+            if targets.is_empty() {
+                return Err("No connected agent matches the message target".into());
+            }
+            for agent in &targets {
+                app_state_lock.access_control.authorize(operator, Permission::Respond, Some(agent))?;
+            }
+            
+            let mut messages = Vec::with_capacity(targets.len());
+            for agent in targets {
+                let message = AgentMessage {
+                    id: Uuid::new_v4().to_string(),
+                    agent_id: agent.id.clone(),
+                    message_type: "operator-message".to_string(),
+                    payload: serde_json::json!({ "message": text, "data": data }),
+                    timestamp: Utc::now(),
+                    direction: MessageDirection::Outbound,
+                    sender: Some(operator.to_string()),
+                    delivered_at: None,
+                    session_id: agent.run_id.clone(),
+                };
                 
-                info!("Agent {} disconnected and cleaned up", client_id);
+                if let Err(e) = app_state_lock.database.save_message(&message).await {
+                    error!("Failed to save operator message to database: {}", e);
+                }
+                audit::record(&app_state_lock.database, "operator-message", operator, Some(&agent.id),
+                    message.payload.clone()).await;
+                messages.push(message);
             }
+            messages
+        };
+        
+        for message in &messages {
+            Self::send_to_client(clients, &message.agent_id, &Self::operator_message_for_agent(message)).await;
+            Self::broadcast_to_guis(clients, "operator-message", message).await;
         }
+        
+        info!("{} sent an operator message to {} agent(s)", operator, messages.len());
+        Ok(messages)
     }
     
-    pub async fn send_response_to_agent(
-        &self,
-        agent_id: &str,
-        response: HumanResponse,
+    /// The wire format of an operator message as seen by agents
+    fn operator_message_for_agent(message: &AgentMessage) -> serde_json::Value {
+        serde_json::json!({
+            "type": "operator-message",
+            "messageId": message.id,
+            "message": message.payload["message"],
+            "data": message.payload["data"],
+            "from": message.sender,
+            "timestamp": message.timestamp.to_rfc3339()
+        })
+    }
+    
+    async fn handle_operator_message_ack(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let message = serde_json::json!({
-            "type": "human-input-response",
-            "requestId": response.request_id,
-            "response": response.response,
-            "additionalContext": response.additional_context,
-            "timestamp": response.timestamp.to_rfc3339()
-        });
+        let message_id = message["messageId"].as_str().unwrap_or("");
         
-        if let Ok(message_text) = serde_json::to_string(&message) {
-            let clients_lock = self.connected_clients.read().await;
-            if let Some(client) = clients_lock.get(agent_id) {
-                client.sender.send(Message::Text(message_text))?;
-                return Ok(());
+        let delivered = {
+            let app_state_lock = app_state.lock().await;
+            app_state_lock.database.mark_message_delivered(message_id, client_id).await?
+        };
+        
+        match delivered {
+            Some(delivered) => {
+                debug!("Agent {} acknowledged operator message {}", client_id, message_id);
+                Self::broadcast_to_guis(clients, "operator-message-delivered", &delivered).await;
             }
+            None => warn!("Agent {} acknowledged unknown operator message {}", client_id, message_id),
         }
         
-        Err("Agent not found or failed to serialize message".into())
+        Ok(())
     }
-}
\ No newline at end of file
+    
+    /// Resends every operator message the agent hasn't acknowledged yet
+    async fn handle_operator_message_poll(
+        client_id: &str,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let messages = {
+            let app_state_lock = app_state.lock().await;
+            app_state_lock.database.get_undelivered_messages(client_id).await?
+        };
+        
+        let messages: Vec<serde_json::Value> = messages.iter().map(Self::operator_message_for_agent).collect();
+        Self::send_to_client(clients, client_id, &serde_json::json!({
+            "type": "operator-messages",
+            "messages": messages,
+            "timestamp": Utc::now().to_rfc3339()
+        })).await;
+        
+        Ok(())
+    }
+    
+    /// Lets an agent withdraw one of its own pending requests
+    async fn handle_request_cancellation(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let request_id = message["requestId"].as_str().unwrap_or("");
+        let reason = message["reason"].as_str().map(|s| s.to_string());
+        
+        let cancelled = {
+            let mut app_state_lock = app_state.lock().await;
+            let request = app_state_lock.request_queue.pending_mut(request_id)
+                .filter(|r| r.agent_id == client_id)
+                .ok_or_else(|| QueueError::NotFound(request_id.to_string()))?;
+            request.status = RequestStatus::Cancelled;
+            let request = request.clone();
+            app_state_lock.request_queue.mark_resolved(request_id, RequestStatus::Cancelled, None);
+            
+            if let Err(e) = app_state_lock.database.update_request_status(request_id, &RequestStatus::Cancelled).await {
+                error!("Failed to update request status in database: {}", e);
+            }
+            audit::record(&app_state_lock.database, "request-cancelled", &request.agent_name, Some(request_id),
+                serde_json::json!({ "reason": reason })).await;
+            Self::export_span(&app_state_lock, |payloads| telemetry::human_input_span(&request, None, payloads));
+            request
+        };
+        
+        info!("Request {} cancelled by agent {}", request_id, client_id);
+        Self::broadcast_to_guis(clients, "request-cancelled", &serde_json::json!({
+            "request": cancelled,
+            "reason": reason,
+        })).await;
+        
+        Ok(())
+    }
+    
+    async fn handle_session_message(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        message_type: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let result = match message_type {
+            "start-session" => Self::start_session(clients, app_state, client_id, message).await,
+            "join-session" => {
+                let session_id = message["sessionId"].as_str().unwrap_or("");
+                Self::join_session(clients, app_state, client_id, session_id).await
+            }
+            _ => {
+                let status = message["status"].as_str().unwrap_or("completed");
+                let summary = message["summary"].as_str().map(|s| s.to_string());
+                Self::end_agent_session(clients, app_state, client_id, message["sessionId"].as_str(), status, summary).await
+            }
+        };
+        
+        match result.map_err(|e| e.to_string()) {
+            Ok(session) => {
+                Self::send_to_client(clients, client_id, &serde_json::json!({
+                    "type": format!("{}-ack", message_type),
+                    "session": session,
+                    "timestamp": Utc::now().to_rfc3339()
+                })).await;
+                Ok(())
+            }
+            Err(reason) => {
+                Self::send_to_client(clients, client_id, &serde_json::json!({
+                    "type": "session-rejected",
+                    "action": message_type,
+                    "sessionId": message["sessionId"],
+                    "reason": reason,
+                    "timestamp": Utc::now().to_rfc3339()
+                })).await;
+                Err(reason.into())
+            }
+        }
+    }
+    
+    fn new_session(id: String, name: Option<String>, metadata: Option<serde_json::Value>, started_by: &str) -> Session {
+        Session {
+            id,
+            name,
+            started_by: started_by.to_string(),
+            started_at: Utc::now(),
+            ended_at: None,
+            status: SessionStatus::Active,
+            summary: None,
+            metadata,
+        }
+    }
+    
+    /// Starts a session with the agent as its first member. Starting a session id that is
+    /// already active joins it instead.
+    async fn start_session(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        client_id: &str,
+        message: &serde_json::Value,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        let session_id = message["sessionId"].as_str()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        
+        let session = {
+            let app_state_lock = app_state.lock().await;
+            if app_state_lock.database.get_session(&session_id).await?.is_some() {
+                None
+            } else {
+                let metadata = &message["metadata"];
+                let session = Self::new_session(
+                    session_id.clone(),
+                    message["name"].as_str().map(|s| s.to_string()),
+                    if metadata.is_null() { None } else { Some(metadata.clone()) },
+                    client_id,
+                );
+                app_state_lock.database.save_session(&session).await?;
+                audit::record(&app_state_lock.database, "session-started", client_id, Some(&session.id),
+                    serde_json::to_value(&session)?).await;
+                Some(session)
+            }
+        };
+        
+        if let Some(session) = &session {
+            info!("Session {} started by agent {}", session.id, client_id);
+            Self::broadcast_to_guis(clients, "session-started", session).await;
+        }
+        
+        Self::join_session(clients, app_state, client_id, &session_id).await
+    }
+    
+    /// Moves the agent into an active session; everything it sends from then on is
+    /// attributed to that session
+    async fn join_session(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        client_id: &str,
+        session_id: &str,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        let (session, agent) = {
+            let mut app_state_lock = app_state.lock().await;
+            let session = app_state_lock.database.get_session(session_id).await?
+                .ok_or_else(|| format!("Session {} not found", session_id))?;
+            if session.status != SessionStatus::Active {
+                return Err(format!("Session {} has already ended ({})", session_id, session.status).into());
+            }
+            
+            let agent = app_state_lock.connected_agents.iter_mut()
+                .find(|a| a.id == client_id)
+                .ok_or("Only registered agents can join sessions")?;
+            agent.run_id = Some(session.id.clone());
+            let agent = agent.clone();
+            
+            app_state_lock.database.save_agent(&agent).await?;
+            audit::record(&app_state_lock.database, "session-joined", &agent.name, Some(session_id),
+                serde_json::Value::Null).await;
+            (session, agent)
+        };
+        
+        info!("Agent {} joined session {}", client_id, session_id);
+        Self::broadcast_to_guis(clients, "session-joined", &serde_json::json!({
+            "session": session,
+            "agent": agent,
+        })).await;
+        
+        Ok(session)
+    }
+    
+    /// Ends the agent's current session, or the given session if the agent is part of it
+    async fn end_agent_session(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        client_id: &str,
+        session_id: Option<&str>,
+        status: &str,
+        summary: Option<String>,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        let (agent_name, session_id) = {
+            let app_state_lock = app_state.lock().await;
+            let agent = app_state_lock.connected_agents.iter()
+                .find(|a| a.id == client_id)
+                .ok_or("Only registered agents can end sessions")?;
+            let current = agent.run_id.clone().ok_or("Agent is not in a session")?;
+            if session_id.is_some_and(|id| id != current) {
+                return Err(format!("Agent is not part of session {}", session_id.unwrap_or_default()).into());
+            }
+            (agent.name.clone(), current)
+        };
+        
+        let status = SessionStatus::parse(status)
+            .ok_or_else(|| format!("Unknown session status '{}'", status))?;
+        Self::finish_session(clients, app_state, &session_id, status, summary, &agent_name).await
+    }
+    
+    /// Records a session's final status and summary
+    async fn finish_session(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        session_id: &str,
+        status: SessionStatus,
+        summary: Option<String>,
+        ended_by: &str,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        if status == SessionStatus::Active {
+            return Err("Sessions must end as completed, failed or cancelled".into());
+        }
+        
+        let session = {
+            let app_state_lock = app_state.lock().await;
+            let mut session = app_state_lock.database.get_session(session_id).await?
+                .ok_or_else(|| format!("Session {} not found", session_id))?;
+            if session.status != SessionStatus::Active {
+                return Err(format!("Session {} has already ended ({})", session_id, session.status).into());
+            }
+            
+            session.status = status;
+            session.summary = summary;
+            session.ended_at = Some(Utc::now());
+            app_state_lock.database.save_session(&session).await?;
+            audit::record(&app_state_lock.database, "session-ended", ended_by, Some(session_id),
+                serde_json::json!({ "status": session.status, "summary": session.summary })).await;
+            Self::export_span(&app_state_lock, |_| telemetry::session_span(&session));
+            session
+        };
+        
+        info!("Session {} ended by {} ({})", session_id, ended_by, session.status);
+        Self::broadcast_to_guis(clients, "session-ended", &session).await;
+        
+        Ok(session)
+    }
+    
+    /// Builds a span and hands it to the trace exporter, if trace export is enabled. The
+    /// builder is told whether payloads may be included.
+    fn export_span(app_state_lock: &AppStateInner, build: impl FnOnce(bool) -> TraceSpan) {
+        if let Some(exporter) = &app_state_lock.telemetry {
+            exporter.export(build(exporter.include_payloads()));
+        }
+    }
+    
+    /// The operator behind `client_id`, for messages only a registered GUI may send
+    async fn gui_operator(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        client_id: &str,
+    ) -> Result<String, String> {
+        let clients_lock = clients.read().await;
+        clients_lock.get(client_id)
+            .filter(|c| matches!(c.client_type, ClientType::GUI))
+            .and_then(|c| c.operator.as_ref())
+            .map(|o| o.name.clone())
+            .ok_or_else(|| format!("Client {} is not a registered GUI", client_id))
+    }
+    
+    async fn handle_claim_message(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        message_type: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let request_id = message["requestId"].as_str().unwrap_or("");
+        let operator = Self::gui_operator(clients, client_id).await?;
+        
+        let result = if message_type == "claim-request" {
+            Self::claim(clients, app_state, request_id, &operator, Some(client_id)).await
+        } else {
+            Self::release(clients, app_state, request_id, &operator).await
+        };
+        
+        if let Err(reason) = result.map_err(|e| e.to_string()) {
+            Self::send_to_client(clients, client_id, &serde_json::json!({
+                "type": "claim-rejected",
+                "requestId": request_id,
+                "action": message_type,
+                "reason": reason,
+                "timestamp": Utc::now().to_rfc3339()
+            })).await;
+            return Err(reason.into());
+        }
+        
+        Ok(())
+    }
+    
+    /// Locks a request to an operator and tells every GUI it is taken
+    async fn claim(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        request_id: &str,
+        operator: &str,
+        client_id: Option<&str>,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        let request = {
+            let mut app_state_lock = app_state.lock().await;
+            let pending = app_state_lock.request_queue.get(request_id)
+                .ok_or_else(|| QueueError::NotFound(request_id.to_string()))?;
+            Self::authorize_response(&app_state_lock, operator, pending)?;
+            
+            let request = app_state_lock.request_queue.claim(request_id, operator, client_id)?;
+            if let Err(e) = app_state_lock.database.save_human_request(&request).await {
+                error!("Failed to save request claim to database: {}", e);
+            }
+            request
+        };
+        
+        info!("Request {} claimed by {}", request_id, operator);
+        Self::broadcast_to_guis(clients, "request-claimed", &request).await;
+        Ok(request)
+    }
+    
+    async fn release(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        request_id: &str,
+        operator: &str,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        let request = {
+            let mut app_state_lock = app_state.lock().await;
+            let request = app_state_lock.request_queue.release(request_id, operator)?;
+            if let Err(e) = app_state_lock.database.save_human_request(&request).await {
+                error!("Failed to save request release to database: {}", e);
+            }
+            request
+        };
+        
+        info!("Request {} released by {}", request_id, operator);
+        Self::broadcast_to_guis(clients, "request-released", &request).await;
+        Ok(request)
+    }
+    
+    /// Checks that `operator` may answer requests from the agent that sent `request`
+    fn authorize_response(
+        app_state: &AppStateInner,
+        operator: &str,
+        request: &HumanInputRequest,
+    ) -> Result<(), AccessError> {
+        let agent = app_state.connected_agents.iter().find(|a| a.id == request.agent_id);
+        app_state.access_control.authorize(operator, Permission::Respond, agent)
+    }
+    
+    /// Validates a response, marks its request completed, records it and delivers it to the agent.
+    /// Every way of answering a request goes through here.
+    async fn complete_request(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        response: HumanResponse,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        Self::resolve_request(clients, app_state, response, RequestStatus::Completed).await
+    }
+    
+    async fn resolve_request(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        mut response: HumanResponse,
+        final_status: RequestStatus,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        let (request, budget_breach) = {
+            let mut app_state_lock = app_state.lock().await;
+            let request = app_state_lock.request_queue.check_answerable(&response)?;
+            if !response.is_automated() {
+                Self::authorize_response(&app_state_lock, &response.responded_by, request)?;
+            }
+            
+            response.response = ResponseValidator::validate_response(request, &response.response)?;
+            let mut request = request.clone();
+            
+            // Operator answers to a request that needs several approvers count as votes; the
+            // agent only hears back once enough approvals are in or someone denies
+            if request.quorum() > 1 && !response.is_automated() {
+                let approved = request.decision_for(&response.response).ok_or_else(|| ValidationError::InvalidResponse(
+                    "requests that need several approvers must be approved or denied".to_string(),
+                ))?;
+                let approval = Approval {
+                    operator: response.responded_by.clone(),
+                    approved,
+                    comment: response.additional_context.clone(),
+                    timestamp: response.timestamp,
+                };
+                
+                request = app_state_lock.request_queue.record_approval(&request.id, approval.clone())?;
+                if let Err(e) = app_state_lock.database.save_approval(&request.id, &approval).await {
+                    error!("Failed to save approval to database: {}", e);
+                }
+                audit::record(&app_state_lock.database, "approval-vote", &approval.operator, Some(&request.id),
+                    serde_json::to_value(&approval)?).await;
+                
+                let approvers = request.approvers();
+                if approved && (approvers.len() as u32) < request.quorum() {
+                    drop(app_state_lock);
+                    info!("Request {} approved by {} ({}/{})", request.id, approval.operator, approvers.len(), request.quorum());
+                    Self::broadcast_to_guis(clients, "approval-recorded", &serde_json::json!({
+                        "request": request,
+                        "approval": approval,
+                    })).await;
+                    return Ok(request);
+                }
+                
+                if approved {
+                    response.response = request.approval_answer();
+                    response.responded_by = approvers.iter().map(|a| a.operator.as_str()).collect::<Vec<_>>().join(", ");
+                    let comments: Vec<&str> = approvers.iter().filter_map(|a| a.comment.as_deref()).collect();
+                    response.additional_context = if comments.is_empty() { None } else { Some(comments.join("\n")) };
+                }
+            }
+            
+            request.status = final_status.clone();
+            request.claimed_by = None;
+            app_state_lock.request_queue.mark_resolved(&request.id, final_status, Some(response.responded_by.clone()));
+            
+            if let Err(e) = app_state_lock.database.update_request_status(&request.id, &request.status).await {
+                error!("Failed to update request status in database: {}", e);
+            }
+            if let Err(e) = app_state_lock.database.save_human_response(&response).await {
+                error!("Failed to save human response to database: {}", e);
+            }
+            
+            let event = match response.source {
+                ResponseSource::Policy => "policy-decision",
+                ResponseSource::Timeout => "timeout-decision",
+                ResponseSource::Operator => "response",
+            };
+            audit::record(&app_state_lock.database, event, &response.responded_by, Some(&request.id),
+                serde_json::to_value(&response)?).await;
+            Self::export_span(&app_state_lock, |payloads| telemetry::human_input_span(&request, Some(&response), payloads));
+            app_state_lock.webhooks.dispatch(WebhookEvent::RequestCompleted, Some(request.priority), serde_json::json!({
+                "request": request,
+                "response": response,
+            }));
+            if !response.is_automated() {
+                METRICS.request_answered((response.timestamp - request.timestamp).num_milliseconds().max(0) as u64);
+            }
+            
+            let budget_breach = app_state_lock.budgets.take_approval_request(&request.id);
+            (request, budget_breach)
+        };
+        
+        // Budget approvals are the server's own requests; the agent just gets resumed or stopped
+        if let Some(breach) = budget_breach {
+            let approved = request.decision_for(&response.response).unwrap_or(false);
+            Self::settle_budget_request(clients, app_state, breach, approved).await;
+            Self::broadcast_to_guis(clients, "human-input-response", &response).await;
+            return Ok(request);
+        }
+        
+        // The answer is recorded by now, so an agent that has gone away only misses the message
+        match Self::deliver_response(clients, &request.agent_id, &response).await {
+            Ok(()) => info!("Human response sent to agent {} for request {}", request.agent_id, request.id),
+            Err(e) => warn!("Could not deliver the response to request {} to agent {}: {}", request.id, request.agent_id, e),
+        }
+        Self::broadcast_to_guis(clients, "human-input-response", &response).await;
+        
+        Ok(request)
+    }
+    
+    /// Periodically times out expired requests, escalates ones past their SLA and prunes
+    /// resolved ones from the queue
+    fn spawn_request_monitor(
+        clients: Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: AppState,
+    ) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                
+                let now = Utc::now();
+                let (expired, escalated, breaches) = {
+                    let mut app_state_lock = app_state.lock().await;
+                    let queue_config = app_state_lock.config.queue.clone();
+                    
+                    // Wall-clock limits are crossed by time passing rather than by any message
+                    let mut breaches = Vec::new();
+                    if app_state_lock.config.budgets.limits_wall_clock() {
+                        let agents: Vec<(String, Option<String>)> = app_state_lock.connected_agents.iter()
+                            .map(|a| (a.id.clone(), a.run_id.clone()))
+                            .collect();
+                        for (agent_id, session_id) in agents {
+                            let crossed = Self::check_budgets(&mut app_state_lock, &agent_id, session_id.as_deref()).await;
+                            breaches.extend(crossed.into_iter().map(|breach| (agent_id.clone(), breach)));
+                        }
+                    }
+                    
+                    let expired = app_state_lock.request_queue.expired(now);
+                    let escalated = app_state_lock.request_queue.age(now, &queue_config);
+                    app_state_lock.request_queue.prune(now, &queue_config);
+                    
+                    for request in &escalated {
+                        if let Err(e) = app_state_lock.database.save_human_request(request).await {
+                            error!("Failed to save escalated request to database: {}", e);
+                        }
+                    }
+                    
+                    (expired, escalated, breaches)
+                };
+                
+                for (agent_id, breach) in breaches {
+                    Self::enforce_budget(&clients, &app_state, &agent_id, breach).await;
+                }
+                
+                for request in escalated {
+                    info!("Request {} aged past its SLA ({} priority)", request.id, request.priority);
+                    Self::broadcast_to_guis(&clients, "request-escalated", &request).await;
+                }
+                
+                for request in expired {
+                    Self::handle_request_timeout(&clients, &app_state, request).await;
+                }
+            }
+        });
+    }
+    
+    /// Applies the request's `on_timeout` policy. Synthesized answers are recorded with
+    /// `responded_by = "system:timeout"` so they can't be mistaken for human decisions.
+    async fn handle_request_timeout(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        request: HumanInputRequest,
+    ) {
+        METRICS.request_timed_out();
+        let policy = TimeoutPolicy::effective_for(&request);
+        if policy != request.on_timeout {
+            warn!("Request {} is no longer low priority or needs several approvers, denying instead of auto-approving", request.id);
+        }
+        app_state.lock().await.webhooks.dispatch(WebhookEvent::RequestTimeout, Some(request.priority), serde_json::json!({
+            "request": request,
+            "onTimeout": policy,
+        }));
+        
+        if let Some(TimeoutPolicy::Escalate) = policy {
+            let escalated = {
+                let mut app_state_lock = app_state.lock().await;
+                if let Some(pending) = app_state_lock.request_queue.pending_mut(&request.id) {
+                    pending.timeout_seconds += request.timeout_seconds;
+                    pending.on_timeout = None;
+                }
+                let escalated = app_state_lock.request_queue
+                    .escalate(&request.id, "escalated after timing out".to_string());
+                if let Some(escalated) = &escalated {
+                    if let Err(e) = app_state_lock.database.save_human_request(escalated).await {
+                        error!("Failed to save escalated request to database: {}", e);
+                    }
+                }
+                escalated
+            };
+            
+            if let Some(escalated) = escalated {
+                info!("Request {} timed out and was escalated to {}", escalated.id, escalated.priority);
+                Self::broadcast_to_guis(clients, "request-escalated", &escalated).await;
+            }
+            return;
+        }
+        
+        if let Some(answer) = policy.as_ref().and_then(|p| p.answer_for(&request)) {
+            let response = HumanResponse {
+                request_id: request.id.clone(),
+                response: answer,
+                additional_context: None,
+                responded_by: "system:timeout".to_string(),
+                timestamp: Utc::now(),
+                source: ResponseSource::Timeout,
+            };
+            
+            match Self::resolve_request(clients, app_state, response, RequestStatus::Timeout).await {
+                Ok(_) => {
+                    info!("Request {} timed out and was answered by its on_timeout policy", request.id);
+                    if policy == Some(TimeoutPolicy::Approve) {
+                        METRICS.auto_approved("timeout");
+                    }
+                    return;
+                }
+                Err(e) => warn!("Could not apply on_timeout policy for request {}: {}", request.id, e),
+            }
+        }
+        
+        let timed_out = {
+            let mut app_state_lock = app_state.lock().await;
+            let timed_out = app_state_lock.request_queue.pending_mut(&request.id)
+                .map(|r| {
+                    r.status = RequestStatus::Timeout;
+                    r.clone()
+                });
+            if timed_out.is_some() {
+                app_state_lock.request_queue.mark_resolved(&request.id, RequestStatus::Timeout, None);
+                if let Err(e) = app_state_lock.database.update_request_status(&request.id, &RequestStatus::Timeout).await {
+                    error!("Failed to update request status in database: {}", e);
+                }
+                audit::record(&app_state_lock.database, "request-expired", "system:timeout", Some(&request.id),
+                    serde_json::json!({ "timeoutSeconds": request.timeout_seconds })).await;
+            }
+            if let Some(timed_out) = &timed_out {
+                Self::export_span(&app_state_lock, |payloads| telemetry::human_input_span(timed_out, None, payloads));
+            }
+            timed_out
+        };
+        
+        if let Some(timed_out) = timed_out {
+            Self::send_to_client(clients, &timed_out.agent_id, &serde_json::json!({
+                "type": "human-input-response",
+                "requestId": timed_out.id,
+                "response": null,
+                "timeout": true,
+                "timestamp": Utc::now().to_rfc3339()
+            })).await;
+            Self::broadcast_to_guis(clients, "request-timeout", &timed_out).await;
+            info!("Request {} timed out without an answer", timed_out.id);
+        }
+    }
+    
+    async fn handle_tool_call_start(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let call = {
+            let app_state_lock = app_state.lock().await;
+            let agent = app_state_lock.connected_agents.iter()
+                .find(|a| a.id == client_id)
+                .ok_or("Only registered agents can report tool calls")?;
+            
+            let call = ToolCall {
+                span_id: message["spanId"].as_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                parent_span_id: message["parentSpanId"].as_str().map(|s| s.to_string()),
+                agent_id: client_id.to_string(),
+                session_id: agent.run_id.clone(),
+                tool_name: message["toolName"].as_str().unwrap_or("unknown").to_string(),
+                arguments: if message["arguments"].is_null() { None } else { Some(message["arguments"].clone()) },
+                result: None,
+                error: None,
+                status: ToolCallStatus::Running,
+                started_at: Utc::now(),
+                ended_at: None,
+                duration_ms: None,
+            };
+            
+            if app_state_lock.database.get_tool_call(&call.span_id).await?.is_some() {
+                return Err(format!("Tool call span {} already exists", call.span_id).into());
+            }
+            app_state_lock.database.save_tool_call(&call).await?;
+            call
+        };
+        
+        debug!("Tool call {} ({}) started by agent {}", call.span_id, call.tool_name, client_id);
+        Self::broadcast_to_guis(clients, "tool-call-start", &call).await;
+        
+        Ok(())
+    }
+    
+    async fn handle_tool_call_end(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let span_id = message["spanId"].as_str().unwrap_or("");
+        
+        let call = {
+            let app_state_lock = app_state.lock().await;
+            let mut call = app_state_lock.database.get_tool_call(span_id).await?
+                .filter(|c| c.agent_id == client_id)
+                .ok_or_else(|| format!("Unknown tool call span {}", span_id))?;
+            if call.status != ToolCallStatus::Running {
+                return Err(format!("Tool call span {} has already ended", span_id).into());
+            }
+            
+            let ended_at = Utc::now();
+            call.error = message["error"].as_str().map(|s| s.to_string());
+            call.result = if message["result"].is_null() { None } else { Some(message["result"].clone()) };
+            call.status = if call.error.is_some() { ToolCallStatus::Failed } else { ToolCallStatus::Succeeded };
+            // Prefer the agent's own measurement; it excludes time spent in transit
+            call.duration_ms = Some(message["durationMs"].as_u64()
+                .unwrap_or_else(|| (ended_at - call.started_at).num_milliseconds().max(0) as u64));
+            call.ended_at = Some(ended_at);
+            
+            app_state_lock.database.save_tool_call(&call).await?;
+            Self::export_span(&app_state_lock, |payloads| telemetry::tool_call_span(&call, payloads));
+            call
+        };
+        
+        debug!("Tool call {} ({}) {} after {:?}ms", call.span_id, call.tool_name, call.status, call.duration_ms);
+        Self::broadcast_to_guis(clients, "tool-call-end", &call).await;
+        
+        Ok(())
+    }
+    
+    /// Records the tokens, cost and latency of one LLM call, broadcasts the running totals
+    /// and enforces the configured budgets
+    async fn handle_usage_report(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (report, agent_totals, session_totals, breaches) = {
+            let mut app_state_lock = app_state.lock().await;
+            let agent = app_state_lock.connected_agents.iter()
+                .find(|a| a.id == client_id)
+                .ok_or("Only registered agents can report usage")?;
+            // A negative or infinite cost would let an agent wind its budget back
+            let cost = match &message["cost"] {
+                serde_json::Value::Null => 0.0,
+                value => value.as_f64()
+                    .filter(|cost| cost.is_finite() && *cost >= 0.0)
+                    .ok_or_else(|| format!("Invalid cost in usage report: {}", value))?,
+            };
+            
+            let report = UsageReport {
+                id: message["id"].as_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                agent_id: client_id.to_string(),
+                session_id: agent.run_id.clone(),
+                model: message["model"].as_str().unwrap_or("unknown").to_string(),
+                input_tokens: message["inputTokens"].as_u64().unwrap_or(0),
+                output_tokens: message["outputTokens"].as_u64().unwrap_or(0),
+                cost,
+                latency_ms: message["latencyMs"].as_u64().unwrap_or(0),
+                timestamp: Utc::now(),
+            };
+            
+            // Agents pick report ids and may resend a report; only the first copy counts
+            let saved = app_state_lock.database.save_usage_report(&report).await
+                .map_err(|e| format!("Failed to save usage report to database: {}", e))?;
+            if !saved {
+                return Err(format!("Usage report {} was already recorded", report.id).into());
+            }
+            
+            let (agent_totals, session_totals) = app_state_lock.usage.record(&report);
+            let breaches = Self::check_budgets(&mut app_state_lock, client_id, report.session_id.as_deref()).await;
+            (report, agent_totals, session_totals, breaches)
+        };
+        
+        Self::broadcast_to_guis(clients, "usage-update", &serde_json::json!({
+            "report": report,
+            "agentTotals": agent_totals,
+            "sessionTotals": session_totals,
+        })).await;
+        
+        for breach in breaches {
+            Self::enforce_budget(clients, app_state, client_id, breach).await;
+        }
+        
+        Ok(())
+    }
+    
+    /// Compares an agent's and its session's consumption with the configured budgets and
+    /// returns the limits crossed for the first time
+    async fn check_budgets(
+        app_state: &mut AppStateInner,
+        agent_id: &str,
+        session_id: Option<&str>,
+    ) -> Vec<BudgetBreach> {
+        let config = app_state.config.budgets.clone();
+        let now = Utc::now();
+        let mut scopes = vec![BudgetScope::Agent(agent_id.to_string())];
+        scopes.extend(session_id.map(|id| BudgetScope::Session(id.to_string())));
+        
+        let mut breaches = Vec::new();
+        for scope in scopes {
+            let Some(budget) = config.for_scope(&scope) else {
+                continue;
+            };
+            
+            let (totals, started_at) = match &scope {
+                BudgetScope::Agent(id) => (
+                    app_state.usage.agent(id),
+                    app_state.connected_agents.iter().find(|a| &a.id == id).map(|a| a.connected_at),
+                ),
+                BudgetScope::Session(id) => (
+                    app_state.usage.session(id),
+                    app_state.database.get_session(id).await.ok().flatten().map(|s| s.started_at),
+                ),
+            };
+            let usage = BudgetUsage {
+                tokens: totals.total_tokens(),
+                cost: totals.cost,
+                wall_clock_seconds: started_at.map_or(0, |t| (now - t).num_seconds().max(0) as u64),
+                approvals: app_state.budgets.approvals(&scope),
+            };
+            breaches.extend(app_state.budgets.check(&scope, budget, usage));
+        }
+        breaches
+    }
+    
+    /// Counts a valid human input request against the agent's budgets, returning any limits
+    /// crossed. When a hard limit is, or would be, crossed the request is refused, isn't
+    /// counted, and the reason comes back with the breaches.
+    async fn admit_budgeted_request(
+        app_state: &mut AppStateInner,
+        agent_id: &str,
+        session_id: Option<&str>,
+    ) -> Result<Vec<BudgetBreach>, (String, Vec<BudgetBreach>)> {
+        let mut scopes = vec![BudgetScope::Agent(agent_id.to_string())];
+        scopes.extend(session_id.map(|id| BudgetScope::Session(id.to_string())));
+        let refusal = |scope: &BudgetScope| {
+            format!("The {} is over its budget; an operator has to raise the limit first", scope)
+        };
+        
+        if let Some(scope) = scopes.iter().find(|s| app_state.budgets.is_exceeded(s)) {
+            return Err((refusal(scope), Vec::new()));
+        }
+        
+        for scope in &scopes {
+            app_state.budgets.count_approval(scope);
+        }
+        let breaches = Self::check_budgets(app_state, agent_id, session_id).await;
+        match scopes.iter().find(|s| app_state.budgets.is_exceeded(s)) {
+            Some(scope) => {
+                let reason = refusal(scope);
+                for scope in &scopes {
+                    app_state.budgets.uncount_approval(scope);
+                }
+                Err((reason, breaches))
+            }
+            None => Ok(breaches),
+        }
+    }
+    
+    /// Agents a budget applies to: the whole session for session budgets
+    fn budget_targets(app_state: &AppStateInner, scope: &BudgetScope) -> Vec<String> {
+        app_state.connected_agents.iter()
+            .filter(|a| match scope {
+                BudgetScope::Agent(id) => &a.id == id,
+                BudgetScope::Session(id) => a.run_id.as_ref() == Some(id),
+            })
+            .map(|a| a.id.clone())
+            .collect()
+    }
+    
+    /// Warns about a soft limit. For a hard limit, pauses or stops the agents and, when
+    /// configured, asks operators whether they may continue.
+    async fn enforce_budget(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        agent_id: &str,
+        breach: BudgetBreach,
+    ) {
+        if breach.level == BudgetLevel::Soft {
+            info!("Soft budget limit crossed by {}: {}", breach.scope, breach.reason);
+            let targets = {
+                let app_state_lock = app_state.lock().await;
+                audit::record(&app_state_lock.database, "budget-warning", BUDGET_ACTOR, Some(agent_id),
+                    serde_json::to_value(&breach).unwrap_or_default()).await;
+                Self::budget_targets(&app_state_lock, &breach.scope)
+            };
+            
+            Self::broadcast_to_guis(clients, "budget-warning", &breach).await;
+            let mut warning = serde_json::to_value(&breach).unwrap_or_default();
+            warning["type"] = serde_json::Value::from("budget-warning");
+            for target in targets {
+                Self::send_to_client(clients, &target, &warning).await;
+            }
+            return;
+        }
+        
+        warn!("Hard budget limit crossed by {}: {}", breach.scope, breach.reason);
+        let (targets, action, approval_request) = {
+            let mut app_state_lock = app_state.lock().await;
+            audit::record(&app_state_lock.database, "budget-exceeded", BUDGET_ACTOR, Some(agent_id),
+                serde_json::to_value(&breach).unwrap_or_default()).await;
+            
+            let targets = Self::budget_targets(&app_state_lock, &breach.scope);
+            let action = app_state_lock.config.budgets.on_exceeded;
+            let approval_request = if action == BudgetAction::RequestApproval {
+                let request = Self::budget_approval_request(&app_state_lock, agent_id, &breach);
+                app_state_lock.budgets.track_approval_request(&request.id, breach.clone());
+                app_state_lock.request_queue.push(request.clone());
+                if let Err(e) = app_state_lock.database.save_human_request(&request).await {
+                    error!("Failed to save human request to database: {}", e);
+                }
+                audit::record(&app_state_lock.database, "request-created", BUDGET_ACTOR, Some(&request.id),
+                    serde_json::to_value(&request).unwrap_or_default()).await;
+                Some(request)
+            } else {
+                None
+            };
+            (targets, action, approval_request)
+        };
+        
+        Self::broadcast_to_guis(clients, "budget-exceeded", &breach).await;
+        let command = if action == BudgetAction::Stop { ControlCommand::Stop } else { ControlCommand::Pause };
+        for target in targets {
+            if let Err(e) = Self::send_control(clients, app_state, &target, command.clone(), BUDGET_ACTOR).await {
+                warn!("Could not send {:?} to agent {} after a budget breach: {}", command, target, e);
+            }
+        }
+        if let Some(request) = approval_request {
+            Self::broadcast_to_guis(clients, "human-input-request", &request).await;
+        }
+    }
+    
+    fn budget_approval_request(app_state: &AppStateInner, agent_id: &str, breach: &BudgetBreach) -> HumanInputRequest {
+        let agent = app_state.connected_agents.iter().find(|a| a.id == agent_id);
+        let agent_name = agent.map(|a| a.name.clone()).unwrap_or_else(|| "Unknown Agent".to_string());
+        let subject = match &breach.scope {
+            BudgetScope::Agent(_) => agent_name.clone(),
+            BudgetScope::Session(id) => format!("Session {}", id),
+        };
+        
+        HumanInputRequest {
+            id: Uuid::new_v4().to_string(),
+            agent_id: agent_id.to_string(),
+            agent_name,
+            request_type: RequestType::Approval,
+            message: format!("{} is paused because it {}. Let it continue?", subject, breach.reason),
+            options: Vec::new(),
+            context: Some(serde_json::json!({ "budget": breach })),
+            schema: None,
+            min_selections: None,
+            max_selections: None,
+            timeout_seconds: 600,
+            on_timeout: Some(TimeoutPolicy::Deny),
+            timestamp: Utc::now(),
+            status: RequestStatus::Pending,
+            priority: RequestPriority::High,
+            priority_reason: Some("budget exceeded".to_string()),
+            claimed_by: None,
+            required_approvers: None,
+            approvals: Vec::new(),
+            session_id: agent.and_then(|a| a.run_id.clone()),
+        }
+    }
+    
+    /// Lifts the hard limit and resumes the agents a budget approval request was about, or
+    /// stops them if it was denied
+    async fn settle_budget_request(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        breach: BudgetBreach,
+        approved: bool,
+    ) {
+        let targets = {
+            let mut app_state_lock = app_state.lock().await;
+            if approved {
+                app_state_lock.budgets.raise(&breach.scope, BudgetLimits::default());
+            }
+            Self::budget_targets(&app_state_lock, &breach.scope)
+        };
+        let command = if approved { ControlCommand::Resume } else { ControlCommand::Stop };
+        info!("Budget request for {} {}", breach.scope, if approved { "approved" } else { "denied" });
+        
+        for agent_id in targets {
+            if let Err(e) = Self::send_control(clients, app_state, &agent_id, command.clone(), BUDGET_ACTOR).await {
+                warn!("Could not send {:?} to agent {}: {}", command, agent_id, e);
+            }
+        }
+    }
+    
+    /// Replaces a scope's hard limits. Agents that were halted for going over budget are
+    /// resumed unless the budget stops them.
+    async fn raise_budget_limit(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        scope: BudgetScope,
+        limits: BudgetLimits,
+        operator: &str,
+    ) {
+        let (was_exceeded, targets, action) = {
+            let mut app_state_lock = app_state.lock().await;
+            let was_exceeded = app_state_lock.budgets.raise(&scope, limits.clone());
+            audit::record(&app_state_lock.database, "budget-raised", operator, None, serde_json::json!({
+                "scope": scope,
+                "limits": limits,
+            })).await;
+            (was_exceeded, Self::budget_targets(&app_state_lock, &scope), app_state_lock.config.budgets.on_exceeded)
+        };
+        
+        info!("{} raised the budget of {}", operator, scope);
+        Self::broadcast_to_guis(clients, "budget-raised", &serde_json::json!({
+            "scope": scope,
+            "limits": limits,
+            "raisedBy": operator,
+        })).await;
+        
+        if was_exceeded && action != BudgetAction::Stop {
+            for agent_id in targets {
+                if let Err(e) = Self::send_control(clients, app_state, &agent_id, ControlCommand::Resume, operator).await {
+                    warn!("Could not resume agent {}: {}", agent_id, e);
+                }
+            }
+        }
+    }
+    
+    async fn handle_content_emission(
+        client_id: &str,
+        message: &serde_json::Value,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        content_type: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Content emission received: {} from agent {}", content_type, client_id);
+        METRICS.content_emitted(content_type);
+        
+        // Keep a record of the emission so the session can be exported later
+        let mut content_message = AgentMessage {
+            id: Uuid::new_v4().to_string(),
+            agent_id: client_id.to_string(),
+            message_type: content_type.to_string(),
+            payload: message["data"].clone(),
+            timestamp: Utc::now(),
+            direction: MessageDirection::Inbound,
+            sender: None,
+            delivered_at: None,
+            session_id: None,
+        };
+        
+        {
+            let app_state_lock = app_state.lock().await;
+            content_message.session_id = app_state_lock.connected_agents.iter()
+                .find(|a| a.id == client_id)
+                .and_then(|a| a.run_id.clone());
+            if let Err(e) = app_state_lock.database.save_message(&content_message).await {
+                error!("Failed to save content emission to database: {}", e);
+            }
+        }
+        
+        // Simply forward the content emission to GUI clients
+        let forwarded_message = serde_json::json!({
+            "type": content_type,
+            "data": message["data"],
+            "timestamp": Utc::now().to_rfc3339()
+        });
+        
+        if let Ok(message_text) = serde_json::to_string(&forwarded_message) {
+            let clients_lock = clients.read().await;
+            for client in clients_lock.values() {
+                if matches!(client.client_type, ClientType::GUI)
+                    && client.sender.send(Message::Text(message_text.clone())).is_err() {
+                    METRICS.message_dropped();
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    async fn broadcast_to_guis<T: serde::Serialize>(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        message_type: &str,
+        data: &T,
+    ) {
+        let message = serde_json::json!({
+            "type": message_type,
+            "data": data,
+            "timestamp": Utc::now().to_rfc3339()
+        });
+        Self::send_to_guis(clients, &message).await;
+    }
+    
+    async fn send_to_guis(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        message: &serde_json::Value,
+    ) {
+        if let Ok(message_text) = serde_json::to_string(message) {
+            let clients_lock = clients.read().await;
+            for client in clients_lock.values() {
+                if matches!(client.client_type, ClientType::GUI)
+                    && client.sender.send(Message::Text(message_text.clone())).is_err() {
+                    METRICS.message_dropped();
+                }
+            }
+        }
+    }
+    
+    async fn handle_http(
+        request: &HttpRequest,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> HttpResponse {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => Self::metrics_response(clients, app_state).await,
+            ("GET", "/healthz") => Self::health_response(clients, app_state, false).await,
+            ("GET", "/readyz") => Self::health_response(clients, app_state, true).await,
+            (_, "/metrics" | "/healthz" | "/readyz") => HttpResponse::text(405, "Method not allowed\n"),
+            ("GET" | "POST", path) if path.starts_with("/respond/") => {
+                Self::handle_link_response(request, clients, app_state).await
+            }
+            _ => HttpResponse::not_found(),
+        }
+    }
+    
+    /// Answers a request from a signed link, going through the same path as answers from a
+    /// GUI. Opening a link only shows a confirmation page, so mail scanners and previews that
+    /// fetch it can't answer; the page posts the link's parameters back to give the answer.
+    /// Links are spent before the answer is submitted so two posts can't both count.
+    async fn handle_link_response(
+        request: &HttpRequest,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> HttpResponse {
+        let request_id = request.path.trim_start_matches("/respond/");
+        let confirming = request.method == "GET";
+        let params = if confirming { request.query.clone() } else { request.form() };
+        
+        let response = {
+            let app_state_lock = app_state.lock().await;
+            let action = match app_state_lock.response_links.verify(request_id, &params) {
+                Ok(action) => action,
+                Err(e) => {
+                    let status = match e {
+                        LinkError::Disabled => 404,
+                        LinkError::Malformed(_) => 400,
+                        LinkError::BadSignature => 403,
+                        LinkError::Expired | LinkError::AlreadyUsed => 410,
+                    };
+                    warn!("Rejected response link for request {}: {}", request_id, e);
+                    return Self::link_page(status, "Link not accepted", &e.to_string());
+                }
+            };
+            
+            let Some(pending) = app_state_lock.request_queue.get(request_id).cloned() else {
+                return Self::link_page(404, "Request not found", "The request no longer exists.");
+            };
+            
+            // Confirming only looks at whether the links are spent; answering spends them
+            let used = if confirming {
+                app_state_lock.database.response_link_used(request_id).await
+            } else {
+                app_state_lock.database.consume_response_link(request_id).await.map(|fresh| !fresh)
+            }.map_err(|e| e.to_string());
+            match used {
+                Ok(false) => {}
+                Ok(true) => return Self::link_page(410, "Link not accepted", &LinkError::AlreadyUsed.to_string()),
+                Err(e) => {
+                    error!("Failed to check response link for request {}: {}", request_id, e);
+                    return Self::link_page(500, "Something went wrong", "The answer could not be recorded.");
+                }
+            }
+            
+            let (answer, label) = match action {
+                LinkAction::Approve => (pending.approval_answer(), "Approve".to_string()),
+                LinkAction::Deny => (pending.denial_answer(), "Deny".to_string()),
+                LinkAction::Choice(option_id) => {
+                    let label = pending.options.iter()
+                        .find(|o| o.id == option_id)
+                        .map_or_else(|| option_id.clone(), |o| o.label.clone());
+                    (serde_json::Value::from(option_id), label)
+                }
+            };
+            if confirming {
+                return Self::link_confirmation(&pending, &label, &params);
+            }
+            
+            HumanResponse {
+                request_id: request_id.to_string(),
+                response: answer,
+                additional_context: None,
+                responded_by: app_state_lock.response_links.operator().to_string(),
+                timestamp: Utc::now(),
+                source: ResponseSource::Operator,
+            }
+        };
+        
+        // Answers turned away before anything was recorded get their link back; anything later
+        // has already resolved the request, so the link stays spent
+        let rejection = match Self::complete_request(clients, app_state, response.clone()).await {
+            Ok(_) => None,
+            Err(e) if Self::rejected_before_resolving(&*e) => Some(e.to_string()),
+            Err(e) => {
+                error!("Request {} was answered through a response link, but: {}", request_id, e);
+                None
+            }
+        };
+        match rejection {
+            None => {
+                info!("Request {} answered through a response link", request_id);
+                Self::link_page(200, "Response recorded", &format!("Your answer ({}) was sent.", response.response))
+            }
+            Some(reason) => {
+                let restored = app_state.lock().await.database.restore_response_link(request_id).await
+                    .map_err(|e| e.to_string());
+                if let Err(restore_error) = restored {
+                    error!("Failed to restore response link for request {}: {}", request_id, restore_error);
+                }
+                Self::link_page(409, "Response not accepted", &reason)
+            }
+        }
+    }
+    
+    /// Whether `complete_request` refused an answer before recording any of it
+    fn rejected_before_resolving(e: &(dyn std::error::Error + 'static)) -> bool {
+        e.is::<QueueError>() || e.is::<AccessError>() || e.is::<ValidationError>()
+    }
+    
+    fn link_page(status: u16, title: &str, message: &str) -> HttpResponse {
+        Self::link_html(status, title, &format!("<p>{}</p>", escape_html(message)))
+    }
+    
+    /// Shows what a link answers, with a form that posts the link's parameters back
+    fn link_confirmation(pending: &HumanInputRequest, label: &str, params: &HashMap<String, String>) -> HttpResponse {
+        let fields: String = ["action", "option", "expires", "sig"].iter()
+            .filter_map(|name| params.get(*name).map(|value| (name, value)))
+            .map(|(name, value)| format!("<input type=\"hidden\" name=\"{}\" value=\"{}\">", name, escape_html(value)))
+            .collect();
+        Self::link_html(200, "Confirm your answer", &format!(
+            "<p>{} asks:</p><blockquote>{}</blockquote>\
+             <form method=\"post\">{}<button type=\"submit\">{}</button></form>",
+            escape_html(&pending.agent_name), escape_html(&pending.message), fields, escape_html(label),
+        ))
+    }
+    
+    fn link_html(status: u16, title: &str, body: &str) -> HttpResponse {
+        HttpResponse::html(status, format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Agent HUD - {0}</title></head>\
+             <body style=\"font-family: sans-serif; margin: 3em\"><h1>{0}</h1>{1}</body></html>\n",
+            escape_html(title), body,
+        ))
+    }
+    
+    /// Liveness only fails when the database can't be reached; readiness also requires the
+    /// schema to be in place and the listener to be registered and accepting connections
+    async fn health_response(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+        readiness: bool,
+    ) -> HttpResponse {
+        let guis = {
+            let clients_lock = clients.read().await;
+            clients_lock.values().filter(|c| matches!(c.client_type, ClientType::GUI)).count()
+        };
+        
+        let app_state_lock = app_state.lock().await;
+        let database = app_state_lock.database.ping().await.map_err(|e| e.to_string());
+        let schema_ready = app_state_lock.database.schema_ready().await.unwrap_or(false);
+        let listener = app_state_lock.websocket_server.as_ref()
+            .map(|server| (server.get_port(), server.is_listening()));
+        let pending_requests = app_state_lock.request_queue.all().iter()
+            .filter(|r| matches!(r.status, RequestStatus::Pending))
+            .count();
+        
+        let listening = listener.is_some_and(|(_, listening)| listening);
+        let healthy = database.is_ok();
+        let ready = healthy && schema_ready && listening;
+        let ok = if readiness { ready } else { healthy };
+        
+        let body = serde_json::json!({
+            "status": if ok { "ok" } else { "unavailable" },
+            "uptimeSeconds": (Utc::now() - app_state_lock.started_at).num_seconds(),
+            "version": env!("CARGO_PKG_VERSION"),
+            "checks": {
+                "database": {
+                    "ok": database.is_ok(),
+                    "error": database.err(),
+                },
+                "migrations": { "ok": schema_ready },
+                "listener": {
+                    "ok": listening,
+                    "port": listener.map(|(port, _)| port),
+                },
+            },
+            "agents": app_state_lock.connected_agents.len(),
+            "guis": guis,
+            "pendingRequests": pending_requests,
+        });
+        
+        HttpResponse::json(if ok { 200 } else { 503 }, &body)
+    }
+    
+    async fn metrics_response(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) -> HttpResponse {
+        let mut gauges = Gauges::default();
+        {
+            let clients_lock = clients.read().await;
+            for client in clients_lock.values() {
+                match client.client_type {
+                    ClientType::Agent => gauges.agents += 1,
+                    ClientType::GUI => gauges.guis += 1,
+                }
+            }
+        }
+        
+        {
+            let app_state_lock = app_state.lock().await;
+            for request in app_state_lock.request_queue.all() {
+                if matches!(request.status, RequestStatus::Pending) {
+                    *gauges.pending_by_priority.entry(request.priority).or_default() += 1;
+                    if request.claimed_by.is_some() {
+                        gauges.claimed_requests += 1;
+                    }
+                }
+            }
+            gauges.pending_controls = app_state_lock.pending_controls.len();
+            match app_state_lock.database.count_sessions(SessionStatus::Active).await {
+                Ok(count) => gauges.active_sessions = count as usize,
+                Err(e) => error!("Failed to count active sessions for metrics: {}", e),
+            }
+        }
+        
+        HttpResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: METRICS.render(&gauges),
+        }
+    }
+    
+    async fn cleanup_client(
+        client_id: &str,
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        app_state: &AppState,
+    ) {
+        // Remove from clients
+        let client_info = {
+            let mut clients_lock = clients.write().await;
+            clients_lock.remove(client_id)
+        };
+        
+        // Free up anything a departing operator had claimed
+        if let Some(operator) = client_info.as_ref().and_then(|c| c.operator.clone()) {
+            let released = {
+                let mut app_state_lock = app_state.lock().await;
+                let released = app_state_lock.request_queue.release_client(client_id);
+                for request in &released {
+                    if let Err(e) = app_state_lock.database.save_human_request(request).await {
+                        error!("Failed to save request release to database: {}", e);
+                    }
+                }
+                released
+            };
+            
+            for request in released {
+                info!("Request {} released after {} disconnected", request.id, operator.name);
+                Self::broadcast_to_guis(clients, "request-released", &request).await;
+            }
+            Self::broadcast_to_guis(clients, "operator-disconnected", &operator).await;
+        }
+        
+        // If it was an agent, remove from app state and notify GUIs
+        if let Some(client) = client_info {
+            if matches!(client.client_type, ClientType::Agent) {
+                let (dropped_controls, tree, orphaned_session) = {
+                    let mut app_state_lock = app_state.lock().await;
+                    // The live record carries status and session changes made since registration
+                    let live_agent = app_state_lock.connected_agents.iter()
+                        .position(|a| a.id == client_id)
+                        .map(|index| app_state_lock.connected_agents.remove(index));
+                    let tree = AgentNode::build_forest(app_state_lock.connected_agents.clone());
+                    
+                    // A session nobody is left in can't be ended by its agents any more
+                    let orphaned_session = live_agent.as_ref()
+                        .and_then(|agent| agent.run_id.clone())
+                        .filter(|run_id| !app_state_lock.connected_agents.iter().any(|a| a.run_id.as_ref() == Some(run_id)));
+                    
+                    // Commands the agent never acknowledged can no longer be carried out
+                    let dropped_ids: Vec<String> = app_state_lock.pending_controls.values()
+                        .filter(|c| c.agent_id == client_id)
+                        .map(|c| c.id.clone())
+                        .collect();
+                    let dropped_controls: Vec<AgentControl> = dropped_ids.iter()
+                        .filter_map(|id| app_state_lock.pending_controls.remove(id))
+                        .map(|mut control| {
+                            control.state = ControlState::Failed;
+                            control.error = Some("agent disconnected".to_string());
+                            control
+                        })
+                        .collect();
+                    
+                    if let Some(agent) = live_agent.as_ref().or(client.agent_info.as_ref()) {
+                        let mut agent = agent.clone();
+                        agent.status = AgentStatus::Disconnected;
+                        agent.last_activity = Utc::now();
+                        if let Err(e) = app_state_lock.database.save_agent(&agent).await {
+                            error!("Failed to save agent to database: {}", e);
+                        }
+                        audit::record(&app_state_lock.database, "agent-disconnected", &agent.name, Some(client_id),
+                            serde_json::Value::Null).await;
+                        app_state_lock.webhooks.dispatch(WebhookEvent::AgentDisconnected, None,
+                            serde_json::to_value(&agent).unwrap_or_default());
+                    }
+                    
+                    (dropped_controls, tree, orphaned_session)
+                };
+                
+                for control in dropped_controls {
+                    Self::broadcast_to_guis(clients, "agent-control-ack", &control).await;
+                }
+                
+                if let Some(session_id) = orphaned_session {
+                    let summary = Some("Ended when its last agent disconnected".to_string());
+                    let ended = Self::finish_session(clients, app_state, &session_id, SessionStatus::Cancelled, summary, DISCONNECT_ACTOR).await
+                        .map_err(|e| e.to_string());
+                    if let Err(e) = ended {
+                        // Already ended sessions are fine; the agent just never left them
+                        debug!("Session {} not ended on disconnect: {}", session_id, e);
+                    }
+                }
+                
+                // Notify GUI clients
+                let disconnect_message = serde_json::json!({
+                    "agentId": client_id,
+                    "name": client.agent_info.as_ref().map(|a| &a.name).unwrap_or(&"Unknown".to_string())
+                });
+                
+                Self::broadcast_to_guis(clients, "agent-disconnected", &disconnect_message).await;
+                Self::broadcast_to_guis(clients, "agent-tree", &tree).await;
+                
+                info!("Agent {} disconnected and cleaned up", client_id);
+            }
+        }
+    }
+    
+    /// Ends a session on behalf of an operator
+    pub async fn end_session(
+        &self,
+        session_id: &str,
+        status: SessionStatus,
+        summary: Option<String>,
+        operator: &str,
+    ) -> Result<Session, Box<dyn std::error::Error>> {
+        Self::finish_session(&self.connected_clients, &self.app_state, session_id, status, summary, operator).await
+    }
+    
+    pub async fn raise_budget(&self, scope: BudgetScope, limits: BudgetLimits, operator: &str) {
+        Self::raise_budget_limit(&self.connected_clients, &self.app_state, scope, limits, operator).await;
+    }
+    
+    /// Broadcasts a message to every connected GUI client
+    pub async fn broadcast<T: serde::Serialize>(&self, message_type: &str, data: &T) {
+        Self::broadcast_to_guis(&self.connected_clients, message_type, data).await;
+    }
+    
+    /// Broadcasts a record played back by a replay. The `replayId` tells GUIs it isn't live,
+    /// so they can keep it apart and clear it when the replay seeks.
+    pub async fn broadcast_replayed<T: serde::Serialize>(&self, replay_id: &str, message_type: &str, data: &T) {
+        let message = serde_json::json!({
+            "type": message_type,
+            "data": data,
+            "replayId": replay_id,
+            "timestamp": Utc::now().to_rfc3339()
+        });
+        Self::send_to_guis(&self.connected_clients, &message).await;
+    }
+    
+    pub async fn send_human_response(
+        &self,
+        response: HumanResponse,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        Self::complete_request(&self.connected_clients, &self.app_state, response).await
+    }
+    
+    pub async fn claim_request(
+        &self,
+        request_id: &str,
+        operator: &str,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        Self::claim(&self.connected_clients, &self.app_state, request_id, operator, None).await
+    }
+    
+    pub async fn release_request(
+        &self,
+        request_id: &str,
+        operator: &str,
+    ) -> Result<HumanInputRequest, Box<dyn std::error::Error>> {
+        Self::release(&self.connected_clients, &self.app_state, request_id, operator).await
+    }
+    
+    pub async fn send_agent_control(
+        &self,
+        agent_id: &str,
+        command: ControlCommand,
+        operator: &str,
+    ) -> Result<AgentControl, Box<dyn std::error::Error>> {
+        Self::issue_control(&self.connected_clients, &self.app_state, agent_id, command, operator).await
+    }
+    
+    pub async fn send_operator_message(
+        &self,
+        agent_ids: &[String],
+        tag: Option<&str>,
+        text: &str,
+        data: serde_json::Value,
+        operator: &str,
+    ) -> Result<Vec<AgentMessage>, Box<dyn std::error::Error>> {
+        Self::deliver_operator_message(&self.connected_clients, &self.app_state, agent_ids, tag, text, data, operator).await
+    }
+    
+    /// Operators of the currently connected GUI clients
+    pub async fn operators(&self) -> Vec<Operator> {
+        let clients_lock = self.connected_clients.read().await;
+        clients_lock.values()
+            .filter_map(|c| c.operator.clone())
+            .collect()
+    }
+    
+    async fn deliver_response(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        agent_id: &str,
+        response: &HumanResponse,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let message = serde_json::json!({
+            "type": "human-input-response",
+            "requestId": response.request_id,
+            "response": response.response,
+            "additionalContext": response.additional_context,
+            "respondedBy": response.responded_by,
+            "timestamp": response.timestamp.to_rfc3339()
+        });
+        
+        let message_text = serde_json::to_string(&message)?;
+        let clients_lock = clients.read().await;
+        if let Some(client) = clients_lock.get(agent_id) {
+            if let Err(e) = client.sender.send(Message::Text(message_text)) {
+                METRICS.message_dropped();
+                return Err(e.into());
+            }
+            return Ok(());
+        }
+        
+        METRICS.message_dropped();
+        Err("Agent not found".into())
+    }
+    
+    async fn send_to_client(
+        clients: &Arc<RwLock<HashMap<String, ClientConnection>>>,
+        client_id: &str,
+        message: &serde_json::Value,
+    ) {
+        if let Ok(message_text) = serde_json::to_string(message) {
+            let clients_lock = clients.read().await;
+            let sent = clients_lock.get(client_id)
+                .is_some_and(|client| client.sender.send(Message::Text(message_text)).is_ok());
+            if !sent {
+                METRICS.message_dropped();
+            }
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -2,11 +2,16 @@
 class AgentHUDApp {
     constructor() {
         this.agents = [];
+        this.agentTree = []; // Agents nested under the agents that spawned them
+        this.usage = {}; // Running token and cost totals by agent id
         this.humanRequests = [];
         this.contentItems = []; // New: content emissions
         this.latestContent = null; // New: latest content item
         this.currentContentIndex = -1; // New: current content index for navigation
         this.currentRequestId = null;
+        this.replay = null; // Status of the replay being played back, if any
+        this.selectedOption = null; // Option picked in the response modal
+        this.pendingResponses = new Map(); // Request id -> callback waiting for the server's verdict
         this.activeTab = 'all';
         this.activeMainTab = 'requests'; // New: main tab state
         this.ws = null;
@@ -62,9 +67,7 @@ class AgentHUDApp {
                 }
                 
                 // Register as GUI client
-                this.ws.send(JSON.stringify({
-                    type: 'register-gui'
-                }));
+                this.registerGui(ws);
                 
                 resolve(); // Resolve the promise on successful connection
             };
@@ -102,7 +105,29 @@ class AgentHUDApp {
         });
     }
     
+    // The app's own webview acts as the desktop operator; a plain browser uses the operator
+    // name and token saved in local storage, if any
+    async registerGui(ws) {
+        let operator = localStorage.getItem('operatorName') || undefined;
+        let token = localStorage.getItem('operatorToken') || undefined;
+        if (window.__TAURI__) {
+            try {
+                token = await window.__TAURI__.core.invoke('get_desktop_token');
+                operator = 'desktop';
+            } catch (error) {
+                console.error('Failed to get the desktop token:', error);
+            }
+        }
+        
+        ws.send(JSON.stringify({ type: 'register-gui', operator, token }));
+    }
+    
     handleWebSocketMessage(data) {
+        // Records played back by a replay carry its id so they can be told apart from live data
+        if (data.replayId && data.data && typeof data.data === 'object') {
+            data.data = { ...data.data, replayId: data.replayId };
+        }
+        
         switch (data.type) {
             case 'agent-connected':
                 this.addAgent(data.data);
@@ -110,12 +135,57 @@ class AgentHUDApp {
             case 'agent-disconnected':
                 this.removeAgent(data.data.agentId);
                 break;
+            case 'agent-status':
+                this.updateAgent(data.data);
+                break;
+            case 'usage-update':
+                this.usage[data.data.report.agent_id] = data.data.agentTotals;
+                this.updateAgentList();
+                break;
+            case 'budget-warning':
+                console.warn(`Budget warning for ${data.data.scope.scope} ${data.data.scope.id}: ${data.data.reason}`);
+                break;
+            case 'budget-exceeded':
+                this.showError(`Budget exceeded for ${data.data.scope.scope} ${data.data.scope.id}: ${data.data.reason}`);
+                break;
+            case 'agent-tree':
+                this.agentTree = data.data;
+                this.updateAgentList();
+                break;
+            case 'control-rejected':
+            case 'operator-message-rejected':
+                this.showError(data.reason);
+                break;
             case 'human-input-request':
                 this.addRequest(data.data);
                 break;
             case 'agent-update':
                 console.log('Agent update:', data.data);
                 break;
+            case 'request-claimed':
+            case 'request-released':
+                this.updateRequest(data.data);
+                break;
+            case 'approval-recorded':
+                this.updateRequest(data.data.request);
+                break;
+            case 'response-accepted':
+            case 'response-rejected':
+                if (this.pendingResponses.has(data.requestId)) {
+                    this.pendingResponses.get(data.requestId)(data);
+                } else if (data.type === 'response-rejected') {
+                    this.showError(data.reason);
+                }
+                break;
+            case 'claim-rejected':
+                this.showError(data.reason);
+                break;
+            case 'replay-reset':
+                this.clearReplay(data.data.replayId);
+                break;
+            case 'replay-status':
+                this.updateReplayStatus(data.data);
+                break;
             case 'content-emission':
                 this.addContentItem(data.data);
                 break;
@@ -126,6 +196,7 @@ class AgentHUDApp {
                     title: data.data.title || 'Markdown Content',
                     agent_id: data.data.agent_id,
                     agent_name: data.data.agent_name,
+                    replayId: data.data.replayId,
                     timestamp: data.data.timestamp || new Date().toISOString()
                 });
                 break;
@@ -137,6 +208,7 @@ class AgentHUDApp {
                     title: data.data.title || 'Code Snippet',
                     agent_id: data.data.agent_id,
                     agent_name: data.data.agent_name,
+                    replayId: data.data.replayId,
                     timestamp: data.data.timestamp || new Date().toISOString()
                 });
                 break;
@@ -148,6 +220,7 @@ class AgentHUDApp {
                     title: data.data.title || 'Image',
                     agent_id: data.data.agent_id,
                     agent_name: data.data.agent_name,
+                    replayId: data.data.replayId,
                     timestamp: data.data.timestamp || new Date().toISOString()
                 });
                 break;
@@ -156,6 +229,33 @@ class AgentHUDApp {
         }
     }
     
+    // Drops everything a replay has shown, e.g. before it seeks and plays back up to the new position
+    clearReplay(replayId) {
+        const live = item => item.replayId !== replayId;
+        this.agents = this.agents.filter(live);
+        this.humanRequests = this.humanRequests.filter(live);
+        this.contentItems = this.contentItems.filter(live);
+        this.latestContent = this.contentItems[this.contentItems.length - 1] || null;
+        this.currentContentIndex = this.contentItems.length - 1;
+        this.updateUI();
+    }
+    
+    updateReplayStatus(status) {
+        const indicator = document.getElementById('replayStatus');
+        if (status.state === 'Stopped') {
+            this.replay = null;
+            indicator.style.display = 'none';
+            this.clearReplay(status.id);
+            return;
+        }
+        
+        const seconds = ms => Math.floor(ms / 1000);
+        this.replay = status;
+        indicator.style.display = '';
+        indicator.querySelector('span').textContent =
+            `Replay ${status.state.toLowerCase()} ${seconds(status.position_ms)}s / ${seconds(status.duration_ms)}s (${status.speed}x)`;
+    }
+    
     addAgent(agent) {
         const existing = this.agents.find(a => a.id === agent.id);
         if (!existing) {
@@ -164,6 +264,14 @@ class AgentHUDApp {
         }
     }
     
+    updateAgent(agent) {
+        const index = this.agents.findIndex(a => a.id === agent.id);
+        if (index !== -1) {
+            this.agents[index] = agent;
+            this.updateUI();
+        }
+    }
+    
     removeAgent(agentId) {
         this.agents = this.agents.filter(a => a.id !== agentId);
         this.updateUI();
@@ -177,6 +285,14 @@ class AgentHUDApp {
         }
     }
     
+    updateRequest(request) {
+        const index = this.humanRequests.findIndex(r => r.id === request.id);
+        if (index !== -1) {
+            this.humanRequests[index] = request;
+            this.updateUI();
+        }
+    }
+    
     addContentItem(contentItem) {
         // Add unique ID if not present
         if (!contentItem.id) {
@@ -227,8 +343,21 @@ class AgentHUDApp {
             return;
         }
         
-        agentList.innerHTML = this.agents.map(agent => `
-            <li class="agent-item">
+        // Show sub-agents under their parent when the server has sent the tree
+        const depths = {};
+        const ordered = [];
+        const walk = (nodes, depth) => nodes.forEach(node => {
+            const agent = this.agents.find(a => a.id === node.agent.id) || node.agent;
+            depths[agent.id] = depth;
+            ordered.push(agent);
+            walk(node.children, depth + 1);
+        });
+        walk(this.agentTree, 0);
+        const agents = ordered.length === this.agents.length ? ordered : this.agents;
+        
+        agentList.innerHTML = agents.map(agent => `
+            <li class="agent-item" style="padding-left: ${(depths[agent.id] || 0) * 16}px"
+                title="${agent.run_id ? 'Run ' + agent.run_id : ''}">
                 <div class="agent-avatar">
                     ${agent.name.charAt(0).toUpperCase()}
                 </div>
@@ -239,6 +368,7 @@ class AgentHUDApp {
                             <span class="status-dot"></span>
                             ${agent.status}
                         </span>
+                        ${this.usage[agent.id] ? `<span class="agent-usage">$${this.usage[agent.id].cost.toFixed(4)}</span>` : ''}
                     </div>
                 </div>
             </li>
@@ -295,6 +425,8 @@ class AgentHUDApp {
                     <span class="status-badge ${request.status.toLowerCase()}">
                         ${request.status}
                     </span>
+                    ${request.claimed_by ? `<div style="font-size: 11px; color: #6b7280;">🔒 ${request.claimed_by.operator}</div>` : ''}
+                    ${request.required_approvers > 1 ? `<div style="font-size: 11px; color: #6b7280;">✔ ${request.approvals.filter(a => a.approved).length}/${request.required_approvers} approvals</div>` : ''}
                 </td>
                 <td>
                     <div style="font-size: 12px; color: #6b7280;">
@@ -302,7 +434,7 @@ class AgentHUDApp {
                     </div>
                 </td>
                 <td>
-                    ${request.status === 'Pending' ? `
+                    ${request.status === 'Pending' && !request.replayId ? `
                         <button class="btn primary" onclick="app.openResponseModal('${request.id}')">
                             Respond
                         </button>
@@ -338,6 +470,7 @@ class AgentHUDApp {
         if (!request) return;
         
         this.currentRequestId = requestId;
+        this.selectedOption = null;
         
         // Populate modal
         document.getElementById('modalAgentName').textContent = request.agent_name;
@@ -350,9 +483,9 @@ class AgentHUDApp {
         
         if (request.options && request.options.length > 0) {
             optionsContainer.style.display = 'block';
-            optionsList.innerHTML = request.options.map(option => `
-                <button class="option-button" onclick="app.selectOption('${option}')">
-                    ${option}
+            optionsList.innerHTML = request.options.map((option, index) => `
+                <button class="option-button${option.recommended ? ' recommended' : ''}" title="${option.description || ''}" onclick="app.selectOption(${index})">
+                    ${option.recommended ? '★ ' : ''}${option.label}
                 </button>
             `).join('');
         } else {
@@ -368,43 +501,71 @@ class AgentHUDApp {
         this.currentRequestId = null;
     }
     
-    selectOption(option) {
-        document.getElementById('responseInput').value = option;
+    // Shows the option's label but answers with its id, which is what the server matches on
+    selectOption(index) {
+        const request = this.humanRequests.find(r => r.id === this.currentRequestId);
+        if (!request) return;
+        
+        this.selectedOption = request.options[index];
+        document.getElementById('responseInput').value = this.selectedOption.label;
     }
     
     async submitResponse() {
         if (!this.currentRequestId) return;
         
-        const response = document.getElementById('responseInput').value.trim();
-        if (!response) {
+        const input = document.getElementById('responseInput').value.trim();
+        if (!input) {
             this.showError('Please enter a response');
             return;
         }
+        const response = this.selectedOption && this.selectedOption.label === input
+            ? this.selectedOption.id
+            : input;
+        
+        if (!this.ws || this.ws.readyState !== WebSocket.OPEN) {
+            this.showError('WebSocket connection not available');
+            return;
+        }
+        
+        // Only report success once the server has accepted the answer
+        const requestId = this.currentRequestId;
+        const verdict = new Promise(resolve => {
+            const timer = setTimeout(() => resolve({ type: 'response-timeout' }), 10000);
+            this.pendingResponses.set(requestId, data => {
+                clearTimeout(timer);
+                resolve(data);
+            });
+        });
         
         try {
-            if (this.ws && this.ws.readyState === WebSocket.OPEN) {
-                this.ws.send(JSON.stringify({
-                    type: 'human-input-response',
-                    requestId: this.currentRequestId,
-                    response: response
-                }));
-                
-                // Update local state
-                const request = this.humanRequests.find(r => r.id === this.currentRequestId);
-                if (request) {
-                    request.status = 'Completed';
-                }
-                
-                this.closeResponseModal();
-                this.updateUI();
-                this.showSuccess('Response sent successfully');
-            } else {
-                this.showError('WebSocket connection not available');
-            }
-            
+            this.ws.send(JSON.stringify({
+                type: 'human-input-response',
+                requestId: requestId,
+                response: response
+            }));
         } catch (error) {
+            this.pendingResponses.delete(requestId);
             console.error('Failed to send response:', error);
             this.showError('Failed to send response');
+            return;
+        }
+        
+        const result = await verdict;
+        this.pendingResponses.delete(requestId);
+        switch (result.type) {
+            case 'response-accepted':
+                this.updateRequest(result.request);
+                if (this.currentRequestId === requestId) {
+                    this.closeResponseModal();
+                }
+                // Requests that need several approvers stay pending until enough operators agree
+                this.showSuccess(result.request.status === 'Pending' ? 'Approval recorded' : 'Response sent successfully');
+                break;
+            case 'response-rejected':
+                this.showError(`Response not accepted: ${result.reason}`);
+                break;
+            default:
+                this.showError('No answer from the server; the response may not have been recorded');
         }
     }
     